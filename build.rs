@@ -0,0 +1,13 @@
+use std::env;
+use std::path::PathBuf;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Use the vendored protoc so the build does not depend on one being
+    // installed
+    env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+    tonic_prost_build::configure()
+        .file_descriptor_set_path(out_dir.join("tasks_descriptor.bin"))
+        .compile_protos(&["proto/tasks.proto"], &["proto"])?;
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use hyper::header::HeaderName;
+use hyper::HeaderMap;
+
+/// The entries of an `Accept`-style header, such as `Accept` or
+/// `Accept-Encoding`, lowercased and paired with their quality.
+///
+/// An entry without a `q` parameter, or with one that does not parse, has
+/// quality 1. Returns `None` when the header is missing or not text, which
+/// each kind of negotiation treats differently.
+pub fn parse(headers: &HeaderMap, name: HeaderName) -> Option<Vec<(String, f32)>> {
+    let value = headers.get(name)?.to_str().ok()?;
+    let entries = value
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';').map(str::trim);
+            let value = parts.next()?.to_ascii_lowercase();
+            if value.is_empty() {
+                return None;
+            }
+            let q = parts
+                .find_map(|p| p.strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            Some((value, q))
+        })
+        .collect();
+    Some(entries)
+}
+
+/// The quality `ranges` give `media_type`, where `is_exact` tells whether a
+/// range names it, which lets aliases such as `application/x-msgpack` count.
+///
+/// The most specific matching range decides, so `application/json;q=0, */*`
+/// rules out JSON while still accepting everything else.
+pub fn quality(ranges: &[(String, f32)], media_type: &str, is_exact: impl Fn(&str) -> bool) -> f32 {
+    let (kind, _) = media_type.split_once('/').unwrap_or((media_type, ""));
+
+    ranges
+        .iter()
+        .filter_map(|(range, q)| {
+            let specificity = if is_exact(range) {
+                2
+            } else if range.strip_suffix("/*") == Some(kind) {
+                1
+            } else if range == "*/*" {
+                0
+            } else {
+                return None;
+            };
+            Some((specificity, *q))
+        })
+        .max_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)))
+        .map_or(0.0, |(_, q)| q)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,35 @@
+use super::*;
+use hyper::header::{ACCEPT, ACCEPT_ENCODING};
+
+fn accept(value: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT, value.parse().unwrap());
+    headers
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(parse(&HeaderMap::new(), ACCEPT), None);
+    assert_eq!(parse(&accept("text/html"), ACCEPT_ENCODING), None);
+    assert_eq!(
+        parse(&accept("Text/HTML, application/json;q=0.5 , ,*/*; charset=utf-8 ;q=0.1"), ACCEPT).unwrap(),
+        [
+            ("text/html".to_string(), 1.0),
+            ("application/json".to_string(), 0.5),
+            ("*/*".to_string(), 0.1)
+        ]
+    );
+    assert_eq!(parse(&accept("text/csv;q=high"), ACCEPT).unwrap(), [("text/csv".to_string(), 1.0)]);
+}
+
+#[test]
+fn test_quality_prefers_the_most_specific_range() {
+    let ranges = parse(&accept("text/*;q=0.5, text/html;q=0.8, */*;q=0.1"), ACCEPT).unwrap();
+    assert_eq!(quality(&ranges, "text/html", |range| range == "text/html"), 0.8);
+    assert_eq!(quality(&ranges, "text/csv", |range| range == "text/csv"), 0.5);
+    assert_eq!(quality(&ranges, "application/json", |range| range == "application/json"), 0.1);
+
+    let ranges = parse(&accept("application/json;q=0, */*"), ACCEPT).unwrap();
+    assert_eq!(quality(&ranges, "application/json", |range| range == "application/json"), 0.0);
+    assert_eq!(quality(&[], "application/json", |range| range == "application/json"), 0.0);
+}
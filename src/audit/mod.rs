@@ -0,0 +1,322 @@
+use crate::models::Task;
+use chrono::{DateTime, Utc};
+use hyper::header::{HeaderMap, HeaderName};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::future::Future;
+use uuid::Uuid;
+use utoipa::ToSchema;
+
+/// Request header naming who is making a change.
+///
+/// There is no authentication, so the actor is whatever the client says it
+/// is; the audit trail records it as given.
+pub const ACTOR: HeaderName = HeaderName::from_static("x-actor");
+
+/// Longest actor name accepted, in bytes.
+pub const MAX_ACTOR_LEN: usize = 100;
+
+/// Actor recorded for requests without a usable `X-Actor` header.
+const ANONYMOUS: &str = "anonymous";
+
+/// Actor recorded for changes made by the server itself, such as purging
+/// the trash.
+const SYSTEM: &str = "system";
+
+/// Most events returned by one audit query when it does not set `limit`.
+pub const DEFAULT_LIMIT: usize = 100;
+
+/// Most events one audit query can return.
+pub const MAX_LIMIT: usize = 1000;
+
+/// Most events an `AuditLog` retains by default; older ones are dropped.
+pub const MAX_EVENTS: usize = 100_000;
+
+/// Task fields left out of diffs, since every write changes them.
+const UNAUDITED_FIELDS: &[&str] = &["version", "updated_at"];
+
+/// Who is behind the changes made while handling a request.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AuditContext {
+    pub actor: String,
+    pub request_id: Option<Uuid>,
+}
+
+impl AuditContext {
+    /// The context of a request: its `X-Actor` header and ID.
+    pub fn from_request(headers: &HeaderMap, request_id: Uuid) -> Self {
+        let actor = headers
+            .get(ACTOR)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|actor| !actor.is_empty() && actor.len() <= MAX_ACTOR_LEN)
+            .unwrap_or(ANONYMOUS);
+        AuditContext {
+            actor: actor.to_string(),
+            request_id: Some(request_id),
+        }
+    }
+
+    /// The context of changes made by the server itself.
+    pub fn system() -> Self {
+        AuditContext {
+            actor: SYSTEM.to_string(),
+            request_id: None,
+        }
+    }
+}
+
+tokio::task_local! {
+    static CONTEXT: AuditContext;
+}
+
+/// Run `future` with `context` as the source of every change it makes to
+/// the store.
+pub async fn scope<F: Future>(context: AuditContext, future: F) -> F::Output {
+    CONTEXT.scope(context, future).await
+}
+
+/// The context of the running request, or the system's outside of one.
+pub fn current() -> AuditContext {
+    CONTEXT.try_with(Clone::clone).unwrap_or_else(|_| AuditContext::system())
+}
+
+/// What a change did to a task.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    Created,
+    Updated,
+    Deleted,
+    Restored,
+    Purged,
+}
+
+/// A field's value before and after a change; `null` where it had none.
+#[derive(Clone, Debug, PartialEq, Serialize, ToSchema)]
+pub struct FieldChange {
+    pub before: Value,
+    pub after: Value,
+}
+
+/// One change to one task.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct AuditEvent {
+    /// Position in the audit log, starting at 1.
+    pub id: u64,
+    pub task_id: u64,
+    pub action: AuditAction,
+    pub actor: String,
+    /// The request that made the change; `null` for changes the server made
+    /// on its own.
+    #[schema(value_type = Option<String>, format = "uuid")]
+    pub request_id: Option<Uuid>,
+    pub timestamp: DateTime<Utc>,
+    /// The fields the change touched, by name.
+    pub changes: BTreeMap<String, FieldChange>,
+}
+
+/// The fields that differ between two states of a task.
+///
+/// A missing state (before creation, after a purge) counts as every field
+/// being `null`. `version` and `updated_at` are left out.
+pub fn diff(before: Option<&Task>, after: Option<&Task>) -> BTreeMap<String, FieldChange> {
+    let fields = |task: Option<&Task>| match task.map(serde_json::to_value) {
+        Some(Ok(Value::Object(fields))) => fields,
+        _ => serde_json::Map::new(),
+    };
+    let (mut before, mut after) = (fields(before), fields(after));
+    let names: Vec<String> = before.keys().chain(after.keys()).cloned().collect();
+
+    let mut changes = BTreeMap::new();
+    for name in names {
+        if UNAUDITED_FIELDS.contains(&name.as_str()) || changes.contains_key(&name) {
+            continue;
+        }
+        let before = before.remove(&name).unwrap_or(Value::Null);
+        let after = after.remove(&name).unwrap_or(Value::Null);
+        if before != after {
+            changes.insert(name, FieldChange { before, after });
+        }
+    }
+    changes
+}
+
+/// The changes made through the store, oldest first.
+///
+/// Only the most recent `capacity` events are retained; recording another
+/// drops the oldest. Event IDs keep counting up regardless.
+pub struct AuditLog {
+    events: VecDeque<AuditEvent>,
+    /// IDs of the retained events by task ID, oldest first.
+    by_task: HashMap<u64, VecDeque<u64>>,
+    next_id: u64,
+    capacity: usize,
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        AuditLog::new(MAX_EVENTS)
+    }
+}
+
+impl AuditLog {
+    /// Creates a log retaining up to `capacity` events.
+    pub fn new(capacity: usize) -> Self {
+        AuditLog {
+            events: VecDeque::new(),
+            by_task: HashMap::new(),
+            next_id: 1,
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Record a change to a task, made by `context` at `timestamp`.
+    ///
+    /// `before` and `after` are the task's states around the change. Returns
+    /// the new event, or `None` if there is no task to record.
+    pub fn record(
+        &mut self,
+        context: &AuditContext,
+        action: AuditAction,
+        before: Option<&Task>,
+        after: Option<&Task>,
+        timestamp: DateTime<Utc>,
+    ) -> Option<&AuditEvent> {
+        let task_id = after.or(before)?.id;
+        if self.events.len() >= self.capacity {
+            self.drop_oldest();
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.by_task.entry(task_id).or_default().push_back(id);
+        self.events.push_back(AuditEvent {
+            id,
+            task_id,
+            action,
+            actor: context.actor.clone(),
+            request_id: context.request_id,
+            timestamp,
+            changes: diff(before, after),
+        });
+        self.events.back()
+    }
+
+    fn drop_oldest(&mut self) {
+        let Some(event) = self.events.pop_front() else {
+            return;
+        };
+        if let Some(ids) = self.by_task.get_mut(&event.task_id) {
+            ids.pop_front();
+            if ids.is_empty() {
+                self.by_task.remove(&event.task_id);
+            }
+        }
+    }
+
+    /// The retained event with the given ID.
+    fn get(&self, id: u64) -> Option<&AuditEvent> {
+        let first = self.events.front()?.id;
+        self.events.get(usize::try_from(id.checked_sub(first)?).ok()?)
+    }
+
+    /// Every retained change to one task, oldest first.
+    pub fn history(&self, task_id: u64) -> Vec<&AuditEvent> {
+        let ids = self.by_task.get(&task_id).into_iter().flatten();
+        ids.filter_map(|&id| self.get(id)).collect()
+    }
+
+    /// A page of the changes matching `query`, newest first, and the
+    /// `before` cursor for the next page if there are more.
+    pub fn query(&self, query: &AuditQuery) -> (Vec<&AuditEvent>, Option<u64>) {
+        let mut events: Vec<&AuditEvent> = self
+            .events
+            .iter()
+            .rev()
+            .skip_while(|event| query.before.is_some_and(|before| event.id >= before))
+            .filter(|event| query.matches(event))
+            .take(query.limit + 1)
+            .collect();
+        if events.len() <= query.limit {
+            return (events, None);
+        }
+        events.truncate(query.limit);
+        let next = events.last().map(|event| event.id);
+        (events, next)
+    }
+}
+
+/// Filters for `GET /audit`, read from the query string.
+///
+/// * `actor=name` keeps the changes made by one actor.
+/// * `since` and `until` (RFC 3339) keep the changes made in that time
+///   range; `since` is inclusive and `until` exclusive.
+/// * `limit` caps how many changes are returned, newest first (default 100,
+///   at most 1000).
+/// * `before=id` keeps the changes older than the event with that ID, to
+///   page back through the log with the cursor each page returns.
+///
+/// Unknown parameters are ignored.
+#[derive(Debug, PartialEq, Eq)]
+pub struct AuditQuery {
+    pub actor: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub before: Option<u64>,
+    pub limit: usize,
+}
+
+impl Default for AuditQuery {
+    fn default() -> Self {
+        AuditQuery {
+            actor: None,
+            since: None,
+            until: None,
+            before: None,
+            limit: DEFAULT_LIMIT,
+        }
+    }
+}
+
+impl AuditQuery {
+    /// Parse a request's query string, returning an error message for an
+    /// invalid value.
+    pub fn parse(query: Option<&str>) -> Result<AuditQuery, String> {
+        let mut audit_query = AuditQuery::default();
+        let timestamp = |name: &str, value: &str| {
+            DateTime::parse_from_rfc3339(value)
+                .map(|time| time.with_timezone(&Utc))
+                .map_err(|_| format!("Invalid {} value: {}", name, value))
+        };
+
+        for (name, value) in form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+            match name.as_ref() {
+                "actor" => audit_query.actor = Some(value.into_owned()),
+                "since" => audit_query.since = Some(timestamp(&name, &value)?),
+                "until" => audit_query.until = Some(timestamp(&name, &value)?),
+                "before" => {
+                    let before = value.parse().map_err(|_| format!("Invalid before value: {}", value))?;
+                    audit_query.before = Some(before);
+                }
+                "limit" => {
+                    audit_query.limit = match value.parse::<usize>() {
+                        Ok(limit) if (1..=MAX_LIMIT).contains(&limit) => limit,
+                        _ => return Err(format!("Invalid limit: must be between 1 and {}", MAX_LIMIT)),
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(audit_query)
+    }
+
+    fn matches(&self, event: &AuditEvent) -> bool {
+        self.actor.as_ref().is_none_or(|actor| *actor == event.actor)
+            && self.since.is_none_or(|since| event.timestamp >= since)
+            && self.until.is_none_or(|until| event.timestamp < until)
+    }
+}
+
+#[cfg(test)]
+mod tests;
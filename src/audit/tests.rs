@@ -0,0 +1,125 @@
+use super::*;
+use crate::models::CreateTask;
+use hyper::header::HeaderValue;
+
+fn task(id: u64, title: &str) -> Task {
+    Task::new(
+        id,
+        CreateTask {
+            title: title.to_string(),
+            description: String::new(),
+            due_at: None,
+            priority: None,
+            tags: Default::default(),
+            parent_id: None,
+            blocked_by: Default::default(),
+        },
+        Utc::now(),
+    )
+}
+
+#[test]
+fn test_diff() {
+    let before = task(1, "Old");
+    let mut after = before.clone();
+    after.title = "New".to_string();
+    after.version += 1;
+    after.updated_at = Utc::now() + chrono::Duration::seconds(1);
+
+    let changes = diff(Some(&before), Some(&after));
+    assert_eq!(changes.keys().collect::<Vec<_>>(), vec!["title"]);
+    assert_eq!(changes["title"], FieldChange { before: "Old".into(), after: "New".into() });
+
+    // Creation changes every field that has a value
+    let changes = diff(None, Some(&before));
+    assert_eq!(changes["id"], FieldChange { before: Value::Null, after: 1.into() });
+    assert!(!changes.contains_key("due_at"));
+    assert!(!changes.contains_key("version"));
+}
+
+#[test]
+fn test_context_from_request() {
+    let request_id = Uuid::new_v4();
+    let mut headers = HeaderMap::new();
+    assert_eq!(AuditContext::from_request(&headers, request_id).actor, "anonymous");
+
+    headers.insert(ACTOR, HeaderValue::from_static(" alice "));
+    let context = AuditContext::from_request(&headers, request_id);
+    assert_eq!(context, AuditContext { actor: "alice".to_string(), request_id: Some(request_id) });
+
+    headers.insert(ACTOR, HeaderValue::from_str(&"x".repeat(MAX_ACTOR_LEN + 1)).unwrap());
+    assert_eq!(AuditContext::from_request(&headers, request_id).actor, "anonymous");
+}
+
+#[tokio::test]
+async fn test_scope() {
+    assert_eq!(current(), AuditContext::system());
+    let context = AuditContext { actor: "alice".to_string(), request_id: Some(Uuid::new_v4()) };
+    let seen = scope(context.clone(), async { current() }).await;
+    assert_eq!(seen, context);
+}
+
+#[test]
+fn test_parse_query() {
+    assert_eq!(AuditQuery::parse(None).unwrap(), AuditQuery::default());
+    let query = AuditQuery::parse(Some("actor=alice&since=2024-05-01T12:00:00Z&limit=5")).unwrap();
+    assert_eq!(query.actor.as_deref(), Some("alice"));
+    assert_eq!(query.since, Some("2024-05-01T12:00:00Z".parse().unwrap()));
+    assert_eq!(query.limit, 5);
+
+    assert!(AuditQuery::parse(Some("until=yesterday")).is_err());
+    assert!(AuditQuery::parse(Some("limit=0")).is_err());
+    assert_eq!(AuditQuery::parse(Some("before=42")).unwrap().before, Some(42));
+    assert!(AuditQuery::parse(Some("before=latest")).is_err());
+}
+
+#[test]
+fn test_log_history_and_query() {
+    let alice = AuditContext { actor: "alice".to_string(), request_id: None };
+    let bob = AuditContext { actor: "bob".to_string(), request_id: None };
+    let start = Utc::now();
+    let later = start + chrono::Duration::minutes(1);
+
+    let mut log = AuditLog::default();
+    let first = task(1, "First");
+    log.record(&alice, AuditAction::Created, None, Some(&first), start);
+    log.record(&bob, AuditAction::Created, None, Some(&task(2, "Second")), start);
+    log.record(&bob, AuditAction::Purged, Some(&first), None, later);
+
+    let history = log.history(1);
+    assert_eq!(history.iter().map(|event| event.id).collect::<Vec<_>>(), vec![1, 3]);
+    assert_eq!(history[1].action, AuditAction::Purged);
+    assert!(log.history(9).is_empty());
+
+    let page = |query: &str| -> (Vec<u64>, Option<u64>) {
+        let (events, next_before) = log.query(&AuditQuery::parse(Some(query)).unwrap());
+        (events.iter().map(|event| event.id).collect(), next_before)
+    };
+    let ids = |query: &str| page(query).0;
+    assert_eq!(ids("actor=bob"), vec![3, 2]);
+    assert_eq!(ids(&format!("since={}", later.to_rfc3339()).replace('+', "%2B")), vec![3]);
+    assert_eq!(ids(&format!("until={}", later.to_rfc3339()).replace('+', "%2B")), vec![2, 1]);
+
+    // Paging back from the newest change
+    assert_eq!(page("limit=2"), (vec![3, 2], Some(2)));
+    assert_eq!(page("limit=2&before=2"), (vec![1], None));
+    assert_eq!(page("actor=bob&limit=1&before=3"), (vec![2], None));
+}
+
+#[test]
+fn test_log_retention() {
+    let alice = AuditContext { actor: "alice".to_string(), request_id: None };
+    let now = Utc::now();
+
+    let mut log = AuditLog::new(2);
+    let first = task(1, "First");
+    log.record(&alice, AuditAction::Created, None, Some(&first), now);
+    log.record(&alice, AuditAction::Created, None, Some(&task(2, "Second")), now);
+    log.record(&alice, AuditAction::Purged, Some(&first), None, now);
+
+    // The oldest event was dropped, but IDs keep counting
+    let (events, _) = log.query(&AuditQuery::default());
+    assert_eq!(events.iter().map(|event| event.id).collect::<Vec<_>>(), vec![3, 2]);
+    assert_eq!(log.history(1).iter().map(|event| event.id).collect::<Vec<_>>(), vec![3]);
+    assert_eq!(log.history(2).iter().map(|event| event.id).collect::<Vec<_>>(), vec![2]);
+}
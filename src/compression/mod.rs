@@ -0,0 +1,228 @@
+use crate::accept;
+use bytes::Bytes;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use http_body_util::{BodyExt, Full};
+use hyper::header::{
+    HeaderMap, HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, VARY,
+};
+use hyper::{Response, StatusCode};
+use std::io::{Read, Write};
+use tracing::warn;
+
+/// Responses smaller than this are sent uncompressed; the encoding overhead
+/// outweighs the savings.
+pub const MIN_COMPRESS_SIZE: usize = 1024;
+
+/// Largest request body accepted after decompression, to guard against
+/// compression bombs.
+pub const MAX_DECOMPRESSED_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Content types that are already compressed and gain nothing from another
+/// pass.
+const COMPRESSED_TYPES: &[&str] = &[
+    "application/gzip",
+    "application/x-gzip",
+    "application/zip",
+    "application/zstd",
+    "application/x-bzip2",
+    "application/x-7z-compressed",
+    "font/woff",
+    "font/woff2",
+];
+
+/// A content coding the server can produce.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+/// Codings in order of preference when the client rates them equally.
+const ENCODINGS: &[Encoding] = &[Encoding::Brotli, Encoding::Zstd, Encoding::Gzip];
+
+impl Encoding {
+    /// The token used in `Accept-Encoding` and `Content-Encoding`.
+    pub fn token(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// Pick a coding from the `Accept-Encoding` header.
+    ///
+    /// Returns `None` when the header is missing or accepts none of the
+    /// supported codings, in which case the response is sent as-is.
+    pub fn negotiate(headers: &HeaderMap) -> Option<Encoding> {
+        let codings = accept::parse(headers, ACCEPT_ENCODING)?;
+
+        let wildcard = codings.iter().find(|(c, _)| c == "*").map(|(_, q)| *q);
+        let mut best: Option<(Encoding, f32)> = None;
+        for &encoding in ENCODINGS {
+            let q = codings
+                .iter()
+                .find(|(c, _)| c == encoding.token() || (encoding == Encoding::Gzip && c == "x-gzip"))
+                .map(|(_, q)| *q)
+                .or(wildcard)
+                .unwrap_or(0.0);
+            if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+                best = Some((encoding, q));
+            }
+        }
+        best.map(|(encoding, _)| encoding)
+    }
+
+    /// Compress `data` with this coding.
+    pub fn encode(self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Encoding::Brotli => {
+                let mut out = Vec::new();
+                let params = brotli::enc::BrotliEncoderParams {
+                    quality: 5,
+                    ..Default::default()
+                };
+                brotli::BrotliCompress(&mut &data[..], &mut out, &params)?;
+                Ok(out)
+            }
+            Encoding::Zstd => zstd::encode_all(data, 3),
+        }
+    }
+}
+
+/// Returns true if a response with this content type is worth compressing.
+fn is_compressible(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    if essence == "image/svg+xml" {
+        return true;
+    }
+    !(essence.starts_with("image/")
+        || essence.starts_with("audio/")
+        || essence.starts_with("video/")
+        || COMPRESSED_TYPES.contains(&essence.as_str()))
+}
+
+/// Compress a response body according to the request's `Accept-Encoding`.
+///
+/// Responses are left untouched when they already carry a `Content-Encoding`,
+/// have no body, have an already-compressed content type, or are smaller than
+/// `MIN_COMPRESS_SIZE`. Compressible responses get `Vary: Accept-Encoding` so
+/// caches keep the encodings apart.
+pub async fn compress_response(
+    request_headers: &HeaderMap,
+    response: Response<Full<Bytes>>,
+) -> Response<Full<Bytes>> {
+    let status = response.status();
+    if status == StatusCode::NO_CONTENT
+        || status == StatusCode::NOT_MODIFIED
+        || status.is_informational()
+        || response.headers().contains_key(CONTENT_ENCODING)
+    {
+        return response;
+    }
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if content_type.is_empty() || !is_compressible(content_type) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    parts.headers.append(VARY, HeaderValue::from_static("accept-encoding"));
+
+    // `Full` never fails and is already in memory, so this resolves at once
+    let body = match body.collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(never) => match never {},
+    };
+    let encoding = match Encoding::negotiate(request_headers) {
+        Some(encoding) if body.len() >= MIN_COMPRESS_SIZE => encoding,
+        _ => return Response::from_parts(parts, Full::new(body)),
+    };
+
+    match encoding.encode(&body) {
+        Ok(compressed) => {
+            parts.headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.token()));
+            parts.headers.remove(CONTENT_LENGTH);
+            Response::from_parts(parts, Full::new(Bytes::from(compressed)))
+        }
+        Err(err) => {
+            warn!(encoding = encoding.token(), error = %err, "Failed to compress response");
+            Response::from_parts(parts, Full::new(body))
+        }
+    }
+}
+
+/// Why a request body could not be decompressed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The `Content-Encoding` is not one the server accepts.
+    Unsupported(String),
+    /// The body is not valid for its declared coding.
+    Invalid(String),
+    /// The body expands beyond `MAX_DECOMPRESSED_SIZE`.
+    TooLarge,
+}
+
+impl DecodeError {
+    /// The response status for this error.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            DecodeError::Unsupported(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            DecodeError::Invalid(_) => StatusCode::BAD_REQUEST,
+            DecodeError::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+        }
+    }
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Unsupported(coding) => write!(f, "Unsupported content encoding: {}", coding),
+            DecodeError::Invalid(err) => write!(f, "Invalid compressed body: {}", err),
+            DecodeError::TooLarge => write!(f, "Decompressed body is too large"),
+        }
+    }
+}
+
+/// Undo the request's `Content-Encoding`, if any.
+///
+/// Only gzip request bodies are supported.
+pub fn decode_request_body(headers: &HeaderMap, body: Bytes) -> Result<Bytes, DecodeError> {
+    let coding = match headers.get(CONTENT_ENCODING) {
+        None => return Ok(body),
+        Some(value) => value
+            .to_str()
+            .map_err(|e| DecodeError::Invalid(e.to_string()))?
+            .trim()
+            .to_ascii_lowercase(),
+    };
+
+    match coding.as_str() {
+        "identity" => Ok(body),
+        "gzip" | "x-gzip" => {
+            let mut decoded = Vec::new();
+            GzDecoder::new(&body[..])
+                .take(MAX_DECOMPRESSED_SIZE + 1)
+                .read_to_end(&mut decoded)
+                .map_err(|e| DecodeError::Invalid(e.to_string()))?;
+            if decoded.len() as u64 > MAX_DECOMPRESSED_SIZE {
+                return Err(DecodeError::TooLarge);
+            }
+            Ok(Bytes::from(decoded))
+        }
+        _ => Err(DecodeError::Unsupported(coding)),
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,107 @@
+use super::*;
+
+fn accept_encoding(value: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT_ENCODING, HeaderValue::from_str(value).unwrap());
+    headers
+}
+
+fn response(content_type: &str, body: Vec<u8>) -> Response<Full<Bytes>> {
+    Response::builder()
+        .header(CONTENT_TYPE, content_type)
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
+async fn body_bytes(response: Response<Full<Bytes>>) -> Bytes {
+    response.into_body().collect().await.unwrap().to_bytes()
+}
+
+fn large_json() -> Vec<u8> {
+    format!("[{}]", vec!["{\"title\":\"Test Task\"}"; 200].join(",")).into_bytes()
+}
+
+#[test]
+fn test_negotiate() {
+    assert_eq!(Encoding::negotiate(&HeaderMap::new()), None);
+    assert_eq!(Encoding::negotiate(&accept_encoding("gzip, deflate, br, zstd")), Some(Encoding::Brotli));
+    assert_eq!(Encoding::negotiate(&accept_encoding("gzip;q=1, br;q=0.5")), Some(Encoding::Gzip));
+    assert_eq!(Encoding::negotiate(&accept_encoding("*;q=0.1, br;q=0")), Some(Encoding::Zstd));
+    assert_eq!(Encoding::negotiate(&accept_encoding("identity")), None);
+}
+
+#[tokio::test]
+async fn test_compress_round_trips() {
+    let original = large_json();
+
+    for (encoding, token) in [(Encoding::Gzip, "gzip"), (Encoding::Brotli, "br"), (Encoding::Zstd, "zstd")] {
+        let compressed = compress_response(&accept_encoding(token), response("application/json", original.clone())).await;
+        assert_eq!(compressed.headers()[CONTENT_ENCODING], token);
+        assert_eq!(compressed.headers()[VARY], "accept-encoding");
+
+        let body = body_bytes(compressed).await;
+        assert!(body.len() < original.len());
+
+        let decoded = match encoding {
+            Encoding::Gzip => {
+                let mut out = Vec::new();
+                GzDecoder::new(&body[..]).read_to_end(&mut out).unwrap();
+                out
+            }
+            Encoding::Brotli => {
+                let mut out = Vec::new();
+                brotli::BrotliDecompress(&mut &body[..], &mut out).unwrap();
+                out
+            }
+            Encoding::Zstd => zstd::decode_all(&body[..]).unwrap(),
+        };
+        assert_eq!(decoded, original);
+    }
+}
+
+#[tokio::test]
+async fn test_small_responses_are_not_compressed() {
+    let compressed = compress_response(&accept_encoding("gzip"), response("application/json", b"{}".to_vec())).await;
+
+    assert!(compressed.headers().get(CONTENT_ENCODING).is_none());
+    assert_eq!(compressed.headers()[VARY], "accept-encoding");
+    assert_eq!(body_bytes(compressed).await, Bytes::from_static(b"{}"));
+}
+
+#[tokio::test]
+async fn test_compressed_content_types_are_skipped() {
+    let compressed = compress_response(&accept_encoding("gzip"), response("image/png", large_json())).await;
+
+    assert!(compressed.headers().get(CONTENT_ENCODING).is_none());
+    assert!(compressed.headers().get(VARY).is_none());
+    assert!(is_compressible("image/svg+xml"));
+    assert!(!is_compressible("application/zip"));
+}
+
+#[test]
+fn test_decode_gzip_request_body() {
+    let original = large_json();
+    let compressed = Encoding::Gzip.encode(&original).unwrap();
+
+    let mut headers = HeaderMap::new();
+    assert_eq!(decode_request_body(&headers, Bytes::from_static(b"{}")).unwrap(), Bytes::from_static(b"{}"));
+
+    headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+    assert_eq!(decode_request_body(&headers, Bytes::from(compressed)).unwrap(), Bytes::from(original));
+
+    let err = decode_request_body(&headers, Bytes::from_static(b"not gzip")).unwrap_err();
+    assert_eq!(err.status(), StatusCode::BAD_REQUEST);
+
+    headers.insert(CONTENT_ENCODING, HeaderValue::from_static("br"));
+    let err = decode_request_body(&headers, Bytes::from_static(b"{}")).unwrap_err();
+    assert_eq!(err.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[test]
+fn test_decode_rejects_compression_bombs() {
+    let bomb = Encoding::Gzip.encode(&vec![0u8; MAX_DECOMPRESSED_SIZE as usize + 1]).unwrap();
+
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+    assert_eq!(decode_request_body(&headers, Bytes::from(bomb)), Err(DecodeError::TooLarge));
+}
@@ -0,0 +1,107 @@
+use crate::formats::Format;
+use crate::models::Task;
+use crate::versioning;
+use hyper::header::{HeaderMap, HeaderName, IF_MATCH, IF_NONE_MATCH};
+
+/// The strong `ETag` for a task's current version, sent as `format` in the
+/// running request's API version.
+///
+/// The tag names the task and when it was created, so that a task created
+/// again under a reused ID does not match its predecessor's, and each
+/// representation has its own tag: a JSON and a CBOR body, or a v1 and a v2
+/// one, are never the same bytes.  `If-Match` only compares the part naming
+/// the task's version, see `EntityTags::matches_version`.
+pub fn etag(task: &Task, format: Format) -> String {
+    let format = match format {
+        Format::Json => "json",
+        Format::Cbor => "cbor",
+        Format::MessagePack => "msgpack",
+        Format::Csv => "csv",
+    };
+    format!("\"{}-{}-{}\"", version_tag(task), versioning::current().name(), format)
+}
+
+/// The part of a task's `ETag` that names its current version, shared by
+/// every representation of it.
+fn version_tag(task: &Task) -> String {
+    format!("{}-{}-{}", task.id, task.created_at.timestamp_micros(), task.version)
+}
+
+/// A parsed `If-Match` or `If-None-Match` header.
+#[derive(Debug, PartialEq, Eq)]
+pub enum EntityTags {
+    /// `*`, which matches any current representation.
+    Any,
+    /// A list of entity tags, kept with their quotes and any `W/` prefix.
+    List(Vec<String>),
+}
+
+impl EntityTags {
+    /// Read `If-Match` from the request, if present.
+    pub fn if_match(headers: &HeaderMap) -> Option<EntityTags> {
+        parse(headers, IF_MATCH)
+    }
+
+    /// Read `If-None-Match` from the request, if present.
+    pub fn if_none_match(headers: &HeaderMap) -> Option<EntityTags> {
+        parse(headers, IF_NONE_MATCH)
+    }
+
+    /// Whether a tag is for the task's current version, as `If-Match`
+    /// checks before a change.
+    ///
+    /// The comparison is strong, so weak tags never match, but ignores which
+    /// representation the tag was sent with: a client that read the task as
+    /// CBOR or from `/v1` may change it as JSON or through `/v2`.
+    pub fn matches_version(&self, task: &Task) -> bool {
+        let version = version_tag(task);
+        let matches = |tag: &str| {
+            tag.strip_prefix('"')
+                .and_then(|tag| tag.strip_prefix(version.as_str()))
+                .is_some_and(|rest| rest.starts_with('-') && rest.ends_with('"'))
+        };
+        match self {
+            EntityTags::Any => true,
+            EntityTags::List(tags) => tags.iter().any(|tag| matches(tag)),
+        }
+    }
+
+    /// Weak comparison, as `If-None-Match` requires: `W/` prefixes are ignored.
+    pub fn matches_weak(&self, etag: &str) -> bool {
+        let opaque = |tag: &str| tag.strip_prefix("W/").unwrap_or(tag).to_string();
+        match self {
+            EntityTags::Any => true,
+            EntityTags::List(tags) => tags.iter().any(|tag| opaque(tag) == opaque(etag)),
+        }
+    }
+}
+
+/// Parse every instance of a conditional header into one tag list.
+///
+/// Unparseable values are kept as-is; they simply never match.
+fn parse(headers: &HeaderMap, name: HeaderName) -> Option<EntityTags> {
+    let values: Vec<&str> = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .collect();
+    if values.is_empty() {
+        return None;
+    }
+
+    let tags: Vec<String> = values
+        .iter()
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+        .collect();
+    if tags.iter().any(|tag| tag == "*") {
+        Some(EntityTags::Any)
+    } else {
+        Some(EntityTags::List(tags))
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,74 @@
+use super::*;
+use crate::versioning::{self, ApiVersion};
+use hyper::header::HeaderValue;
+
+fn headers(name: HeaderName, value: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(name, HeaderValue::from_str(value).unwrap());
+    headers
+}
+
+fn task(version: u64) -> Task {
+    serde_json::from_value(serde_json::json!({
+        "id": 1,
+        "title": "Write docs",
+        "description": "",
+        "completed": false,
+        "version": version,
+        "created_at": "2026-10-18T09:00:00Z",
+        "updated_at": "2026-10-18T09:00:00Z",
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(EntityTags::if_match(&HeaderMap::new()), None);
+    assert_eq!(EntityTags::if_match(&headers(IF_MATCH, "*")), Some(EntityTags::Any));
+    assert_eq!(
+        EntityTags::if_none_match(&headers(IF_NONE_MATCH, "\"1\", W/\"2\"")),
+        Some(EntityTags::List(vec!["\"1\"".to_string(), "W/\"2\"".to_string()]))
+    );
+}
+
+#[tokio::test]
+async fn test_version_comparison() {
+    let stale = task(2);
+    let task = task(3);
+    let json = etag(&task, Format::Json);
+    let cbor_v2 = versioning::scope(ApiVersion::V2, async { etag(&task, Format::Cbor) }).await;
+
+    // Any representation of the current version matches
+    assert!(EntityTags::List(vec![json.clone()]).matches_version(&task));
+    assert!(EntityTags::List(vec!["\"1\"".to_string(), cbor_v2]).matches_version(&task));
+    assert!(EntityTags::Any.matches_version(&task));
+
+    assert!(!EntityTags::List(vec![format!("W/{}", json)]).matches_version(&task));
+    assert!(!EntityTags::List(vec![etag(&stale, Format::Json)]).matches_version(&task));
+    assert!(!EntityTags::List(vec!["\"1-1792314000000000-3\"".to_string()]).matches_version(&task));
+    assert!(!EntityTags::List(vec!["\"1-1792314000000000-30-v1-json\"".to_string()]).matches_version(&task));
+}
+
+#[test]
+fn test_weak_comparison() {
+    let tags = EntityTags::List(vec!["W/\"2\"".to_string()]);
+
+    assert!(tags.matches_weak("\"2\""));
+    assert!(!tags.matches_weak("\"1\""));
+}
+
+#[tokio::test]
+async fn test_etag_varies_by_task_and_representation() {
+    let task = task(3);
+    assert_eq!(etag(&task, Format::Json), "\"1-1792314000000000-3-v1-json\"");
+    assert_eq!(etag(&task, Format::MessagePack), "\"1-1792314000000000-3-v1-msgpack\"");
+    let v2 = versioning::scope(ApiVersion::V2, async { etag(&task, Format::Json) }).await;
+    assert_eq!(v2, "\"1-1792314000000000-3-v2-json\"");
+
+    // A task created again under the same ID does not match the old one
+    let recreated = Task {
+        created_at: task.created_at + chrono::Duration::seconds(1),
+        ..task.clone()
+    };
+    assert_ne!(etag(&recreated, Format::Json), etag(&task, Format::Json));
+}
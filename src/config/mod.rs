@@ -1,7 +1,96 @@
 use std::net::SocketAddr;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::versioning::Deprecation;
 
 pub struct Config {
     pub addr: SocketAddr,
+    /// Where the gRPC service listens.
+    pub grpc_addr: SocketAddr,
+    /// How long a `POST /tasks` response is kept for replay under its
+    /// `Idempotency-Key`.
+    pub idempotency_ttl: Duration,
+    /// How long a deleted task stays in the trash before it is purged.
+    pub trash_retention: Duration,
+    /// How often the trash is checked for tasks to purge.
+    pub trash_purge_interval: Duration,
+    /// How many times a webhook delivery is attempted before it is
+    /// dead-lettered.
+    pub webhook_max_attempts: u32,
+    /// How long to wait before retrying a failed webhook delivery; doubles
+    /// with each retry.
+    pub webhook_backoff: Duration,
+    /// Hosts webhooks may be delivered to even though they resolve to a
+    /// loopback, private or link-local address, which are refused otherwise.
+    pub webhook_allowed_hosts: Vec<String>,
+    /// Origins, as in `https://app.example.com`, whose pages may open a
+    /// WebSocket to `/ws` or `/graphql` besides the server's own.
+    pub ws_allowed_origins: Vec<String>,
+    /// Whether to enable conveniences meant for development only, such as
+    /// the GraphiQL IDE at `GET /graphql`.
+    pub dev_mode: bool,
+    /// When API v1 is deprecated and sunset, if it is to be retired. It is
+    /// served without deprecation headers otherwise.
+    pub v1_deprecation: Option<Deprecation>,
+}
+
+impl Config {
+    /// Creates a `Config` from the defaults, overridden by the environment.
+    ///
+    /// * `GRPC_ADDR` sets `grpc_addr`.
+    /// * `IDEMPOTENCY_TTL_SECS` sets `idempotency_ttl`.
+    /// * `TRASH_RETENTION_SECS` sets `trash_retention`.
+    /// * `TRASH_PURGE_INTERVAL_SECS` sets `trash_purge_interval`.
+    /// * `WEBHOOK_MAX_ATTEMPTS` sets `webhook_max_attempts`.
+    /// * `WEBHOOK_BACKOFF_SECS` sets `webhook_backoff`.
+    /// * `WEBHOOK_ALLOWED_HOSTS` sets `webhook_allowed_hosts`, separated by
+    ///   commas.
+    /// * `WS_ALLOWED_ORIGINS` sets `ws_allowed_origins`, separated by commas.
+    /// * `DEV_MODE` set to `true` or `1` turns on `dev_mode`.
+    /// * `API_V1_DEPRECATED_AT` and `API_V1_SUNSET_AT`, both RFC 3339 times,
+    ///   set `v1_deprecation`.
+    ///
+    /// Values that are not a whole number of seconds, a socket address or a
+    /// time are ignored, as are a purge interval and a number of attempts of
+    /// zero and a sunset that does not follow the deprecation.
+    pub fn from_env() -> Self {
+        let mut config = Config::default();
+        if let Some(addr) = std::env::var("GRPC_ADDR").ok().and_then(|addr| addr.parse().ok()) {
+            config.grpc_addr = addr;
+        }
+        if let Some(ttl) = secs_from_env("IDEMPOTENCY_TTL_SECS") {
+            config.idempotency_ttl = ttl;
+        }
+        if let Some(retention) = secs_from_env("TRASH_RETENTION_SECS") {
+            config.trash_retention = retention;
+        }
+        if let Some(interval) = secs_from_env("TRASH_PURGE_INTERVAL_SECS").filter(|d| !d.is_zero()) {
+            config.trash_purge_interval = interval;
+        }
+        let attempts = std::env::var("WEBHOOK_MAX_ATTEMPTS").ok().and_then(|value| value.parse::<u32>().ok());
+        if let Some(attempts) = attempts.filter(|&attempts| attempts > 0) {
+            config.webhook_max_attempts = attempts;
+        }
+        if let Some(backoff) = secs_from_env("WEBHOOK_BACKOFF_SECS") {
+            config.webhook_backoff = backoff;
+        }
+        if let Ok(hosts) = std::env::var("WEBHOOK_ALLOWED_HOSTS") {
+            config.webhook_allowed_hosts =
+                hosts.split(',').map(str::trim).filter(|host| !host.is_empty()).map(String::from).collect();
+        }
+        if let Ok(origins) = std::env::var("WS_ALLOWED_ORIGINS") {
+            config.ws_allowed_origins =
+                origins.split(',').map(str::trim).filter(|origin| !origin.is_empty()).map(String::from).collect();
+        }
+        config.dev_mode = std::env::var("DEV_MODE").is_ok_and(|value| value == "true" || value == "1");
+        let since = time_from_env("API_V1_DEPRECATED_AT");
+        if let (Some(since), Some(sunset)) = (since, time_from_env("API_V1_SUNSET_AT")) {
+            config.v1_deprecation = Some(Deprecation { since, sunset }).filter(|_| sunset > since);
+        }
+        config
+    }
 }
 
 impl Default for Config {
@@ -9,10 +98,41 @@ impl Default for Config {
     ///
     /// The default values are:
     ///
-    /// * `addr`: `127.0.0.1:3000`
+    /// * `addr`: `127.0.0.1:3001`
+    /// * `grpc_addr`: `127.0.0.1:50051`
+    /// * `idempotency_ttl`: 24 hours
+    /// * `trash_retention`: 30 days
+    /// * `trash_purge_interval`: 1 hour
+    /// * `webhook_max_attempts`: 5
+    /// * `webhook_backoff`: 1 second
+    /// * `webhook_allowed_hosts`: none
+    /// * `ws_allowed_origins`: none
+    /// * `dev_mode`: off
+    /// * `v1_deprecation`: none
     fn default() -> Self {
         Config {
             addr: SocketAddr::from(([127, 0, 0, 1], 3001)),
+            grpc_addr: SocketAddr::from(([127, 0, 0, 1], 50051)),
+            idempotency_ttl: Duration::from_secs(24 * 60 * 60),
+            trash_retention: Duration::from_secs(30 * 24 * 60 * 60),
+            trash_purge_interval: Duration::from_secs(60 * 60),
+            webhook_max_attempts: 5,
+            webhook_backoff: Duration::from_secs(1),
+            webhook_allowed_hosts: Vec::new(),
+            ws_allowed_origins: Vec::new(),
+            dev_mode: false,
+            v1_deprecation: None,
         }
     }
 }
+
+/// Read an RFC 3339 time from an environment variable.
+fn time_from_env(name: &str) -> Option<DateTime<Utc>> {
+    let value = std::env::var(name).ok()?;
+    DateTime::parse_from_rfc3339(&value).ok().map(|time| time.with_timezone(&Utc))
+}
+
+/// Read a duration in whole seconds from an environment variable.
+fn secs_from_env(name: &str) -> Option<Duration> {
+    std::env::var(name).ok()?.parse::<u64>().ok().map(Duration::from_secs)
+}
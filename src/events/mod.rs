@@ -0,0 +1,92 @@
+use crate::audit::AuditAction;
+use crate::models::Task;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+
+/// How many recent events are kept for clients resuming with
+/// `Last-Event-ID`.
+pub const REPLAY_CAPACITY: usize = 1024;
+
+/// A change to a task, as pushed to `GET /tasks/events` subscribers.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct TaskEvent {
+    /// The change's position in the audit log; doubles as the SSE event ID.
+    pub id: u64,
+    #[serde(rename = "type")]
+    pub action: AuditAction,
+    pub task_id: u64,
+    /// The task after the change; `None` once it has been purged.
+    pub task: Option<Task>,
+}
+
+impl TaskEvent {
+    /// Encode the event as a Server-Sent Events message.
+    pub fn to_sse(&self) -> String {
+        let data = serde_json::to_string(self).unwrap_or_default();
+        let action = serde_json::to_value(self.action).unwrap_or_default();
+        format!("id: {}\nevent: {}\ndata: {}\n\n", self.id, action.as_str().unwrap_or_default(), data)
+    }
+}
+
+/// Fans task changes out to every subscriber, keeping the most recent ones
+/// for replay.
+pub struct EventBus {
+    sender: broadcast::Sender<TaskEvent>,
+    replay: Mutex<VecDeque<TaskEvent>>,
+    capacity: usize,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        EventBus::new(REPLAY_CAPACITY)
+    }
+}
+
+impl EventBus {
+    /// Creates a bus replaying up to `capacity` events.
+    ///
+    /// A subscriber that falls more than `capacity` events behind is
+    /// dropped, and can reconnect to catch up from the replay buffer.
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        EventBus {
+            sender,
+            replay: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }
+    }
+
+    /// Send an event to every subscriber.
+    pub fn publish(&self, event: TaskEvent) {
+        let mut replay = self.replay.lock().unwrap();
+        if replay.len() == self.capacity {
+            replay.pop_front();
+        }
+        replay.push_back(event.clone());
+        // No subscribers is not an error
+        let _ = self.sender.send(event);
+    }
+
+    /// Subscribe to events, returning the buffered events after
+    /// `last_event_id` along with a receiver for the ones after those.
+    ///
+    /// Events older than the buffer are lost. Without `last_event_id` only
+    /// new events are received.
+    pub fn subscribe(&self, last_event_id: Option<u64>) -> (Vec<TaskEvent>, broadcast::Receiver<TaskEvent>) {
+        // Holding the buffer lock keeps `publish` from slipping an event in
+        // between the replay and the subscription
+        let replay = self.replay.lock().unwrap();
+        let receiver = self.sender.subscribe();
+        let missed = match last_event_id {
+            Some(last) => replay.iter().filter(|event| event.id > last).cloned().collect(),
+            None => Vec::new(),
+        };
+        (missed, receiver)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,43 @@
+use super::*;
+
+fn event(id: u64) -> TaskEvent {
+    TaskEvent {
+        id,
+        action: AuditAction::Deleted,
+        task_id: 7,
+        task: None,
+    }
+}
+
+#[test]
+fn test_to_sse() {
+    assert_eq!(
+        event(3).to_sse(),
+        "id: 3\nevent: deleted\ndata: {\"id\":3,\"type\":\"deleted\",\"task_id\":7,\"task\":null}\n\n"
+    );
+}
+
+#[tokio::test]
+async fn test_subscribe_receives_new_events() {
+    let bus = EventBus::new(4);
+    bus.publish(event(1));
+    let (missed, mut receiver) = bus.subscribe(None);
+    assert!(missed.is_empty());
+
+    bus.publish(event(2));
+    assert_eq!(receiver.recv().await.unwrap().id, 2);
+}
+
+#[test]
+fn test_resume_from_replay_buffer() {
+    let bus = EventBus::new(3);
+    for id in 1..=5 {
+        bus.publish(event(id));
+    }
+
+    let ids = |last: u64| bus.subscribe(Some(last)).0.iter().map(|event| event.id).collect::<Vec<_>>();
+    assert_eq!(ids(3), vec![4, 5]);
+    assert!(ids(5).is_empty());
+    // Only the last three are kept
+    assert_eq!(ids(0), vec![3, 4, 5]);
+}
@@ -0,0 +1,185 @@
+use crate::accept;
+use crate::models::Task;
+use crate::versioning::ApiVersion;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use hyper::header::{ACCEPT, CONTENT_TYPE};
+use hyper::HeaderMap;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+/// A wire format the task API can read and write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Cbor,
+    MessagePack,
+    Csv,
+}
+
+/// Formats every task endpoint can respond with, in order of preference.
+pub const TASK_FORMATS: &[Format] = &[Format::Json, Format::Cbor, Format::MessagePack];
+
+/// Formats `GET /tasks` can respond with. CSV only makes sense for a list.
+pub const TASK_LIST_FORMATS: &[Format] = &[Format::Json, Format::Cbor, Format::MessagePack, Format::Csv];
+
+impl Format {
+    /// The media type sent in the `Content-Type` header.
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::Cbor => "application/cbor",
+            Format::MessagePack => "application/msgpack",
+            Format::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    /// Look up a format by media type, ignoring parameters and case.
+    ///
+    /// The vendor types naming an API version, such as
+    /// `application/vnd.rws.v2+json`, are the format of their suffix.
+    pub fn from_media_type(media_type: &str) -> Option<Format> {
+        let essence = media_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        if let Some((_, suffix)) = ApiVersion::from_media_type(&essence) {
+            return match suffix {
+                "json" => Some(Format::Json),
+                "cbor" => Some(Format::Cbor),
+                "msgpack" => Some(Format::MessagePack),
+                _ => None,
+            };
+        }
+        match essence.as_str() {
+            "application/json" => Some(Format::Json),
+            "application/cbor" => Some(Format::Cbor),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(Format::MessagePack)
+            }
+            "text/csv" => Some(Format::Csv),
+            _ => None,
+        }
+    }
+
+    /// Pick the response format from the `Accept` header.
+    ///
+    /// The supported format with the highest quality wins; ties go to the one
+    /// listed first in `supported`. A missing `Accept` header selects the first
+    /// supported format. Returns `None` when nothing acceptable is supported,
+    /// which callers turn into 406 Not Acceptable.
+    pub fn negotiate(headers: &HeaderMap, supported: &[Format]) -> Option<Format> {
+        let Some(ranges) = accept::parse(headers, ACCEPT) else {
+            return supported.first().copied();
+        };
+
+        let mut best: Option<(Format, f32)> = None;
+        for &format in supported {
+            let media_type = format.content_type().split(';').next().unwrap_or_default();
+            let q = accept::quality(&ranges, media_type, |range| Format::from_media_type(range) == Some(format));
+            if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) {
+                best = Some((format, q));
+            }
+        }
+        best.map(|(format, _)| format)
+    }
+
+    /// Determine the format of a request body from its `Content-Type` header.
+    ///
+    /// A missing header is treated as JSON, which is what clients sent before
+    /// other formats were supported. Returns `None` for unsupported types,
+    /// which callers turn into 415 Unsupported Media Type.
+    pub fn from_request(headers: &HeaderMap) -> Option<Format> {
+        match headers.get(CONTENT_TYPE) {
+            None => Some(Format::Json),
+            Some(value) => value
+                .to_str()
+                .ok()
+                .and_then(Format::from_media_type)
+                .filter(|format| TASK_FORMATS.contains(format)),
+        }
+    }
+
+    /// Serialize a value in this format.
+    ///
+    /// CSV has no generic encoding; use `encode_tasks_csv` for task lists.
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<Bytes, String> {
+        match self {
+            Format::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            Format::Cbor => {
+                let mut buf = Vec::new();
+                ciborium::into_writer(value, &mut buf).map_err(|e| e.to_string())?;
+                Ok(buf)
+            }
+            Format::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+            Format::Csv => Err("CSV is only supported for task lists".to_string()),
+        }
+        .map(Bytes::from)
+    }
+
+    /// Deserialize a request body in this format.
+    pub fn decode<T: DeserializeOwned>(self, body: &[u8]) -> Result<T, String> {
+        match self {
+            Format::Json => serde_json::from_slice(body).map_err(|e| e.to_string()),
+            Format::Cbor => ciborium::from_reader(body).map_err(|e| e.to_string()),
+            Format::MessagePack => rmp_serde::from_slice(body).map_err(|e| e.to_string()),
+            Format::Csv => Err("CSV request bodies are not supported".to_string()),
+        }
+    }
+}
+
+/// Export tasks as CSV with a header row.
+///
+/// Tags and blocking task IDs are joined with `;` into one cell each; empty
+/// cells stand for missing values.
+pub fn encode_tasks_csv(tasks: &[Task]) -> Result<Bytes, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer
+        .write_record([
+            "id",
+            "title",
+            "description",
+            "completed",
+            "priority",
+            "due_at",
+            "created_at",
+            "updated_at",
+            "completed_at",
+            "tags",
+            "parent_id",
+            "blocked_by",
+            "deleted_at",
+        ])
+        .map_err(|e| e.to_string())?;
+    for task in tasks {
+        writer
+            .write_record([
+                task.id.to_string(),
+                task.title.clone(),
+                task.description.clone(),
+                task.completed.to_string(),
+                task.priority.map(|p| p.to_string()).unwrap_or_default(),
+                rfc3339(task.due_at),
+                rfc3339(Some(task.created_at)),
+                rfc3339(Some(task.updated_at)),
+                rfc3339(task.completed_at),
+                join(&task.tags),
+                task.parent_id.map(|id| id.to_string()).unwrap_or_default(),
+                join(&task.blocked_by),
+                rfc3339(task.deleted_at),
+            ])
+            .map_err(|e| e.to_string())?;
+    }
+    writer.into_inner().map(Bytes::from).map_err(|e| e.to_string())
+}
+
+/// Format a set for a CSV cell, its items separated by `;`.
+fn join<T: ToString>(items: &BTreeSet<T>) -> String {
+    items.iter().map(T::to_string).collect::<Vec<_>>().join(";")
+}
+
+/// Format an optional timestamp for a CSV cell, empty if missing.
+fn rfc3339(time: Option<DateTime<Utc>>) -> String {
+    time.map(|time| time.to_rfc3339()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests;
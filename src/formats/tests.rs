@@ -0,0 +1,109 @@
+use super::*;
+use crate::models::{CreateTask, Priority};
+use hyper::header::HeaderValue;
+
+fn accept(value: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT, HeaderValue::from_str(value).unwrap());
+    headers
+}
+
+#[test]
+fn test_negotiate_defaults_to_json() {
+    assert_eq!(Format::negotiate(&HeaderMap::new(), TASK_FORMATS), Some(Format::Json));
+    assert_eq!(Format::negotiate(&accept("*/*"), TASK_LIST_FORMATS), Some(Format::Json));
+}
+
+#[test]
+fn test_negotiate_picks_highest_quality() {
+    assert_eq!(Format::negotiate(&accept("application/cbor"), TASK_FORMATS), Some(Format::Cbor));
+    assert_eq!(
+        Format::negotiate(&accept("application/json;q=0.5, application/x-msgpack"), TASK_FORMATS),
+        Some(Format::MessagePack)
+    );
+    assert_eq!(Format::negotiate(&accept("text/*"), TASK_LIST_FORMATS), Some(Format::Csv));
+    assert_eq!(
+        Format::negotiate(&accept("application/json;q=0, */*"), TASK_FORMATS),
+        Some(Format::Cbor)
+    );
+}
+
+#[test]
+fn test_negotiate_not_acceptable() {
+    assert_eq!(Format::negotiate(&accept("text/csv"), TASK_FORMATS), None);
+    assert_eq!(Format::negotiate(&accept("application/xml"), TASK_LIST_FORMATS), None);
+    assert_eq!(Format::negotiate(&accept("application/json;q=0"), TASK_FORMATS), None);
+}
+
+#[test]
+fn test_negotiate_vendor_types() {
+    let vendor = accept("application/vnd.rws.v2+json");
+    assert_eq!(Format::negotiate(&vendor, TASK_FORMATS), Some(Format::Json));
+    let vendor = accept("application/vnd.rws.v1+msgpack, application/json;q=0.5");
+    assert_eq!(Format::negotiate(&vendor, TASK_FORMATS), Some(Format::MessagePack));
+    assert_eq!(Format::negotiate(&accept("application/vnd.rws.v9+json"), TASK_FORMATS), None);
+    assert_eq!(Format::negotiate(&accept("application/vnd.rws.v2+xml"), TASK_FORMATS), None);
+}
+
+#[test]
+fn test_from_request() {
+    let mut headers = HeaderMap::new();
+    assert_eq!(Format::from_request(&headers), Some(Format::Json));
+
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/cbor"));
+    assert_eq!(Format::from_request(&headers), Some(Format::Cbor));
+
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/csv"));
+    assert_eq!(Format::from_request(&headers), None);
+}
+
+#[test]
+fn test_binary_formats_round_trip() {
+    let create_task = CreateTask {
+        title: "Test Task".to_string(),
+        description: "Test Description".to_string(),
+        due_at: None,
+        priority: None,
+        tags: Default::default(),
+        parent_id: None,
+        blocked_by: Default::default(),
+    };
+
+    for format in [Format::Json, Format::Cbor, Format::MessagePack] {
+        let bytes = format.encode(&create_task).unwrap();
+        let decoded: CreateTask = format.decode(&bytes).unwrap();
+        assert_eq!(decoded.title, "Test Task");
+        assert_eq!(decoded.description, "Test Description");
+    }
+}
+
+#[test]
+fn test_encode_tasks_csv() {
+    let created_at = "2024-05-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+    let tasks = vec![Task {
+        id: 7,
+        title: "Quote \"this\"".to_string(),
+        description: "a, b".to_string(),
+        completed: true,
+        version: 1,
+        due_at: None,
+        priority: Some(Priority::High),
+        created_at,
+        updated_at: created_at,
+        completed_at: Some(created_at),
+        deleted_at: Some(created_at),
+        tags: ["home".to_string(), "ops:deploy".to_string()].into(),
+        parent_id: Some(3),
+        blocked_by: [4, 5].into(),
+    }];
+
+    let csv = encode_tasks_csv(&tasks).unwrap();
+    assert_eq!(
+        std::str::from_utf8(&csv).unwrap(),
+        "id,title,description,completed,priority,due_at,created_at,updated_at,completed_at,\
+         tags,parent_id,blocked_by,deleted_at\n\
+         7,\"Quote \"\"this\"\"\",\"a, b\",true,high,,\
+         2024-05-01T12:00:00+00:00,2024-05-01T12:00:00+00:00,2024-05-01T12:00:00+00:00,\
+         home;ops:deploy,3,4;5,2024-05-01T12:00:00+00:00\n"
+    );
+}
@@ -0,0 +1,169 @@
+use crate::models::{self, CreateTask, UpdateTask};
+use bytes::Bytes;
+use hyper::header::{CONTENT_TYPE, COOKIE};
+use hyper::HeaderMap;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use uuid::Uuid;
+
+/// Name of the cookie and hidden form field carrying the CSRF token.
+pub const CSRF_TOKEN: &str = "csrf_token";
+
+/// Submitted form fields, keyed by field name.
+pub type FormFields = HashMap<String, String>;
+
+/// Returns true if the request body is an HTML form submission.
+pub fn is_form(headers: &HeaderMap) -> bool {
+    match content_type(headers) {
+        Some(ct) => {
+            ct.starts_with("application/x-www-form-urlencoded")
+                || ct.starts_with("multipart/form-data")
+        }
+        None => false,
+    }
+}
+
+fn content_type(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_ascii_lowercase())
+}
+
+/// Parse an `application/x-www-form-urlencoded` or `multipart/form-data` body.
+///
+/// Multipart file parts are read as text like any other field; the task forms
+/// have no file inputs. Repeated fields keep the last value.
+pub async fn parse_form(headers: &HeaderMap, body: Bytes) -> Result<FormFields, String> {
+    let raw_content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+
+    match content_type(headers) {
+        Some(ct) if ct.starts_with("application/x-www-form-urlencoded") => {
+            Ok(form_urlencoded::parse(&body).into_owned().collect())
+        }
+        Some(ct) if ct.starts_with("multipart/form-data") => {
+            let boundary = multer::parse_boundary(raw_content_type).map_err(|e| e.to_string())?;
+            let stream = futures_util::stream::once(async move { Ok::<_, Infallible>(body) });
+            let mut multipart = multer::Multipart::new(stream, boundary);
+
+            let mut fields = FormFields::new();
+            while let Some(field) = multipart.next_field().await.map_err(|e| e.to_string())? {
+                let Some(name) = field.name().map(str::to_string) else {
+                    continue;
+                };
+                let value = field.text().await.map_err(|e| e.to_string())?;
+                fields.insert(name, value);
+            }
+            Ok(fields)
+        }
+        _ => Err("Unsupported form content type".to_string()),
+    }
+}
+
+/// Returns the CSRF token from the request's cookie, if any.
+pub fn csrf_cookie(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, _)| *name == CSRF_TOKEN)
+        .map(|(_, value)| value.to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Generate a fresh, unguessable CSRF token.
+pub fn new_csrf_token() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+/// Build the `Set-Cookie` value that stores the CSRF token.
+pub fn csrf_set_cookie(token: &str) -> String {
+    format!("{}={}; Path=/; HttpOnly; SameSite=Strict", CSRF_TOKEN, token)
+}
+
+/// Verify a form submission using the double-submit cookie pattern.
+///
+/// The hidden form field must match the token in the `csrf_token` cookie. A
+/// cross-site page can make the browser send the cookie, but it cannot read it
+/// to put the same value in the form.
+pub fn verify_csrf(headers: &HeaderMap, fields: &FormFields) -> bool {
+    match (csrf_cookie(headers), fields.get(CSRF_TOKEN)) {
+        (Some(cookie), Some(field)) => constant_time_eq(cookie.as_bytes(), field.as_bytes()),
+        _ => false,
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// The fields of the create and edit task forms.
+#[derive(Debug, Default, Serialize)]
+pub struct TaskForm {
+    pub title: String,
+    pub description: String,
+    pub completed: bool,
+}
+
+impl TaskForm {
+    /// Read the task fields from a submitted form.
+    ///
+    /// An unchecked checkbox is not submitted at all, so `completed` is true
+    /// only when the field is present with a truthy value.
+    pub fn from_fields(fields: &FormFields) -> Self {
+        TaskForm {
+            title: fields.get("title").map(|t| t.trim().to_string()).unwrap_or_default(),
+            description: fields
+                .get("description")
+                .map(|d| d.trim().to_string())
+                .unwrap_or_default(),
+            completed: matches!(
+                fields.get("completed").map(String::as_str),
+                Some("true" | "on" | "1")
+            ),
+        }
+    }
+
+    /// Validate the form, returning an error message per invalid field.
+    pub fn validate(&self) -> BTreeMap<&'static str, String> {
+        models::validate_fields(&self.title, &self.description)
+    }
+}
+
+impl From<TaskForm> for CreateTask {
+    fn from(form: TaskForm) -> Self {
+        CreateTask {
+            title: form.title,
+            description: form.description,
+            due_at: None,
+            priority: None,
+            tags: Default::default(),
+            parent_id: None,
+            blocked_by: Default::default(),
+        }
+    }
+}
+
+impl From<TaskForm> for UpdateTask {
+    fn from(form: TaskForm) -> Self {
+        UpdateTask {
+            title: Some(form.title),
+            description: Some(form.description),
+            completed: Some(form.completed),
+            due_at: None,
+            priority: None,
+            tags: None,
+            parent_id: None,
+            blocked_by: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,91 @@
+use super::*;
+use crate::models::MAX_DESCRIPTION_LEN;
+use hyper::header::HeaderValue;
+
+fn headers(content_type: &str, cookie: Option<&str>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_str(content_type).unwrap());
+    if let Some(cookie) = cookie {
+        headers.insert(COOKIE, HeaderValue::from_str(cookie).unwrap());
+    }
+    headers
+}
+
+#[test]
+fn test_is_form() {
+    assert!(is_form(&headers("application/x-www-form-urlencoded", None)));
+    assert!(is_form(&headers("multipart/form-data; boundary=X", None)));
+    assert!(!is_form(&headers("application/json", None)));
+    assert!(!is_form(&HeaderMap::new()));
+}
+
+#[tokio::test]
+async fn test_parse_urlencoded_form() {
+    let body = Bytes::from("title=Buy+milk&description=2%25+fat&completed=on");
+    let fields = parse_form(&headers("application/x-www-form-urlencoded", None), body)
+        .await
+        .unwrap();
+
+    assert_eq!(fields["title"], "Buy milk");
+    assert_eq!(fields["description"], "2% fat");
+    assert!(TaskForm::from_fields(&fields).completed);
+}
+
+#[tokio::test]
+async fn test_parse_multipart_form() {
+    let body = Bytes::from(
+        "--X\r\n\
+         Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+         Buy milk\r\n\
+         --X\r\n\
+         Content-Disposition: form-data; name=\"description\"\r\n\r\n\
+         Semi-skimmed\r\n\
+         --X--\r\n",
+    );
+    let fields = parse_form(&headers("multipart/form-data; boundary=X", None), body)
+        .await
+        .unwrap();
+
+    assert_eq!(fields["title"], "Buy milk");
+    assert_eq!(fields["description"], "Semi-skimmed");
+    assert!(!TaskForm::from_fields(&fields).completed);
+}
+
+#[tokio::test]
+async fn test_parse_form_rejects_other_content_types() {
+    let result = parse_form(&headers("application/json", None), Bytes::from("{}")).await;
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_verify_csrf() {
+    let mut fields = FormFields::new();
+    fields.insert(CSRF_TOKEN.to_string(), "abc123".to_string());
+
+    let cookie = Some("theme=dark; csrf_token=abc123");
+    assert!(verify_csrf(&headers("application/x-www-form-urlencoded", cookie), &fields));
+
+    let wrong_cookie = Some("csrf_token=other");
+    assert!(!verify_csrf(&headers("application/x-www-form-urlencoded", wrong_cookie), &fields));
+    assert!(!verify_csrf(&headers("application/x-www-form-urlencoded", None), &fields));
+    assert!(!verify_csrf(&headers("application/x-www-form-urlencoded", cookie), &FormFields::new()));
+}
+
+#[test]
+fn test_task_form_validation() {
+    let valid = TaskForm {
+        title: "Title".to_string(),
+        description: String::new(),
+        completed: false,
+    };
+    assert!(valid.validate().is_empty());
+
+    let invalid = TaskForm {
+        title: String::new(),
+        description: "x".repeat(MAX_DESCRIPTION_LEN + 1),
+        completed: false,
+    };
+    let errors = invalid.validate();
+    assert_eq!(errors["title"], "Title is required");
+    assert!(errors.contains_key("description"));
+}
@@ -0,0 +1,365 @@
+use crate::events::TaskEvent;
+use crate::handlers::tasks::store_error_status;
+use crate::models::{self, CreateTask, Priority, ReplaceTask, Task};
+use crate::query::{ListQuery, SortKey};
+use crate::store::{Store, StoreError};
+use crate::ws::Subscription;
+use async_graphql::{
+    Context, Enum, Error, ErrorExtensions, InputObject, Object, Schema, Subscription as GraphQlSubscription,
+};
+use chrono::{DateTime, Utc};
+use futures_util::{stream, Stream};
+use hyper::StatusCode;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Deepest a query may nest fields, so `parent { parent { … } }` chains
+/// cannot be made arbitrarily expensive.
+pub const MAX_DEPTH: usize = 16;
+
+/// The schema served at `/graphql`.
+pub type TaskSchema = Schema<QueryRoot, MutationRoot, SubscriptionRoot>;
+
+/// Builds the schema, resolving against `store`.
+pub fn schema(store: Arc<Store>) -> TaskSchema {
+    Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+        .data(store)
+        .limit_depth(MAX_DEPTH)
+        .finish()
+}
+
+/// The `/graphql` endpoint: the schema, and whether the GraphiQL IDE is
+/// served there.
+pub struct GraphQl {
+    pub schema: TaskSchema,
+    pub graphiql: bool,
+}
+
+impl GraphQl {
+    pub fn new(store: Arc<Store>, graphiql: bool) -> Self {
+        GraphQl {
+            schema: schema(store),
+            graphiql,
+        }
+    }
+}
+
+fn store<'a>(ctx: &Context<'a>) -> &'a Arc<Store> {
+    ctx.data_unchecked::<Arc<Store>>()
+}
+
+/// A store failure as a GraphQL error, with the HTTP status the same failure
+/// gets from the REST routes in its `status` extension.
+fn store_error(err: StoreError, conditional: bool) -> Error {
+    let status = store_error_status(&err, conditional);
+    Error::new(err.to_string()).extend_with(|_, extensions| extensions.set("status", status.as_u16()))
+}
+
+/// Validation failures as a GraphQL error, with the message for each field
+/// in its `fields` extension.
+fn validation_error(errors: &BTreeMap<&'static str, String>) -> Error {
+    let fields = async_graphql::Value::from_json(serde_json::json!(errors)).unwrap_or_default();
+    Error::new("Validation failed").extend_with(|_, extensions| {
+        extensions.set("status", StatusCode::UNPROCESSABLE_ENTITY.as_u16());
+        extensions.set("fields", fields.clone());
+    })
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+#[graphql(name = "Priority", remote = "crate::models::Priority")]
+enum PriorityValue {
+    Low,
+    Medium,
+    High,
+    Urgent,
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+#[graphql(name = "ChangeType", remote = "crate::audit::AuditAction")]
+enum ChangeType {
+    Created,
+    Updated,
+    Deleted,
+    Restored,
+    Purged,
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+#[graphql(name = "TaskSort", remote = "crate::query::SortKey")]
+enum TaskSort {
+    Id,
+    DueAt,
+    Priority,
+}
+
+/// Whether a tag filter keeps tasks with any or all of the tags.
+#[derive(Enum, Clone, Copy, Default, PartialEq, Eq)]
+enum TagMatch {
+    #[default]
+    Any,
+    All,
+}
+
+#[Object]
+impl Task {
+    async fn id(&self) -> u64 {
+        self.id
+    }
+
+    async fn title(&self) -> &str {
+        &self.title
+    }
+
+    async fn description(&self) -> &str {
+        &self.description
+    }
+
+    async fn completed(&self) -> bool {
+        self.completed
+    }
+
+    /// Incremented on every change; pass it back as `version` to make a
+    /// mutation conditional.
+    async fn version(&self) -> u64 {
+        self.version
+    }
+
+    async fn due_at(&self) -> Option<DateTime<Utc>> {
+        self.due_at
+    }
+
+    async fn priority(&self) -> Option<PriorityValue> {
+        self.priority.map(Into::into)
+    }
+
+    async fn tags(&self) -> Vec<&str> {
+        self.tags.iter().map(String::as_str).collect()
+    }
+
+    async fn parent_id(&self) -> Option<u64> {
+        self.parent_id
+    }
+
+    /// The task this one is a subtask of.
+    async fn parent(&self, ctx: &Context<'_>) -> Option<Task> {
+        let parent_id = self.parent_id?;
+        store(ctx).get_task(parent_id).await
+    }
+
+    async fn subtasks(&self, ctx: &Context<'_>) -> Vec<Task> {
+        store(ctx).subtasks(self.id).await.unwrap_or_default()
+    }
+
+    async fn blocked_by(&self) -> Vec<u64> {
+        self.blocked_by.iter().copied().collect()
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    async fn updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+
+    async fn completed_at(&self) -> Option<DateTime<Utc>> {
+        self.completed_at
+    }
+
+    async fn deleted_at(&self) -> Option<DateTime<Utc>> {
+        self.deleted_at
+    }
+}
+
+#[Object]
+impl TaskEvent {
+    /// The change's position in the audit log.
+    async fn id(&self) -> u64 {
+        self.id
+    }
+
+    #[graphql(name = "type")]
+    async fn action(&self) -> ChangeType {
+        self.action.into()
+    }
+
+    async fn task_id(&self) -> u64 {
+        self.task_id
+    }
+
+    /// The task after the change; null once it has been purged.
+    async fn task(&self) -> Option<&Task> {
+        self.task.as_ref()
+    }
+}
+
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// A task by ID; null if there is none or it is in the trash.
+    async fn task(&self, ctx: &Context<'_>, id: u64) -> Option<Task> {
+        store(ctx).get_task(id).await
+    }
+
+    /// Lists tasks, filtered and sorted as `GET /tasks` does.
+    #[allow(clippy::too_many_arguments)]
+    async fn tasks(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] tags: Vec<String>,
+        #[graphql(default)] tag_match: TagMatch,
+        #[graphql(default)] overdue: bool,
+        #[graphql(default)] include_deleted: bool,
+        #[graphql(default_with = "TaskSort::Id")] sort: TaskSort,
+        #[graphql(default)] descending: bool,
+    ) -> Vec<Task> {
+        let list_query = ListQuery {
+            tags: tags.iter().map(|tag| models::normalize_tag(tag)).collect(),
+            all_tags: tag_match == TagMatch::All,
+            overdue,
+            include_deleted,
+            sort: SortKey::from(sort),
+            descending,
+        };
+        store(ctx).query_tasks(&list_query, Utc::now()).await
+    }
+}
+
+#[derive(InputObject)]
+struct CreateTaskInput {
+    title: String,
+    description: String,
+    due_at: Option<DateTime<Utc>>,
+    priority: Option<PriorityValue>,
+    #[graphql(default)]
+    tags: Vec<String>,
+    parent_id: Option<u64>,
+    #[graphql(default)]
+    blocked_by: Vec<u64>,
+}
+
+/// A task's complete new state; fields left out are cleared.
+#[derive(InputObject)]
+struct ReplaceTaskInput {
+    title: String,
+    description: String,
+    completed: bool,
+    due_at: Option<DateTime<Utc>>,
+    priority: Option<PriorityValue>,
+    #[graphql(default)]
+    tags: Vec<String>,
+    parent_id: Option<u64>,
+    #[graphql(default)]
+    blocked_by: Vec<u64>,
+}
+
+pub struct MutationRoot;
+
+/// Mutations mirror `POST /tasks`, `PUT /tasks/{id}` and
+/// `DELETE /tasks/{id}`. A `version` argument plays the part of `If-Match`.
+/// Failures carry the status the REST route would have answered with in a
+/// `status` extension.
+#[Object]
+impl MutationRoot {
+    async fn create_task(&self, ctx: &Context<'_>, input: CreateTaskInput) -> async_graphql::Result<Task> {
+        let task = CreateTask {
+            title: input.title,
+            description: input.description,
+            due_at: input.due_at,
+            priority: input.priority.map(Priority::from),
+            tags: input.tags.iter().map(|tag| models::normalize_tag(tag)).collect(),
+            parent_id: input.parent_id,
+            blocked_by: input.blocked_by.into_iter().collect(),
+        };
+        let errors = task.validate();
+        if !errors.is_empty() {
+            return Err(validation_error(&errors));
+        }
+
+        store(ctx).create_task(task).await.map_err(|err| store_error(err, false))
+    }
+
+    /// Replaces a task, creating it with the ID if there is none and no
+    /// `version` is given.
+    async fn update_task(
+        &self,
+        ctx: &Context<'_>,
+        id: u64,
+        input: ReplaceTaskInput,
+        version: Option<u64>,
+    ) -> async_graphql::Result<Task> {
+        let replacement = ReplaceTask {
+            id: Some(id),
+            title: input.title,
+            description: input.description,
+            completed: input.completed,
+            due_at: input.due_at,
+            priority: input.priority.map(Priority::from),
+            tags: input.tags.iter().map(|tag| models::normalize_tag(tag)).collect(),
+            parent_id: input.parent_id,
+            blocked_by: input.blocked_by.into_iter().collect(),
+            version: None,
+            _created_at: None,
+            _updated_at: None,
+            _completed_at: None,
+            _deleted_at: None,
+        };
+        let errors = replacement.validate();
+        if !errors.is_empty() {
+            return Err(validation_error(&errors));
+        }
+
+        let result = store(ctx)
+            .replace_task(id, replacement, |current| match (current, version) {
+                (Some(task), Some(version)) => task.version == version,
+                (current, version) => current.is_some() || version.is_none(),
+            })
+            .await;
+        match result {
+            Ok((task, _)) => Ok(task),
+            Err(err) => Err(store_error(err, version.is_some())),
+        }
+    }
+
+    /// Moves a task to the trash, returning it as deleted.
+    async fn delete_task(&self, ctx: &Context<'_>, id: u64, version: Option<u64>) -> async_graphql::Result<Task> {
+        let result = match version {
+            Some(version) => store(ctx).delete_task_if(id, |task| task.version == version).await,
+            None => store(ctx).delete_task(id).await,
+        };
+        result.map_err(|err| store_error(err, version.is_some()))
+    }
+}
+
+pub struct SubscriptionRoot;
+
+#[GraphQlSubscription]
+impl SubscriptionRoot {
+    /// Changes to the tasks with any of `ids` or `tags`, or to every task if
+    /// both are empty, from the time of subscribing.
+    ///
+    /// Ends if the subscriber falls too far behind; it can subscribe again
+    /// and query for what it missed.
+    async fn task_changes(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] ids: Vec<u64>,
+        #[graphql(default)] tags: Vec<String>,
+    ) -> impl Stream<Item = TaskEvent> {
+        let subscription = Subscription::new(ids.into_iter().collect(), tags.into_iter().collect());
+        let (_, receiver) = store(ctx).subscribe(None);
+        stream::unfold((receiver, subscription), |(mut receiver, subscription)| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) if subscription.matches(&event) => return Some((event, (receiver, subscription))),
+                    Ok(_) => continue,
+                    Err(_) => return None,
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,116 @@
+use super::*;
+use futures_util::StreamExt;
+use serde_json::{json, Value};
+
+async fn execute(schema: &TaskSchema, query: &str) -> Value {
+    let response = schema.execute(query).await;
+    serde_json::to_value(&response).unwrap()
+}
+
+async fn create(schema: &TaskSchema, fields: &str) -> Value {
+    let response = execute(schema, &format!("mutation {{ createTask(input: {{ {} }}) {{ id version }} }}", fields)).await;
+    response["data"]["createTask"].clone()
+}
+
+#[tokio::test]
+async fn test_create_and_query_tasks() {
+    let schema = schema(Arc::new(Store::new()));
+    let parent = create(&schema, r#"title: "Parent", description: "", tags: ["Work"]"#).await;
+    let parent_id = parent["id"].as_u64().unwrap();
+    create(&schema, &format!(r#"title: "Child", description: "", priority: HIGH, parentId: {}"#, parent_id)).await;
+
+    let response = execute(&schema, r#"{ tasks(tags: ["work"]) { title tags } }"#).await;
+    assert_eq!(response["data"]["tasks"], json!([{ "title": "Parent", "tags": ["work"] }]));
+
+    let response = execute(&schema, "{ tasks(sort: PRIORITY) { title priority parent { title } } }").await;
+    assert_eq!(
+        response["data"]["tasks"],
+        json!([
+            { "title": "Child", "priority": "HIGH", "parent": { "title": "Parent" } },
+            { "title": "Parent", "priority": null, "parent": null },
+        ])
+    );
+
+    let response = execute(&schema, &format!("{{ task(id: {}) {{ subtasks {{ title }} }} }}", parent_id)).await;
+    assert_eq!(response["data"]["task"]["subtasks"], json!([{ "title": "Child" }]));
+    assert_eq!(execute(&schema, "{ task(id: 99) { title } }").await["data"]["task"], Value::Null);
+}
+
+#[tokio::test]
+async fn test_mutation_errors_carry_status() {
+    let schema = schema(Arc::new(Store::new()));
+    let response = execute(&schema, r#"mutation { createTask(input: { title: "T", description: "", parentId: 99 }) { id } }"#).await;
+    assert_eq!(response["errors"][0]["extensions"]["status"], 422);
+
+    let response = execute(&schema, r#"mutation { createTask(input: { title: "T", description: "", tags: [""] }) { id } }"#).await;
+    assert_eq!(response["errors"][0]["message"], "Validation failed");
+    assert!(response["errors"][0]["extensions"]["fields"]["tags"].is_string());
+
+    let response = execute(&schema, r#"mutation { createTask(input: { title: " ", description: "" }) { id } }"#).await;
+    assert_eq!(response["errors"][0]["extensions"]["fields"]["title"], "Title is required");
+    assert_eq!(execute(&schema, "{ tasks { id } }").await["data"]["tasks"], serde_json::json!([]));
+
+    let response = execute(&schema, "mutation { deleteTask(id: 7) { id } }").await;
+    assert_eq!(response["errors"][0]["extensions"]["status"], 404);
+}
+
+#[tokio::test]
+async fn test_update_and_delete_honor_version() {
+    let schema = schema(Arc::new(Store::new()));
+    let task = create(&schema, r#"title: "Draft", description: "", tags: ["a"]"#).await;
+    let id = task["id"].as_u64().unwrap();
+
+    let update = |version: u64| {
+        format!(
+            r#"mutation {{ updateTask(id: {}, version: {}, input: {{ title: "Final", description: "", completed: true }}) {{ title completed tags version }} }}"#,
+            id, version
+        )
+    };
+    let response = execute(&schema, &update(5)).await;
+    assert_eq!(response["errors"][0]["extensions"]["status"], 412);
+
+    let response = execute(&schema, &update(1)).await;
+    assert_eq!(
+        response["data"]["updateTask"],
+        json!({ "title": "Final", "completed": true, "tags": [], "version": 2 })
+    );
+
+    let response = execute(&schema, &format!("mutation {{ deleteTask(id: {}, version: 1) {{ id }} }}", id)).await;
+    assert_eq!(response["errors"][0]["extensions"]["status"], 412);
+    let response = execute(&schema, &format!("mutation {{ deleteTask(id: {}) {{ deletedAt }} }}", id)).await;
+    assert!(response["data"]["deleteTask"]["deletedAt"].is_string());
+
+    let response = execute(&schema, "{ tasks(includeDeleted: true) { title } live: tasks { title } }").await;
+    assert_eq!(response["data"]["tasks"], json!([{ "title": "Final" }]));
+    assert_eq!(response["data"]["live"], json!([]));
+}
+
+#[tokio::test]
+async fn test_update_creates_missing_tasks_without_version() {
+    let schema = schema(Arc::new(Store::new()));
+    let replace = r#"input: { title: "New", description: "", completed: false }) { id title }"#;
+
+    let response = execute(&schema, &format!("mutation {{ updateTask(id: 4, version: 1, {} }}", replace)).await;
+    assert_eq!(response["errors"][0]["extensions"]["status"], 412);
+
+    let response = execute(&schema, &format!("mutation {{ updateTask(id: 4, {} }}", replace)).await;
+    assert_eq!(response["data"]["updateTask"], json!({ "id": 4, "title": "New" }));
+}
+
+#[tokio::test]
+async fn test_subscribe_to_task_changes() {
+    let store = Arc::new(Store::new());
+    let schema = schema(Arc::clone(&store));
+    let mut changes = schema.execute_stream(r#"subscription { taskChanges(tags: ["Urgent"]) { type taskId task { title } } }"#);
+
+    // Poll once so the subscription is in place before the changes
+    assert!(futures_util::poll!(changes.next()).is_pending());
+    create(&schema, r#"title: "Other", description: """#).await;
+    create(&schema, r#"title: "Fire", description: "", tags: ["urgent"]"#).await;
+
+    let response = serde_json::to_value(changes.next().await.unwrap()).unwrap();
+    assert_eq!(
+        response["data"]["taskChanges"],
+        json!({ "type": "CREATED", "taskId": 2, "task": { "title": "Fire" } })
+    );
+}
@@ -0,0 +1,294 @@
+use crate::audit::{self, AuditAction, AuditContext};
+use crate::events;
+use crate::models::{self, CreateTask, Priority, UpdateTask};
+use crate::query::{ListQuery, SortKey};
+use crate::store::{Store, StoreError};
+use crate::ws::Subscription;
+use chrono::{DateTime, Utc};
+use futures_util::{stream, Stream, StreamExt};
+use proto::task_service_server::TaskServiceServer;
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tonic::transport::server::Router;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+/// The types and service traits generated from `proto/tasks.proto`.
+#[allow(clippy::all)]
+pub mod proto {
+    tonic::include_proto!("tasks.v1");
+
+    /// Descriptors of every message and service, for server reflection.
+    pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("tasks_descriptor");
+}
+
+/// Builds the gRPC server: the task service, resolving against `store`, and
+/// the reflection service describing it.
+pub fn router(store: Arc<Store>) -> Result<Router, tonic_reflection::server::Error> {
+    let reflection = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
+        .build_v1()?;
+    Ok(Server::builder()
+        .add_service(TaskServiceServer::new(TaskService { store }))
+        .add_service(reflection))
+}
+
+/// `tasks.v1.TaskService`, backed by the same store as the HTTP API.
+pub struct TaskService {
+    store: Arc<Store>,
+}
+
+/// The audit context of a call: its `x-actor` metadata and a fresh request
+/// ID.
+fn context<T>(request: &Request<T>) -> AuditContext {
+    AuditContext::from_request(&request.metadata().clone().into_headers(), Uuid::new_v4())
+}
+
+/// Map a store failure to a status. A missing task is `NOT_FOUND`, except
+/// under a `version` check, where there is nothing for the version to match.
+fn store_status(err: StoreError, conditional: bool) -> Status {
+    let message = err.to_string();
+    match err {
+        StoreError::NotFound if !conditional => Status::not_found(message),
+        StoreError::NotFound | StoreError::PreconditionFailed => Status::failed_precondition(message),
+        StoreError::Conflict(_) | StoreError::Aborted => Status::aborted(message),
+        StoreError::Invalid(_) | StoreError::InvalidField(..) => Status::invalid_argument(message),
+    }
+}
+
+fn validation_status(errors: &BTreeMap<&'static str, String>) -> Status {
+    let fields: Vec<String> = errors.iter().map(|(field, message)| format!("{}: {}", field, message)).collect();
+    Status::invalid_argument(format!("Validation failed: {}", fields.join("; ")))
+}
+
+fn timestamp(time: DateTime<Utc>) -> prost_types::Timestamp {
+    prost_types::Timestamp {
+        seconds: time.timestamp(),
+        nanos: time.timestamp_subsec_nanos() as i32,
+    }
+}
+
+fn datetime(timestamp: prost_types::Timestamp) -> Result<DateTime<Utc>, Status> {
+    u32::try_from(timestamp.nanos)
+        .ok()
+        .and_then(|nanos| DateTime::from_timestamp(timestamp.seconds, nanos))
+        .ok_or_else(|| Status::invalid_argument("Invalid timestamp"))
+}
+
+fn priority(priority: i32) -> Result<Option<Priority>, Status> {
+    match proto::Priority::try_from(priority) {
+        Ok(proto::Priority::Unspecified) => Ok(None),
+        Ok(proto::Priority::Low) => Ok(Some(Priority::Low)),
+        Ok(proto::Priority::Medium) => Ok(Some(Priority::Medium)),
+        Ok(proto::Priority::High) => Ok(Some(Priority::High)),
+        Ok(proto::Priority::Urgent) => Ok(Some(Priority::Urgent)),
+        Err(_) => Err(Status::invalid_argument(format!("Invalid priority: {}", priority))),
+    }
+}
+
+impl From<&models::Task> for proto::Task {
+    fn from(task: &models::Task) -> Self {
+        let priority = match task.priority {
+            None => proto::Priority::Unspecified,
+            Some(Priority::Low) => proto::Priority::Low,
+            Some(Priority::Medium) => proto::Priority::Medium,
+            Some(Priority::High) => proto::Priority::High,
+            Some(Priority::Urgent) => proto::Priority::Urgent,
+        };
+        proto::Task {
+            id: task.id,
+            title: task.title.clone(),
+            description: task.description.clone(),
+            completed: task.completed,
+            version: task.version,
+            due_at: task.due_at.map(timestamp),
+            priority: priority.into(),
+            tags: task.tags.iter().cloned().collect(),
+            parent_id: task.parent_id,
+            blocked_by: task.blocked_by.iter().copied().collect(),
+            created_at: Some(timestamp(task.created_at)),
+            updated_at: Some(timestamp(task.updated_at)),
+            completed_at: task.completed_at.map(timestamp),
+            deleted_at: task.deleted_at.map(timestamp),
+        }
+    }
+}
+
+impl From<&events::TaskEvent> for proto::TaskEvent {
+    fn from(event: &events::TaskEvent) -> Self {
+        let change_type = match event.action {
+            AuditAction::Created => proto::ChangeType::Created,
+            AuditAction::Updated => proto::ChangeType::Updated,
+            AuditAction::Deleted => proto::ChangeType::Deleted,
+            AuditAction::Restored => proto::ChangeType::Restored,
+            AuditAction::Purged => proto::ChangeType::Purged,
+        };
+        proto::TaskEvent {
+            id: event.id,
+            r#type: change_type.into(),
+            task_id: event.task_id,
+            task: event.task.as_ref().map(proto::Task::from),
+        }
+    }
+}
+
+impl TryFrom<proto::CreateTask> for CreateTask {
+    type Error = Status;
+
+    fn try_from(task: proto::CreateTask) -> Result<Self, Status> {
+        Ok(CreateTask {
+            title: task.title,
+            description: task.description,
+            due_at: task.due_at.map(datetime).transpose()?,
+            priority: priority(task.priority)?,
+            tags: task.tags.iter().map(|tag| models::normalize_tag(tag)).collect(),
+            parent_id: task.parent_id,
+            blocked_by: task.blocked_by.into_iter().collect(),
+        })
+    }
+}
+
+impl TryFrom<proto::UpdateTask> for UpdateTask {
+    type Error = Status;
+
+    fn try_from(task: proto::UpdateTask) -> Result<Self, Status> {
+        let due_at = match task.due_at {
+            Some(due_at) => Some(Some(datetime(due_at)?)),
+            None if task.clear_due_at => Some(None),
+            None => None,
+        };
+        Ok(UpdateTask {
+            title: task.title,
+            description: task.description,
+            completed: task.completed,
+            due_at,
+            priority: task.priority.map(priority).transpose()?,
+            tags: task.tags.map(|tags| tags.tags.iter().map(|tag| models::normalize_tag(tag)).collect()),
+            parent_id: task.parent_id.map(|parent_id| Some(parent_id).filter(|&id| id != 0)),
+            blocked_by: task.blocked_by.map(|blocked_by| blocked_by.ids.into_iter().collect()),
+        })
+    }
+}
+
+type TaskEventStream = Pin<Box<dyn Stream<Item = Result<proto::TaskEvent, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl proto::task_service_server::TaskService for TaskService {
+    async fn create_task(
+        &self,
+        request: Request<proto::CreateTaskRequest>,
+    ) -> Result<Response<proto::Task>, Status> {
+        let context = context(&request);
+        let task = request.into_inner().task.ok_or_else(|| Status::invalid_argument("Missing task"))?;
+        let task = CreateTask::try_from(task)?;
+        let errors = task.validate();
+        if !errors.is_empty() {
+            return Err(validation_status(&errors));
+        }
+
+        let task = audit::scope(context, self.store.create_task(task)).await.map_err(|err| store_status(err, false))?;
+        Ok(Response::new(proto::Task::from(&task)))
+    }
+
+    async fn get_task(&self, request: Request<proto::GetTaskRequest>) -> Result<Response<proto::Task>, Status> {
+        let id = request.into_inner().id;
+        let task = self.store.get_task(id).await.ok_or_else(|| store_status(StoreError::NotFound, false))?;
+        Ok(Response::new(proto::Task::from(&task)))
+    }
+
+    async fn list_tasks(
+        &self,
+        request: Request<proto::ListTasksRequest>,
+    ) -> Result<Response<proto::ListTasksResponse>, Status> {
+        let request = request.into_inner();
+        let sort = match proto::SortKey::try_from(request.sort) {
+            Ok(proto::SortKey::Id) => SortKey::Id,
+            Ok(proto::SortKey::DueAt) => SortKey::DueAt,
+            Ok(proto::SortKey::Priority) => SortKey::Priority,
+            Err(_) => return Err(Status::invalid_argument(format!("Invalid sort key: {}", request.sort))),
+        };
+        let list_query = ListQuery {
+            tags: request.tags.iter().map(|tag| models::normalize_tag(tag)).collect(),
+            all_tags: request.all_tags,
+            overdue: request.overdue,
+            include_deleted: request.include_deleted,
+            sort,
+            descending: request.descending,
+        };
+        let tasks = self.store.query_tasks(&list_query, Utc::now()).await;
+        Ok(Response::new(proto::ListTasksResponse {
+            tasks: tasks.iter().map(proto::Task::from).collect(),
+        }))
+    }
+
+    async fn update_task(
+        &self,
+        request: Request<proto::UpdateTaskRequest>,
+    ) -> Result<Response<proto::Task>, Status> {
+        let context = context(&request);
+        let request = request.into_inner();
+        let task = request.task.ok_or_else(|| Status::invalid_argument("Missing task"))?;
+        let update = UpdateTask::try_from(task)?;
+        let errors = update.validate();
+        if !errors.is_empty() {
+            return Err(validation_status(&errors));
+        }
+
+        let version = request.version;
+        let result = self
+            .store
+            .update_task_if(request.id, update, |task| version.is_none_or(|version| task.version == version));
+        let task = audit::scope(context, result).await.map_err(|err| store_status(err, version.is_some()))?;
+        Ok(Response::new(proto::Task::from(&task)))
+    }
+
+    async fn delete_task(
+        &self,
+        request: Request<proto::DeleteTaskRequest>,
+    ) -> Result<Response<proto::Task>, Status> {
+        let context = context(&request);
+        let proto::DeleteTaskRequest { id, version } = request.into_inner();
+        let result = self
+            .store
+            .delete_task_if(id, |task| version.is_none_or(|version| task.version == version));
+        let task = audit::scope(context, result).await.map_err(|err| store_status(err, version.is_some()))?;
+        Ok(Response::new(proto::Task::from(&task)))
+    }
+
+    type WatchTasksStream = TaskEventStream;
+
+    /// Replays the recent changes after `after_id`, if set, then streams new
+    /// ones. A watcher that falls too far behind gets `ABORTED`, and can
+    /// watch again from the last event it saw.
+    async fn watch_tasks(
+        &self,
+        request: Request<proto::WatchTasksRequest>,
+    ) -> Result<Response<TaskEventStream>, Status> {
+        let request = request.into_inner();
+        let subscription = Subscription::new(request.ids.into_iter().collect(), request.tags.into_iter().collect());
+        let (missed, receiver) = self.store.subscribe(request.after_id);
+
+        let live = stream::unfold(Some(receiver), |receiver| async move {
+            let mut receiver = receiver?;
+            match receiver.recv().await {
+                Ok(event) => Some((Ok(event), Some(receiver))),
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    let status = Status::aborted("Watcher fell behind; watch again with after_id to catch up");
+                    Some((Err(status), None))
+                }
+                Err(broadcast::error::RecvError::Closed) => None,
+            }
+        });
+        let events = stream::iter(missed.into_iter().map(Ok))
+            .chain(live)
+            .filter(move |event| std::future::ready(event.as_ref().map_or(true, |event| subscription.matches(event))))
+            .map(|event| event.map(|event| proto::TaskEvent::from(&event)));
+        Ok(Response::new(Box::pin(events)))
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,149 @@
+use super::proto::task_service_client::TaskServiceClient;
+use super::*;
+use crate::audit::AuditQuery;
+use tokio::net::TcpListener;
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::Channel;
+use tonic::Code;
+
+/// Serve the gRPC router on a local port, returning a client for it.
+async fn serve(store: Arc<Store>) -> TaskServiceClient<Channel> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let router = router(store).unwrap();
+    tokio::spawn(router.serve_with_incoming(TcpIncoming::from(listener)));
+    TaskServiceClient::connect(format!("http://{}", addr)).await.unwrap()
+}
+
+fn create_request(title: &str, tags: &[&str]) -> proto::CreateTaskRequest {
+    proto::CreateTaskRequest {
+        task: Some(proto::CreateTask {
+            title: title.to_string(),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..Default::default()
+        }),
+    }
+}
+
+#[tokio::test]
+async fn test_crud() {
+    let store = Arc::new(Store::new());
+    let mut client = serve(Arc::clone(&store)).await;
+
+    let mut request = Request::new(create_request("Write docs", &["Docs"]));
+    request.metadata_mut().insert("x-actor", "grace".parse().unwrap());
+    let task = client.create_task(request).await.unwrap().into_inner();
+    assert_eq!((task.id, task.version, task.tags.clone()), (1, 1, vec!["docs".to_string()]));
+    assert!(task.created_at.is_some());
+    client.create_task(create_request("Other", &[])).await.unwrap();
+
+    let fetched = client.get_task(proto::GetTaskRequest { id: 1 }).await.unwrap().into_inner();
+    assert_eq!(fetched, task);
+
+    let list = proto::ListTasksRequest {
+        tags: vec!["DOCS".to_string()],
+        ..Default::default()
+    };
+    let tasks = client.list_tasks(list).await.unwrap().into_inner().tasks;
+    assert_eq!(tasks.iter().map(|task| task.id).collect::<Vec<_>>(), [1]);
+
+    let update = |version| proto::UpdateTaskRequest {
+        id: 1,
+        task: Some(proto::UpdateTask {
+            completed: Some(true),
+            priority: Some(proto::Priority::High.into()),
+            ..Default::default()
+        }),
+        version: Some(version),
+    };
+    let status = client.update_task(update(7)).await.unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    let updated = client.update_task(update(1)).await.unwrap().into_inner();
+    assert!(updated.completed && updated.completed_at.is_some());
+    assert_eq!((updated.version, updated.title.as_str()), (2, "Write docs"));
+    assert_eq!(updated.priority(), proto::Priority::High);
+
+    let deleted = client.delete_task(proto::DeleteTaskRequest { id: 1, version: None }).await.unwrap().into_inner();
+    assert!(deleted.deleted_at.is_some());
+    let status = client.get_task(proto::GetTaskRequest { id: 1 }).await.unwrap_err();
+    assert_eq!(status.code(), Code::NotFound);
+
+    let (events, _) = store.audit_events(&AuditQuery::default()).await;
+    let actors: Vec<&str> = events.iter().map(|event| event.actor.as_str()).collect();
+    assert_eq!(actors, ["anonymous", "anonymous", "anonymous", "grace"]);
+}
+
+#[tokio::test]
+async fn test_invalid_requests() {
+    let mut client = serve(Arc::new(Store::new())).await;
+
+    let status = client.create_task(create_request("", &[])).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert!(status.message().contains("title"));
+
+    let status = client.create_task(proto::CreateTaskRequest { task: None }).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    let mut request = create_request("Child", &[]);
+    request.task.as_mut().unwrap().parent_id = Some(42);
+    let status = client.create_task(request).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    let request = proto::DeleteTaskRequest { id: 9, version: Some(1) };
+    assert_eq!(client.delete_task(request).await.unwrap_err().code(), Code::FailedPrecondition);
+}
+
+#[tokio::test]
+async fn test_watch_tasks() {
+    let store = Arc::new(Store::new());
+    let mut client = serve(Arc::clone(&store)).await;
+    client.create_task(create_request("Before", &["ops"])).await.unwrap();
+
+    let request = proto::WatchTasksRequest {
+        tags: vec!["ops".to_string()],
+        ..Default::default()
+    };
+    let mut events = client.watch_tasks(request).await.unwrap().into_inner();
+    client.create_task(create_request("Unrelated", &[])).await.unwrap();
+    client.create_task(create_request("Deploy", &["Ops"])).await.unwrap();
+
+    let event = events.message().await.unwrap().unwrap();
+    assert_eq!(event.r#type(), proto::ChangeType::Created);
+    assert_eq!((event.id, event.task_id), (3, 3));
+    assert_eq!(event.task.unwrap().title, "Deploy");
+
+    // Resuming replays what came after the given event
+    let request = proto::WatchTasksRequest {
+        after_id: Some(1),
+        ..Default::default()
+    };
+    let mut events = client.watch_tasks(request).await.unwrap().into_inner();
+    assert_eq!(events.message().await.unwrap().unwrap().id, 2);
+    assert_eq!(events.message().await.unwrap().unwrap().id, 3);
+}
+
+#[tokio::test]
+async fn test_reflection_lists_the_service() {
+    use tonic_reflection::pb::v1::server_reflection_client::ServerReflectionClient;
+    use tonic_reflection::pb::v1::server_reflection_request::MessageRequest;
+    use tonic_reflection::pb::v1::server_reflection_response::MessageResponse;
+    use tonic_reflection::pb::v1::ServerReflectionRequest;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(router(Arc::new(Store::new())).unwrap().serve_with_incoming(TcpIncoming::from(listener)));
+    let channel = Channel::from_shared(format!("http://{}", addr)).unwrap().connect().await.unwrap();
+    let mut client = ServerReflectionClient::new(channel);
+
+    let request = ServerReflectionRequest {
+        host: String::new(),
+        message_request: Some(MessageRequest::ListServices(String::new())),
+    };
+    let mut responses = client.server_reflection_info(stream::iter([request])).await.unwrap().into_inner();
+    let response = responses.message().await.unwrap().unwrap();
+    let Some(MessageResponse::ListServicesResponse(services)) = response.message_response else {
+        panic!("expected a list of services");
+    };
+    let names: Vec<String> = services.service.into_iter().map(|service| service.name).collect();
+    assert!(names.contains(&"tasks.v1.TaskService".to_string()), "{:?}", names);
+}
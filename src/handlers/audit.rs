@@ -0,0 +1,98 @@
+use super::tasks::{respond_with_error, respond_with_payload};
+use crate::audit::AuditQuery;
+use crate::formats::Format;
+use crate::responses::{AuditLogBody, Envelope, ErrorEnvelope, HistoryBody};
+use crate::store::Store;
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{Response, StatusCode};
+use std::sync::Arc;
+use tokio::time::Instant;
+use tracing::instrument;
+use uuid::Uuid;
+
+/// Handler for `GET /tasks/{id}/history`.
+///
+/// Lists every change made to the task, oldest first, with who made it, in
+/// which request, and the before and after value of each field it touched.
+/// The history of a deleted task stays available, even once it has been
+/// purged.  Returns 404 Not Found if no task ever had the ID.
+#[utoipa::path(
+    get,
+    path = "/tasks/{id}/history",
+    operation_id = "getTaskHistory",
+    tag = "audit",
+    summary = "Get a task's history",
+    description = "Lists the changes made to the task, oldest first, including after it was deleted.",
+    params(("id" = u64, Path, description = "Task ID")),
+    responses(
+        (status = 200, description = "The task's changes", body = Envelope<HistoryBody>),
+        (status = 400, description = "The ID is invalid", body = ErrorEnvelope),
+        (status = 404, description = "No task ever had the ID", body = ErrorEnvelope),
+    )
+)]
+#[instrument(skip(store))]
+pub async fn handle_task_history(
+    store: Arc<Store>,
+    task_id_str: &str,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let task_id = match task_id_str.parse::<u64>() {
+        Ok(id) => id,
+        Err(_) => return respond_with_error(format, "Invalid task ID", request_id, start_time, StatusCode::BAD_REQUEST),
+    };
+
+    let events = store.history(task_id).await;
+    if events.is_empty() {
+        return respond_with_error(format, "Task not found", request_id, start_time, StatusCode::NOT_FOUND);
+    }
+    let body = HistoryBody { task_id, events };
+    Ok(respond_with_payload(format, StatusCode::OK, body, request_id, start_time))
+}
+
+/// Handler for `GET /audit`.
+///
+/// Lists the changes made to all tasks, newest first, filtered as described
+/// on `AuditQuery`.  When there are more than fit in the page, the body's
+/// `next_before` is the `before` value fetching the next one.  An invalid
+/// filter is a 400 Bad Request.
+#[utoipa::path(
+    get,
+    path = "/audit",
+    operation_id = "listAuditEvents",
+    tag = "audit",
+    summary = "List changes to tasks",
+    description = "Lists the changes made to all tasks, newest first, a page at a time.",
+    params(
+        ("actor" = Option<String>, Query, description = "Only changes made by this `X-Actor`"),
+        ("since" = Option<String>, Query, description = "Only changes made at or after this RFC 3339 time"),
+        ("until" = Option<String>, Query, description = "Only changes made before this RFC 3339 time"),
+        ("before" = Option<u64>, Query, description = "Only changes older than this event ID, from `next_before`"),
+        ("limit" = Option<usize>, Query, description = "Most changes to return"),
+    ),
+    responses(
+        (status = 200, description = "The matching changes", body = Envelope<AuditLogBody>),
+        (status = 400, description = "A query parameter is invalid", body = ErrorEnvelope),
+    )
+)]
+#[instrument(skip(store))]
+pub async fn handle_audit_log(
+    store: Arc<Store>,
+    query: Option<&str>,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let audit_query = match AuditQuery::parse(query) {
+        Ok(audit_query) => audit_query,
+        Err(message) => {
+            return respond_with_error(format, &message, request_id, start_time, StatusCode::BAD_REQUEST)
+        }
+    };
+
+    let (events, next_before) = store.audit_events(&audit_query).await;
+    let body = AuditLogBody { events, next_before };
+    Ok(respond_with_payload(format, StatusCode::OK, body, request_id, start_time))
+}
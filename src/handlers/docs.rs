@@ -0,0 +1,46 @@
+use crate::openapi;
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{Response, StatusCode};
+use tracing::instrument;
+
+/// Swagger UI, loaded from a CDN and pointed at `/openapi.json`.
+const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>Tasks API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js" crossorigin></script>
+  <script>
+    window.onload = () => {
+      window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+    };
+  </script>
+</body>
+</html>
+"##;
+
+/// Handler for `GET /openapi.json`: the OpenAPI 3.1 document describing the
+/// task endpoints.
+#[instrument(skip_all)]
+pub async fn handle_openapi() -> Result<Response<Full<Bytes>>, hyper::Error> {
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "application/json")
+        .body(Full::new(Bytes::from_static(openapi::spec_json().as_bytes())))
+        .unwrap())
+}
+
+/// Handler for `GET /docs`: Swagger UI for browsing and trying out the API.
+#[instrument(skip_all)]
+pub async fn handle_docs() -> Result<Response<Full<Bytes>>, hyper::Error> {
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "text/html; charset=utf-8")
+        .body(Full::new(Bytes::from_static(SWAGGER_UI.as_bytes())))
+        .unwrap())
+}
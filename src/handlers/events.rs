@@ -0,0 +1,84 @@
+use crate::store::Store;
+use bytes::Bytes;
+use futures_util::stream::{self, StreamExt};
+use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::{BodyExt, StreamBody};
+use hyper::body::Frame;
+use hyper::header::{HeaderMap, HeaderName, CACHE_CONTROL, CONTENT_TYPE};
+use hyper::{Response, StatusCode};
+use std::convert::Infallible;
+use std::time::Duration;
+use tokio::time::{self, Instant};
+use tracing::{info, instrument};
+use uuid::Uuid;
+
+/// Request header carrying the ID of the last event a reconnecting client saw.
+const LAST_EVENT_ID: HeaderName = HeaderName::from_static("last-event-id");
+
+/// How often a comment is sent on an idle stream, so proxies do not close it.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// How long clients should wait before reconnecting, in milliseconds.
+const RETRY_MS: u64 = 3000;
+
+/// Handler for `GET /tasks/events`.
+///
+/// Streams task changes as Server-Sent Events: each has the change's ID,
+/// its type (`created`, `updated`, `deleted`, `restored` or `purged`) as the
+/// event name, and the task as it now is in the data.  A client that
+/// reconnects with `Last-Event-ID` first gets the changes it missed, as far
+/// back as the store's replay buffer goes.  Idle streams carry a comment
+/// every 15 seconds.
+///
+/// A client that falls too far behind has its stream closed, and can resume
+/// by reconnecting.
+#[utoipa::path(
+    get,
+    path = "/tasks/events",
+    operation_id = "streamTaskEvents",
+    tag = "tasks",
+    summary = "Stream task changes",
+    description = "Streams each change as a Server-Sent Event named by its type, with a `TaskEvent` as its data.",
+    params(("Last-Event-ID" = Option<u64>, Header, description = "Replay the changes after this event ID")),
+    responses((status = 200, description = "The stream of changes", content_type = "text/event-stream", body = String))
+)]
+#[instrument(skip(headers, store))]
+pub fn handle_task_events(
+    headers: &HeaderMap,
+    store: &Store,
+    request_id: Uuid,
+) -> Response<UnsyncBoxBody<Bytes, Infallible>> {
+    let last_event_id = headers
+        .get(LAST_EVENT_ID)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok());
+    let (missed, receiver) = store.subscribe(last_event_id);
+    info!(?last_event_id, replayed = missed.len(), "Event stream opened");
+
+    let retry = stream::once(async { format!("retry: {}\n\n", RETRY_MS) });
+    let replay = stream::iter(missed.into_iter().map(|event| event.to_sse()));
+    let keep_alive = time::interval_at(Instant::now() + KEEP_ALIVE, KEEP_ALIVE);
+    let live = stream::unfold((receiver, keep_alive), |(mut receiver, mut keep_alive)| async move {
+        let message = tokio::select! {
+            event = receiver.recv() => match event {
+                Ok(event) => event.to_sse(),
+                // Lagging behind or shutting down; the client can reconnect
+                // with `Last-Event-ID`
+                Err(_) => return None,
+            },
+            _ = keep_alive.tick() => ": keep-alive\n\n".to_string(),
+        };
+        Some((message, (receiver, keep_alive)))
+    });
+    let frames = retry
+        .chain(replay)
+        .chain(live)
+        .map(|message| Ok::<_, Infallible>(Frame::data(Bytes::from(message))));
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "text/event-stream")
+        .header(CACHE_CONTROL, "no-cache")
+        .body(StreamBody::new(frames).boxed_unsync())
+        .unwrap()
+}
@@ -0,0 +1,143 @@
+use super::tasks::{read_body, respond};
+use super::ws::{accept_key, is_upgrade, plain_response, switching_protocols, AllowedOrigins};
+use crate::audit::{self, AuditContext};
+use crate::formats::Format;
+use crate::graphql::GraphQl;
+use async_graphql::http::{GraphiQLSource, WebSocket, WebSocketProtocols, WsMessage};
+use async_graphql::BatchRequest;
+use bytes::Bytes;
+use futures_util::future::ready;
+use futures_util::{SinkExt, StreamExt};
+use http_body_util::Full;
+use hyper::header::{HeaderValue, ALLOW, SEC_WEBSOCKET_PROTOCOL};
+use hyper::{body::Incoming, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Role};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use tracing::{error, info, instrument};
+use uuid::Uuid;
+
+/// Handler for `POST /graphql`.
+///
+/// Executes a GraphQL request, or a batch of them sent as a JSON array.  The
+/// response is always JSON and 200 OK once the request could be parsed, with
+/// failures reported in its `errors` as GraphQL expects.  Mutations are
+/// audited under the request's `X-Actor`, as over REST.
+#[instrument(skip_all)]
+pub async fn handle_graphql(
+    req: Request<Incoming>,
+    graphql: &GraphQl,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let batch = match read_body::<BatchRequest>(req, Format::Json, request_id, start_time).await? {
+        Ok(batch) => batch,
+        Err(response) => return Ok(response),
+    };
+    let response = graphql.schema.execute_batch(batch).await;
+    let body = serde_json::to_value(&response).unwrap_or_default();
+    Ok(respond(Format::Json, StatusCode::OK, &body))
+}
+
+/// Handler for `GET /graphql`.
+///
+/// A WebSocket handshake offering the `graphql-transport-ws` or
+/// `graphql-ws` subprotocol opens a connection for subscriptions (and any
+/// other operation), audited under the handshake's `X-Actor`, if it comes
+/// from a page on an allowed origin as on `handle_websocket`.  Otherwise
+/// the GraphiQL IDE is served if it is enabled, and 405 Method Not Allowed
+/// returned if not.
+#[instrument(skip_all)]
+pub async fn handle_graphql_get(
+    req: Request<Incoming>,
+    graphql: &GraphQl,
+    origins: &AllowedOrigins,
+    request_id: Uuid,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    if is_upgrade(req.headers()) {
+        return Ok(upgrade(req, graphql, origins, request_id));
+    }
+    if !graphql.graphiql {
+        let mut response = plain_response(StatusCode::METHOD_NOT_ALLOWED, "GraphQL requests must be POSTed");
+        response.headers_mut().insert(ALLOW, HeaderValue::from_static("POST"));
+        return Ok(response);
+    }
+    let page = GraphiQLSource::build().endpoint("/graphql").subscription_endpoint("/graphql").finish();
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "text/html; charset=utf-8")
+        .body(Full::new(Bytes::from(page)))
+        .unwrap())
+}
+
+/// Upgrade a `GET /graphql` handshake to a GraphQL-over-WebSocket
+/// connection.
+fn upgrade(
+    req: Request<Incoming>,
+    graphql: &GraphQl,
+    origins: &AllowedOrigins,
+    request_id: Uuid,
+) -> Response<Full<Bytes>> {
+    let accept = match accept_key(req.headers(), origins) {
+        Ok(accept) => accept,
+        Err(response) => return *response,
+    };
+    let protocol = req
+        .headers()
+        .get(SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|value| value.to_str().ok())
+        .into_iter()
+        .flat_map(|value| value.split(','))
+        .find_map(|protocol| protocol.trim().parse::<WebSocketProtocols>().ok());
+    let Some(protocol) = protocol else {
+        return plain_response(StatusCode::BAD_REQUEST, "Expected the graphql-transport-ws or graphql-ws subprotocol");
+    };
+    let context = AuditContext::from_request(req.headers(), request_id);
+    let schema = graphql.schema.clone();
+
+    tokio::task::spawn(async move {
+        let upgraded = match hyper::upgrade::on(req).await {
+            Ok(upgraded) => upgraded,
+            Err(err) => return error!(error = %err, "WebSocket upgrade failed"),
+        };
+        info!(%request_id, "GraphQL WebSocket connection opened");
+        let socket = WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None).await;
+        let (mut sink, stream) = socket.split();
+
+        // Pings are answered by the protocol layer, so only text reaches
+        // the GraphQL connection
+        let incoming = stream
+            .take_while(|message| ready(matches!(message, Ok(message) if !message.is_close())))
+            .filter_map(|message| {
+                ready(match message {
+                    Ok(Message::Text(text)) => Some(text.as_str().to_string()),
+                    _ => None,
+                })
+            });
+        let mut outgoing = WebSocket::new(schema, incoming, protocol);
+        audit::scope(context, async move {
+            while let Some(message) = outgoing.next().await {
+                let message = match message {
+                    WsMessage::Text(text) => Message::text(text),
+                    WsMessage::Close(code, reason) => Message::Close(Some(CloseFrame {
+                        code: CloseCode::from(code),
+                        reason: reason.into(),
+                    })),
+                };
+                if sink.send(message).await.is_err() {
+                    break;
+                }
+            }
+        })
+        .await;
+    });
+
+    let mut response = switching_protocols(accept);
+    response
+        .headers_mut()
+        .insert(SEC_WEBSOCKET_PROTOCOL, HeaderValue::from_static(protocol.sec_websocket_protocol()));
+    response
+}
@@ -1,15 +1,63 @@
+// Handler for task history and the audit log
+pub mod audit;
+
 // Handler for basic endpoints (root and health)
 pub mod basic;
 
+// Handler for the API description and its documentation page
+pub mod docs;
+
+// Handler for the stream of task changes
+pub mod events;
+
+// Handler for the GraphQL endpoint
+pub mod graphql;
+
+// Handler for server-rendered HTML pages
+pub mod pages;
+
+// Handler for subtasks and task dependencies
+pub mod relations;
+
+// Handler for full-text task search
+pub mod search;
+
+// Handler for task tags
+pub mod tags;
+
 // Handler for task-related endpoints
 pub mod tasks;
 
+// Handler for the trash of deleted tasks
+pub mod trash;
+
+// Handler for webhook subscriptions
+pub mod webhooks;
+
+// Handler for the WebSocket endpoint
+pub mod ws;
+
 // Re-export handlers
+pub use audit::*;
 pub use basic::*;
+pub use docs::*;
+pub use events::*;
+pub use graphql::*;
+pub use pages::*;
+pub use relations::*;
+pub use search::*;
+pub use tags::*;
 pub use tasks::*;
+pub use trash::*;
+pub use webhooks::*;
+pub use ws::*;
 
 #[cfg(test)]
 mod tests {
     mod basic_tests;
+    mod conditional_tests;
+    mod negotiation_tests;
+    mod pages_tests;
     mod tasks_tests;
+    mod ws_tests;
 }
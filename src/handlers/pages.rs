@@ -0,0 +1,284 @@
+use crate::compression;
+use crate::forms::{self, TaskForm};
+use crate::store::{Store, StoreError};
+use crate::templates;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::header::{HeaderMap, CONTENT_TYPE, LOCATION, SET_COOKIE};
+use hyper::{body::Incoming, Request, Response, StatusCode};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::time::Instant;
+use tracing::{error, instrument};
+use uuid::Uuid;
+
+/// Handler for the HTML task list page.
+///
+/// Renders `index.html` (which extends `base.html`) with every task in the
+/// store, ordered by ID. Browsers reach this through `GET /` when their
+/// `Accept` header prefers `text/html`.
+///
+/// If the template fails to render, the function returns a 500 Internal
+/// Server Error response with a plain-text body.
+#[instrument(skip_all)]
+pub async fn handle_index_page(
+    store: Arc<Store>,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let mut tasks = store.list_tasks().await;
+    tasks.sort_by_key(|task| task.id);
+
+    let ctx = json!({
+        "tasks": tasks,
+        "request_id": request_id.to_string(),
+        "processing_time_ms": start_time.elapsed().as_millis(),
+    });
+
+    Ok(render_page("index.html", ctx, StatusCode::OK, None))
+}
+
+/// Handler for the empty "new task" form.
+///
+/// The form posts back to `POST /tasks`. A CSRF cookie is issued if the
+/// browser does not already have one.
+#[instrument(skip_all)]
+pub async fn handle_new_task_page(
+    headers: &HeaderMap,
+    request_id: Uuid,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let (csrf_token, set_cookie) = csrf_token_for(headers);
+    let ctx = form_context(
+        "New task",
+        None,
+        &TaskForm::default(),
+        &BTreeMap::new(),
+        &csrf_token,
+        request_id,
+    );
+
+    Ok(render_page("task_form.html", ctx, StatusCode::OK, set_cookie))
+}
+
+/// Handler for the "edit task" form, pre-filled from the store.
+///
+/// Returns 400 Bad Request for a malformed ID and 404 Not Found if the task
+/// does not exist.
+#[instrument(skip(headers, store))]
+pub async fn handle_edit_task_page(
+    headers: &HeaderMap,
+    store: Arc<Store>,
+    task_id_str: &str,
+    request_id: Uuid,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let Ok(task_id) = task_id_str.parse::<u64>() else {
+        return Ok(plain_response(StatusCode::BAD_REQUEST, "Invalid task ID"));
+    };
+    let Some(task) = store.get_task(task_id).await else {
+        return Ok(plain_response(StatusCode::NOT_FOUND, "Task not found"));
+    };
+
+    let form = TaskForm {
+        title: task.title,
+        description: task.description,
+        completed: task.completed,
+    };
+    let (csrf_token, set_cookie) = csrf_token_for(headers);
+    let ctx = form_context(
+        "Edit task",
+        Some(task_id),
+        &form,
+        &BTreeMap::new(),
+        &csrf_token,
+        request_id,
+    );
+
+    Ok(render_page("task_form.html", ctx, StatusCode::OK, set_cookie))
+}
+
+/// Handler for `POST /tasks` form submissions.
+///
+/// Accepts `application/x-www-form-urlencoded` and `multipart/form-data`
+/// bodies. On success the task is created and the browser is redirected to
+/// the task list with 303 See Other, so reloading the page does not submit
+/// the form again. Invalid input re-renders the form with the submitted
+/// values and a 422 Unprocessable Entity status. A missing or mismatched
+/// CSRF token is rejected with 403 Forbidden. A gzip-encoded body is decoded
+/// as for the JSON API; other encodings are a 415 Unsupported Media Type.
+#[instrument(skip_all)]
+pub async fn handle_create_task_form(
+    req: Request<Incoming>,
+    store: Arc<Store>,
+    request_id: Uuid,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let (parts, body) = req.into_parts();
+    let body = body.collect().await?.to_bytes();
+    let body = match compression::decode_request_body(&parts.headers, body) {
+        Ok(body) => body,
+        Err(err) => return Ok(plain_response(err.status(), &err.to_string())),
+    };
+    let fields = match forms::parse_form(&parts.headers, body).await {
+        Ok(fields) => fields,
+        Err(err) => return Ok(plain_response(StatusCode::BAD_REQUEST, &err)),
+    };
+    if !forms::verify_csrf(&parts.headers, &fields) {
+        return Ok(plain_response(StatusCode::FORBIDDEN, "Invalid CSRF token"));
+    }
+
+    let form = TaskForm::from_fields(&fields);
+    let errors = form.validate();
+    if !errors.is_empty() {
+        let csrf_token = forms::csrf_cookie(&parts.headers).unwrap_or_default();
+        let ctx = form_context("New task", None, &form, &errors, &csrf_token, request_id);
+        return Ok(render_page("task_form.html", ctx, StatusCode::UNPROCESSABLE_ENTITY, None));
+    }
+
+    match store.create_task(form.into()).await {
+        Ok(_) => Ok(redirect("/")),
+        Err(err) => Ok(plain_response(StatusCode::UNPROCESSABLE_ENTITY, &err.to_string())),
+    }
+}
+
+/// Handler for `POST /tasks/{id}/edit` form submissions.
+///
+/// Behaves like `handle_create_task_form`, but replaces the title,
+/// description and completed status of an existing task. Returns 404 Not
+/// Found if the task does not exist, whatever was submitted.  If the store
+/// rejects the change, the form is re-rendered with the reason: 422
+/// Unprocessable Entity, next to the field at fault, if the task would be
+/// invalid, such as with a parent that no longer exists, and 409 Conflict if
+/// it cannot be completed while it is blocked.
+#[instrument(skip(req, store))]
+pub async fn handle_edit_task_form(
+    req: Request<Incoming>,
+    store: Arc<Store>,
+    task_id_str: &str,
+    request_id: Uuid,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let Ok(task_id) = task_id_str.parse::<u64>() else {
+        return Ok(plain_response(StatusCode::BAD_REQUEST, "Invalid task ID"));
+    };
+
+    let (parts, body) = req.into_parts();
+    let body = body.collect().await?.to_bytes();
+    let body = match compression::decode_request_body(&parts.headers, body) {
+        Ok(body) => body,
+        Err(err) => return Ok(plain_response(err.status(), &err.to_string())),
+    };
+    let fields = match forms::parse_form(&parts.headers, body).await {
+        Ok(fields) => fields,
+        Err(err) => return Ok(plain_response(StatusCode::BAD_REQUEST, &err)),
+    };
+    if !forms::verify_csrf(&parts.headers, &fields) {
+        return Ok(plain_response(StatusCode::FORBIDDEN, "Invalid CSRF token"));
+    }
+    if store.get_task(task_id).await.is_none() {
+        return Ok(plain_response(StatusCode::NOT_FOUND, "Task not found"));
+    }
+
+    let form = TaskForm::from_fields(&fields);
+    let errors = form.validate();
+    if !errors.is_empty() {
+        let csrf_token = forms::csrf_cookie(&parts.headers).unwrap_or_default();
+        let ctx = form_context(
+            "Edit task",
+            Some(task_id),
+            &form,
+            &errors,
+            &csrf_token,
+            request_id,
+        );
+        return Ok(render_page("task_form.html", ctx, StatusCode::UNPROCESSABLE_ENTITY, None));
+    }
+
+    match store.update_task(task_id, form.into()).await {
+        Ok(_) => Ok(redirect("/")),
+        Err(StoreError::NotFound) => Ok(plain_response(StatusCode::NOT_FOUND, "Task not found")),
+        Err(err) => {
+            let (field, status) = match &err {
+                StoreError::InvalidField(field, _) => (*field, StatusCode::UNPROCESSABLE_ENTITY),
+                StoreError::Invalid(_) => ("task", StatusCode::UNPROCESSABLE_ENTITY),
+                // Completing a blocked task; show why next to the checkbox
+                _ => ("completed", StatusCode::CONFLICT),
+            };
+            let errors = BTreeMap::from([(field, err.to_string())]);
+            let form = TaskForm::from_fields(&fields);
+            let csrf_token = forms::csrf_cookie(&parts.headers).unwrap_or_default();
+            let ctx = form_context("Edit task", Some(task_id), &form, &errors, &csrf_token, request_id);
+            Ok(render_page("task_form.html", ctx, status, None))
+        }
+    }
+}
+
+/// Returns the browser's CSRF token, or a new one together with the
+/// `Set-Cookie` value that stores it.
+fn csrf_token_for(headers: &HeaderMap) -> (String, Option<String>) {
+    match forms::csrf_cookie(headers) {
+        Some(token) => (token, None),
+        None => {
+            let token = forms::new_csrf_token();
+            let cookie = forms::csrf_set_cookie(&token);
+            (token, Some(cookie))
+        }
+    }
+}
+
+fn form_context(
+    heading: &str,
+    task_id: Option<u64>,
+    form: &TaskForm,
+    errors: &BTreeMap<&'static str, String>,
+    csrf_token: &str,
+    request_id: Uuid,
+) -> serde_json::Value {
+    json!({
+        "heading": heading,
+        "task_id": task_id,
+        "form": form,
+        "errors": errors,
+        "csrf_token": csrf_token,
+        "request_id": request_id.to_string(),
+    })
+}
+
+/// Render a template into a `text/html` response.
+fn render_page(
+    name: &str,
+    ctx: serde_json::Value,
+    status: StatusCode,
+    set_cookie: Option<String>,
+) -> Response<Full<Bytes>> {
+    match templates::render(name, ctx) {
+        Ok(html) => {
+            let mut builder = Response::builder()
+                .status(status)
+                .header(CONTENT_TYPE, "text/html; charset=utf-8");
+            if let Some(cookie) = set_cookie {
+                builder = builder.header(SET_COOKIE, cookie);
+            }
+            builder.body(Full::new(Bytes::from(html))).unwrap()
+        }
+        Err(err) => {
+            error!(template = name, error = %err, "Failed to render template");
+            plain_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error")
+        }
+    }
+}
+
+fn plain_response(status: StatusCode, message: &str) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(Full::new(Bytes::from(message.to_string())))
+        .unwrap()
+}
+
+/// Redirect-after-post: 303 See Other makes the browser follow up with a GET.
+fn redirect(location: &str) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(StatusCode::SEE_OTHER)
+        .header(LOCATION, location)
+        .body(Full::new(Bytes::new()))
+        .unwrap()
+}
@@ -0,0 +1,99 @@
+use super::tasks::{respond_with_error, respond_with_payload};
+use crate::formats::Format;
+use crate::responses::{DependencyGraphBody, Envelope, ErrorEnvelope, SubtasksBody};
+use crate::store::Store;
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{Response, StatusCode};
+use std::sync::Arc;
+use tokio::time::Instant;
+use tracing::instrument;
+use uuid::Uuid;
+
+/// Handler for `GET /tasks/{id}/subtasks`.
+///
+/// Lists the task's direct subtasks, by ID.  Returns 404 Not Found if the
+/// task does not exist.
+#[utoipa::path(
+    get,
+    path = "/tasks/{id}/subtasks",
+    operation_id = "listSubtasks",
+    tag = "tasks",
+    summary = "List a task's subtasks",
+    params(("id" = u64, Path, description = "Task ID")),
+    responses(
+        (status = 200, description = "The task's direct subtasks", body = Envelope<SubtasksBody>),
+        (status = 400, description = "The ID is invalid", body = ErrorEnvelope),
+        (status = 404, description = "There is no such task", body = ErrorEnvelope),
+    )
+)]
+#[instrument(skip(store))]
+pub async fn handle_list_subtasks(
+    store: Arc<Store>,
+    task_id_str: &str,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let task_id = match task_id_str.parse::<u64>() {
+        Ok(id) => id,
+        Err(_) => return respond_with_error(format, "Invalid task ID", request_id, start_time, StatusCode::BAD_REQUEST),
+    };
+
+    match store.subtasks(task_id).await {
+        Some(subtasks) => {
+            let body = SubtasksBody {
+                parent_id: task_id,
+                tasks: subtasks,
+            };
+            Ok(respond_with_payload(format, StatusCode::OK, body, request_id, start_time))
+        }
+        None => respond_with_error(format, "Task not found", request_id, start_time, StatusCode::NOT_FOUND),
+    }
+}
+
+/// Handler for `GET /tasks/{id}/dependencies`.
+///
+/// Returns the task's dependency graph: the task, every task it is
+/// transitively blocked by and every task transitively blocked by it, with
+/// one edge per `blocked_by` relation between them.  Returns 404 Not Found
+/// if the task does not exist.
+#[utoipa::path(
+    get,
+    path = "/tasks/{id}/dependencies",
+    operation_id = "getDependencyGraph",
+    tag = "tasks",
+    summary = "Get a task's dependency graph",
+    description = "Returns the tasks the task is transitively blocked by or blocks, with an edge per `blocked_by`.",
+    params(("id" = u64, Path, description = "Task ID")),
+    responses(
+        (status = 200, description = "The dependency graph", body = Envelope<DependencyGraphBody>),
+        (status = 400, description = "The ID is invalid", body = ErrorEnvelope),
+        (status = 404, description = "There is no such task", body = ErrorEnvelope),
+    )
+)]
+#[instrument(skip(store))]
+pub async fn handle_dependency_graph(
+    store: Arc<Store>,
+    task_id_str: &str,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let task_id = match task_id_str.parse::<u64>() {
+        Ok(id) => id,
+        Err(_) => return respond_with_error(format, "Invalid task ID", request_id, start_time, StatusCode::BAD_REQUEST),
+    };
+
+    match store.dependency_graph(task_id).await {
+        Some(graph) => {
+            let body = DependencyGraphBody {
+                task_id,
+                tasks: graph.tasks,
+                edges: graph.edges,
+            };
+            Ok(respond_with_payload(format, StatusCode::OK, body, request_id, start_time))
+        }
+        None => respond_with_error(format, "Task not found", request_id, start_time, StatusCode::NOT_FOUND),
+    }
+}
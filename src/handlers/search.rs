@@ -0,0 +1,89 @@
+use super::tasks::{respond_with_error, respond_with_payload};
+use crate::formats::Format;
+use crate::responses::{Envelope, ErrorEnvelope, Highlights, SearchBody, SearchResult};
+use crate::search::{self, SNIPPET_LEN};
+use crate::store::Store;
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{Response, StatusCode};
+use std::sync::Arc;
+use tokio::time::Instant;
+use tracing::instrument;
+use uuid::Uuid;
+
+/// Results returned when the query does not set `limit`.
+const DEFAULT_LIMIT: usize = 20;
+
+/// Most results a single search can return.
+const MAX_LIMIT: usize = 100;
+
+/// Handler for `GET /tasks/search?q=...`.
+///
+/// Searches task titles and descriptions through the store's full-text
+/// index.  Each result carries the task, its relevance score and HTML
+/// highlights: the whole title and a snippet of the description, escaped,
+/// with matching words wrapped in `<mark>`.  Results are best first;
+/// `limit` caps how many are returned (default 20, at most 100).
+///
+/// A missing or empty `q`, or an invalid `limit`, is a 400 Bad Request.
+#[utoipa::path(
+    get,
+    path = "/tasks/search",
+    operation_id = "searchTasks",
+    tag = "tasks",
+    summary = "Search tasks",
+    description = "Searches titles and descriptions, returning the best matches first with their words highlighted.",
+    params(
+        ("q" = String, Query, description = "The words to search for"),
+        ("limit" = Option<usize>, Query, description = "Most results to return, from 1 to 100; 20 by default"),
+    ),
+    responses(
+        (status = 200, description = "The matching tasks", body = Envelope<SearchBody>),
+        (status = 400, description = "`q` is missing or `limit` is invalid", body = ErrorEnvelope),
+    )
+)]
+#[instrument(skip(store))]
+pub async fn handle_search_tasks(
+    store: Arc<Store>,
+    query: Option<&str>,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let mut q = None;
+    let mut limit = DEFAULT_LIMIT;
+    for (name, value) in form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+        match name.as_ref() {
+            "q" => q = Some(value.into_owned()),
+            "limit" => match value.parse::<usize>() {
+                Ok(value) if (1..=MAX_LIMIT).contains(&value) => limit = value,
+                _ => {
+                    let message = format!("Invalid limit: must be between 1 and {}", MAX_LIMIT);
+                    return respond_with_error(format, &message, request_id, start_time, StatusCode::BAD_REQUEST);
+                }
+            },
+            _ => {}
+        }
+    }
+    let Some(q) = q.filter(|q| !q.trim().is_empty()) else {
+        return respond_with_error(format, "Missing search query q", request_id, start_time, StatusCode::BAD_REQUEST);
+    };
+
+    let terms = search::query_terms(&q);
+    let results: Vec<SearchResult> = store
+        .search_tasks(&terms, limit)
+        .await
+        .into_iter()
+        .map(|(task, score)| SearchResult {
+            score,
+            highlights: Highlights {
+                title: search::highlight(&task.title, &terms, usize::MAX),
+                description: search::highlight(&task.description, &terms, SNIPPET_LEN),
+            },
+            task,
+        })
+        .collect();
+
+    let body = SearchBody { query: q, results };
+    Ok(respond_with_payload(format, StatusCode::OK, body, request_id, start_time))
+}
@@ -0,0 +1,197 @@
+use super::tasks::{
+    precondition_holds, read_body, respond_with_error, respond_with_payload, respond_with_store_error,
+    respond_with_validation_errors, with_etag,
+};
+use crate::conditional::{self, EntityTags};
+use crate::formats::Format;
+use crate::models::{self, AddTags, Task};
+use crate::responses::{Envelope, ErrorEnvelope, TagCount, TagsBody, TaskBody};
+use crate::store::{Store, StoreError};
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::header::HeaderMap;
+use hyper::{body::Incoming, Request, Response, StatusCode};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::time::Instant;
+use tracing::instrument;
+use uuid::Uuid;
+
+/// Handler for adding tags to a task with `POST /tasks/{id}/tags`.
+///
+/// The body is `{"tags": [...]}`; tags are trimmed and lowercased, and tags
+/// the task already has are ignored.  The response contains the updated task
+/// and its new `ETag`.  Invalid tags, or more than `MAX_TAGS` in total,
+/// return 422 Unprocessable Entity.  `If-Match` is honored as in
+/// `handle_update_task`.
+#[utoipa::path(
+    post,
+    path = "/tasks/{id}/tags",
+    operation_id = "addTags",
+    tag = "tasks",
+    summary = "Add tags to a task",
+    description = "Adds the tags, trimmed and lowercased, ignoring any the task already has.",
+    request_body = AddTags,
+    params(
+        ("id" = u64, Path, description = "Task ID"),
+        ("If-Match" = Option<String>, Header, description = "Only change the task if its ETag matches"),
+    ),
+    responses(
+        (status = 200, description = "The tags were added", body = Envelope<TaskBody>),
+        (status = 400, description = "The ID or body is invalid", body = ErrorEnvelope),
+        (status = 404, description = "There is no such task", body = ErrorEnvelope),
+        (status = 409, description = "The task is in the trash", body = ErrorEnvelope),
+        (status = 412, description = "A precondition failed", body = ErrorEnvelope),
+        (status = 415, description = "The body is in an unsupported format", body = ErrorEnvelope),
+        (status = 422, description = "A tag is invalid, or the task would have too many", body = ErrorEnvelope),
+    )
+)]
+#[instrument(skip(req, store))]
+pub async fn handle_add_tags(
+    req: Request<Incoming>,
+    store: Arc<Store>,
+    task_id_str: &str,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let task_id = match task_id_str.parse::<u64>() {
+        Ok(id) => id,
+        Err(_) => return respond_with_error(format, "Invalid task ID", request_id, start_time, StatusCode::BAD_REQUEST),
+    };
+    let if_match = EntityTags::if_match(req.headers());
+    let add = match read_body::<AddTags>(req, format, request_id, start_time).await? {
+        Ok(add) => add,
+        Err(response) => return Ok(response),
+    };
+    if let Some((field, message)) = models::validate_tags(&add.tags) {
+        let errors = BTreeMap::from([(field, message)]);
+        return respond_with_validation_errors(format, &errors, request_id, start_time);
+    }
+
+    let result = store
+        .modify_task(task_id, |task| {
+            if !precondition_holds(&if_match, task) {
+                return Err(StoreError::PreconditionFailed);
+            }
+            let mut task = task.clone();
+            task.tags.extend(add.tags);
+            match models::validate_tags(&task.tags) {
+                Some((_, message)) => Err(StoreError::Invalid(message)),
+                None => Ok(task),
+            }
+        })
+        .await;
+    match result {
+        Ok(task) => respond_with_task(task, "Tags added successfully", format, request_id, start_time),
+        Err(err) => respond_with_store_error(err, if_match.is_some(), format, request_id, start_time),
+    }
+}
+
+/// Handler for removing a tag from a task with `DELETE /tasks/{id}/tags/{tag}`.
+///
+/// Returns the updated task and its new `ETag`, or 404 Not Found if the task
+/// does not have the tag.  `If-Match` is honored as in `handle_update_task`.
+#[utoipa::path(
+    delete,
+    path = "/tasks/{id}/tags/{tag}",
+    operation_id = "removeTag",
+    tag = "tasks",
+    summary = "Remove a tag from a task",
+    params(
+        ("id" = u64, Path, description = "Task ID"),
+        ("tag" = String, Path, description = "The tag, in any case"),
+        ("If-Match" = Option<String>, Header, description = "Only change the task if its ETag matches"),
+    ),
+    responses(
+        (status = 200, description = "The tag was removed", body = Envelope<TaskBody>),
+        (status = 400, description = "The ID is invalid", body = ErrorEnvelope),
+        (status = 404, description = "There is no such task, or it does not have the tag", body = ErrorEnvelope),
+        (status = 409, description = "The task is in the trash", body = ErrorEnvelope),
+        (status = 412, description = "A precondition failed", body = ErrorEnvelope),
+    )
+)]
+#[instrument(skip(headers, store))]
+pub async fn handle_remove_tag(
+    headers: &HeaderMap,
+    store: Arc<Store>,
+    task_id_str: &str,
+    tag: &str,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let task_id = match task_id_str.parse::<u64>() {
+        Ok(id) => id,
+        Err(_) => return respond_with_error(format, "Invalid task ID", request_id, start_time, StatusCode::BAD_REQUEST),
+    };
+    let tag = models::normalize_tag(tag);
+
+    let if_match = EntityTags::if_match(headers);
+    let mut missing_tag = false;
+    let result = store
+        .modify_task(task_id, |task| {
+            if !precondition_holds(&if_match, task) {
+                return Err(StoreError::PreconditionFailed);
+            }
+            let mut task = task.clone();
+            if !task.tags.remove(&tag) {
+                missing_tag = true;
+                return Err(StoreError::NotFound);
+            }
+            Ok(task)
+        })
+        .await;
+    match result {
+        Ok(task) => respond_with_task(task, "Tag removed successfully", format, request_id, start_time),
+        Err(StoreError::NotFound) if missing_tag => {
+            respond_with_error(format, "Tag not found on task", request_id, start_time, StatusCode::NOT_FOUND)
+        }
+        Err(err) => respond_with_store_error(err, if_match.is_some(), format, request_id, start_time),
+    }
+}
+
+/// Handler for `GET /tags`.
+///
+/// Lists every tag in use with the number of tasks carrying it, sorted by
+/// tag.
+#[utoipa::path(
+    get,
+    path = "/tags",
+    operation_id = "listTags",
+    tag = "tasks",
+    summary = "List tags",
+    description = "Lists every tag in use, with how many tasks have it.",
+    responses((status = 200, description = "The tags, sorted", body = Envelope<TagsBody>))
+)]
+#[instrument(skip(store))]
+pub async fn handle_list_tags(
+    store: Arc<Store>,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let tags: Vec<TagCount> = store
+        .tag_counts()
+        .await
+        .into_iter()
+        .map(|(tag, count)| TagCount { tag, count })
+        .collect();
+    Ok(respond_with_payload(format, StatusCode::OK, TagsBody { tags }, request_id, start_time))
+}
+
+/// Respond with a changed task and its `ETag`.
+fn respond_with_task(
+    task: Task,
+    message: &'static str,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let etag = conditional::etag(&task, format);
+    let body = TaskBody {
+        task,
+        message: Some(message),
+    };
+    Ok(with_etag(respond_with_payload(format, StatusCode::OK, body, request_id, start_time), &etag))
+}
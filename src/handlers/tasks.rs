@@ -1,255 +1,832 @@
-use crate::models::{CreateTask, UpdateTask, Task};
-use crate::store::Store;
+use crate::compression;
+use crate::conditional::{self, EntityTags};
+use crate::formats::{self, Format, TASK_FORMATS};
+use crate::idempotency::{self, Begin, IdempotencyCache};
+use crate::models::{BatchOperation, BatchRequest, CreateTask, ReplaceTask, Task, UpdateTask, MAX_BATCH_OPERATIONS};
+use crate::openapi::JsonPatchOperation;
+use crate::responses::{
+    BatchBody, BatchResult, Body, CreatedTaskBody, Envelope, ErrorBody, ErrorEnvelope, MessageBody, Meta, TaskBody,
+    TaskListBody,
+};
+use crate::versioning;
+use crate::patch::{self, TaskPatch};
+use crate::query::ListQuery;
+use crate::store::{Store, StoreError};
+use hyper::header::{HeaderMap, HeaderValue, CONTENT_TYPE, ETAG, LOCATION, VARY};
 use hyper::{Request, Response, StatusCode, body::Incoming};
 use bytes::Bytes;
 use http_body_util::{Full, BodyExt};
-use serde_json::json;
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use tokio::time::Instant;
 use uuid::Uuid;
-use tracing::instrument;
+use tracing::{error, instrument};
 
 /// Handler for creating a new task.
 ///
-/// This function takes in a hyper request, a Store instance, a request ID, and
-/// a start time.  It returns a hyper response with a JSON body containing the
-/// newly created task's ID, as well as a message, request ID, timestamp, and
-/// processing time.
+/// This function takes in a hyper request, a Store instance, the
+/// idempotency cache, the negotiated response format, a request ID, and a
+/// start time.  It returns a 201 Created response whose body holds the full
+/// stored task, its ID and a message, with a `Location` header pointing at
+/// the task and its `ETag`.  Under `/v2` the body is the `{data, meta}`
+/// envelope, with the request ID, timestamp, and processing time in `meta`.
 ///
-/// If the request body is invalid, the function returns a 400 Bad Request
-/// response with an error message.
+/// The request body may be JSON, CBOR or MessagePack, as declared by its
+/// `Content-Type` header, and may be gzip-compressed with
+/// `Content-Encoding: gzip`.  An unsupported `Content-Type` returns a 415
+/// Unsupported Media Type response.  If the request body is invalid, the
+/// function returns a 400 Bad Request response with an error message.
+///
+/// With an `Idempotency-Key` header, the first successful response is kept
+/// for the cache's TTL and replayed, marked `Idempotent-Replayed: true`, to
+/// retries from the same principal with an identical body, so retries never
+/// create duplicates.  Reusing the key with a different body, or for another
+/// API version or response format, returns 422 Unprocessable Entity, and
+/// retrying while the first request is still being handled returns 409
+/// Conflict.
 ///
 /// The function is instrumented with tracing.
+#[utoipa::path(
+    post,
+    path = "/tasks",
+    operation_id = "createTask",
+    tag = "tasks",
+    summary = "Create a task",
+    description = "With an `Idempotency-Key`, retries with the same body replay the first response instead of creating another task.",
+    request_body = CreateTask,
+    params(("Idempotency-Key" = Option<String>, Header, description = "Makes retries of the request safe")),
+    responses(
+        (status = 201, description = "The task was created", body = Envelope<CreatedTaskBody>),
+        (status = 400, description = "The body or Idempotency-Key is invalid", body = ErrorEnvelope),
+        (status = 409, description = "A request with the same Idempotency-Key is in progress", body = ErrorEnvelope),
+        (status = 415, description = "The body is in an unsupported format", body = ErrorEnvelope),
+        (status = 422, description = "The task is invalid", body = ErrorEnvelope),
+    )
+)]
 #[instrument(skip_all)]
 pub async fn handle_create_task(
-    mut req: Request<Incoming>,
+    req: Request<Incoming>,
     store: Arc<Store>,
+    idempotency: &IdempotencyCache,
+    format: Format,
     request_id: Uuid,
     start_time: Instant,
 ) -> Result<Response<Full<Bytes>>, hyper::Error> {
-    let body = req
-        .body_mut()
-        .collect()
-        .await?
-        .to_bytes();
-    let task_data = match serde_json::from_slice::<CreateTask>(&body) {
-        Ok(task) => task,
-        Err(_) => {
-            return respond_with_error(
-                "Invalid request body",
-                request_id,
-                start_time,
-                StatusCode::BAD_REQUEST,
-            );
+    let key = match idempotency::key(req.headers()) {
+        Ok(key) => key,
+        Err(message) => {
+            return respond_with_error(format, message, request_id, start_time, StatusCode::BAD_REQUEST)
         }
     };
+    let principal = idempotency::principal(req.headers());
+    let (body_format, body) = match read_raw_body(req, format, request_id, start_time).await? {
+        Ok(body) => body,
+        Err(response) => return Ok(response),
+    };
+
+    let fingerprint = idempotency::fingerprint(versioning::current(), format, body_format, &body);
+    let reservation = match &key {
+        None => None,
+        Some(key) => match idempotency.begin(&principal, key, fingerprint) {
+            Begin::Proceed(reservation) => Some(reservation),
+            Begin::Replay(response) => return Ok(response),
+            Begin::Mismatch => {
+                return respond_with_error(
+                    format,
+                    "Idempotency-Key was already used with a different request",
+                    request_id,
+                    start_time,
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                )
+            }
+            Begin::InProgress => {
+                return respond_with_error(
+                    format,
+                    "A request with this Idempotency-Key is still being processed",
+                    request_id,
+                    start_time,
+                    StatusCode::CONFLICT,
+                )
+            }
+        },
+    };
+
+    let task_data = match decode_body::<CreateTask>(body_format, &body, format, request_id, start_time)? {
+        Ok(task) => task,
+        Err(response) => return Ok(response),
+    };
+    let errors = task_data.validate();
+    if !errors.is_empty() {
+        return respond_with_validation_errors(format, &errors, request_id, start_time);
+    }
 
-    let new_task_id = store.create_task(task_data).await;
-    let response = json!({
-        "id": new_task_id,
-        "message": "Task created successfully",
-        "request_id": request_id.to_string(),
-        "timestamp": chrono::Utc::now().to_rfc3339(),
-        "processing_time_ms": start_time.elapsed().as_millis(),
-    });
-    let bytes = Bytes::from(response.to_string());
-    Ok(Response::builder()
-        .status(StatusCode::CREATED)
-        .header("content-type", "application/json")
-        .body(Full::new(bytes))
-        .unwrap())
+    let task = match store.create_task(task_data).await {
+        Ok(task) => task,
+        Err(err) => return respond_with_store_error(err, false, format, request_id, start_time),
+    };
+    let new_task_id = task.id;
+    let etag = conditional::etag(&task, format);
+    let created = CreatedTaskBody {
+        id: new_task_id,
+        task,
+        message: "Task created successfully",
+    };
+    let response = respond_with_payload(format, StatusCode::CREATED, created, request_id, start_time);
+    let mut response = with_etag(response, &etag);
+    response
+        .headers_mut()
+        .insert(LOCATION, HeaderValue::from_str(&format!("/tasks/{}", new_task_id)).unwrap());
+    Ok(match reservation {
+        Some(reservation) => reservation.complete(response).await,
+        None => response,
+    })
 }
 
-// Handler for updating a task
+// Handler for replacing a task with `PUT /tasks/{id}`
+//
+// The body is the complete task (`ReplaceTask`) and replaces the stored one
+// outright; fields left out are a 400 Bad Request rather than being kept. If
+// no task has the ID, it is created with that ID and the response is 201
+// Created instead of 200 OK. Either way the body contains the stored task and
+// the response carries its new `ETag`.
+//
+// `If-Match` makes the write conditional on the current `ETag` (and rules out
+// creating), while `If-None-Match: *` only allows creating. A failed
+// condition is 412 Precondition Failed; a body that fails validation is 422
+// Unprocessable Entity.
+#[utoipa::path(
+    put,
+    path = "/tasks/{id}",
+    operation_id = "replaceTask",
+    tag = "tasks",
+    summary = "Replace a task",
+    description = "Replaces the whole task, or creates it with the given ID if there is none.",
+    request_body = ReplaceTask,
+    params(
+        ("id" = u64, Path, description = "Task ID"),
+        ("If-Match" = Option<String>, Header, description = "Only replace the task if its ETag matches"),
+        ("If-None-Match" = Option<String>, Header, description = "`*` to only create the task"),
+    ),
+    responses(
+        (status = 200, description = "The task was replaced", body = Envelope<TaskBody>),
+        (status = 201, description = "The task was created", body = Envelope<TaskBody>),
+        (status = 400, description = "The ID or body is invalid", body = ErrorEnvelope),
+        (status = 409, description = "The task is in the trash, or the change conflicts with related tasks", body = ErrorEnvelope),
+        (status = 412, description = "A precondition failed", body = ErrorEnvelope),
+        (status = 422, description = "The task is invalid", body = ErrorEnvelope),
+    )
+)]
 #[instrument(skip(store, req))]
 pub async fn handle_update_task(
-    mut req: Request<Incoming>,
+    req: Request<Incoming>,
     store: Arc<Store>,
     task_id_str: &str,
+    format: Format,
     request_id: Uuid,
     start_time: Instant,
 ) -> Result<Response<Full<Bytes>>, hyper::Error> {
     let task_id = match task_id_str.parse::<u64>() {
         Ok(id) => id,
-        Err(_) => return respond_with_error("Invalid task ID", request_id, start_time, StatusCode::BAD_REQUEST),
+        Err(_) => return respond_with_error(format, "Invalid task ID", request_id, start_time, StatusCode::BAD_REQUEST),
     };
 
-    let body_bytes = req.body_mut().collect().await?.to_bytes();
-    let update_data: UpdateTask = match serde_json::from_slice(&body_bytes) {
+    let if_match = EntityTags::if_match(req.headers());
+    let if_none_match = EntityTags::if_none_match(req.headers());
+    let replacement = match read_body::<ReplaceTask>(req, format, request_id, start_time).await? {
         Ok(data) => data,
-        Err(_) => return respond_with_error("Invalid request body", request_id, start_time, StatusCode::BAD_REQUEST),
-    };
-
-    match store.update_task(task_id, update_data).await {
-        Some(_) => {
-            let response = json!({
-                "message": "Task updated successfully",
-                "request_id": request_id.to_string(),
-                "timestamp": chrono::Utc::now().to_rfc3339(),
-                "processing_time_ms": start_time.elapsed().as_millis()
-            });
-            Ok(Response::builder()
-                .status(StatusCode::OK)
-                .header("content-type", "application/json")
-                .body(Full::new(Bytes::from(response.to_string())))
-                .unwrap())
+        Err(response) => return Ok(response),
+    };
+
+    if replacement.id.is_some_and(|id| id != task_id) {
+        return respond_with_error(
+            format,
+            "Task id does not match the URL",
+            request_id,
+            start_time,
+            StatusCode::UNPROCESSABLE_ENTITY,
+        );
+    }
+    let errors = replacement.validate();
+    if !errors.is_empty() {
+        return respond_with_validation_errors(format, &errors, request_id, start_time);
+    }
+
+    let result = store
+        .replace_task(task_id, replacement, |current| match current {
+            Some(task) => {
+                precondition_holds(&if_match, task)
+                    && if_none_match
+                        .as_ref()
+                        .is_none_or(|tags| !tags.matches_weak(&conditional::etag(task, format)))
+            }
+            None => if_match.is_none(),
+        })
+        .await;
+    match result {
+        Ok((task, created)) => {
+            let (status, message) = if created {
+                (StatusCode::CREATED, "Task created successfully")
+            } else {
+                (StatusCode::OK, "Task updated successfully")
+            };
+            let etag = conditional::etag(&task, format);
+            let body = TaskBody {
+                task,
+                message: Some(message),
+            };
+            let mut response = with_etag(respond_with_payload(format, status, body, request_id, start_time), &etag);
+            if created {
+                response
+                    .headers_mut()
+                    .insert(LOCATION, HeaderValue::from_str(&format!("/tasks/{}", task_id)).unwrap());
+            }
+            Ok(response)
+        }
+        Err(err) => respond_with_store_error(err, if_match.is_some(), format, request_id, start_time),
+    }
+}
+
+/// Returns true if the task's current version satisfies the request's
+/// `If-Match` header, whichever representation the tag was sent with.
+///
+/// Without the header every task passes.
+pub(super) fn precondition_holds(if_match: &Option<EntityTags>, task: &Task) -> bool {
+    if_match.as_ref().is_none_or(|tags| tags.matches_version(task))
+}
+
+/// Map a conditional store failure to a response.
+///
+/// A missing task is 404 Not Found, except under `If-Match`, where there is
+/// no current representation to match and the answer is 412.
+pub(super) fn respond_with_store_error(
+    err: StoreError,
+    has_if_match: bool,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let status = store_error_status(&err, has_if_match);
+    respond_with_error(format, &err.to_string(), request_id, start_time, status)
+}
+
+/// The response status for a store failure.
+pub(crate) fn store_error_status(err: &StoreError, has_if_match: bool) -> StatusCode {
+    match err {
+        StoreError::NotFound if !has_if_match => StatusCode::NOT_FOUND,
+        StoreError::NotFound | StoreError::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+        StoreError::Conflict(_) => StatusCode::CONFLICT,
+        StoreError::Invalid(_) | StoreError::InvalidField(..) => StatusCode::UNPROCESSABLE_ENTITY,
+        StoreError::Aborted => StatusCode::FAILED_DEPENDENCY,
+    }
+}
+
+/// Attach an `ETag` header to a response.
+pub(super) fn with_etag(mut response: Response<Full<Bytes>>, etag: &str) -> Response<Full<Bytes>> {
+    if let Ok(value) = HeaderValue::from_str(etag) {
+        response.headers_mut().insert(ETAG, value);
+    }
+    response
+}
+
+/// Handler for partially updating a task with `PATCH /tasks/{id}`.
+///
+/// The body is either a JSON Merge Patch (`application/merge-patch+json`) or
+/// a JSON Patch (`application/json-patch+json`).  The patch is applied to the
+/// stored task under the store's write lock, so it sees the latest version
+/// and either applies completely or not at all.  The response contains the
+/// patched task and its new `ETag`.
+///
+/// Other content types return 415 Unsupported Media Type with an
+/// `Accept-Patch` header.  A failed JSON Patch `test` operation returns 409
+/// Conflict, and a patch that leaves the task invalid returns 422
+/// Unprocessable Entity.  `If-Match` is honored as in `handle_update_task`.
+#[utoipa::path(
+    patch,
+    path = "/tasks/{id}",
+    operation_id = "patchTask",
+    tag = "tasks",
+    summary = "Patch a task",
+    description = "Applies a JSON Merge Patch or a JSON Patch to the task atomically.",
+    request_body(content(
+        (UpdateTask = "application/merge-patch+json"),
+        (Vec<JsonPatchOperation> = "application/json-patch+json"),
+    )),
+    params(
+        ("id" = u64, Path, description = "Task ID"),
+        ("If-Match" = Option<String>, Header, description = "Only patch the task if its ETag matches"),
+    ),
+    responses(
+        (status = 200, description = "The task was patched", body = Envelope<TaskBody>),
+        (status = 400, description = "The ID or patch is invalid", body = ErrorEnvelope),
+        (status = 404, description = "There is no such task", body = ErrorEnvelope),
+        (status = 409, description = "A JSON Patch `test` failed, or the change conflicts with related tasks", body = ErrorEnvelope),
+        (status = 412, description = "A precondition failed", body = ErrorEnvelope),
+        (status = 415, description = "The patch is in an unsupported format", body = ErrorEnvelope),
+        (status = 422, description = "The patched task is invalid", body = ErrorEnvelope),
+    )
+)]
+#[instrument(skip(store, req))]
+pub async fn handle_patch_task(
+    req: Request<Incoming>,
+    store: Arc<Store>,
+    task_id_str: &str,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let task_id = match task_id_str.parse::<u64>() {
+        Ok(id) => id,
+        Err(_) => return respond_with_error(format, "Invalid task ID", request_id, start_time, StatusCode::BAD_REQUEST),
+    };
+
+    let (parts, body) = req.into_parts();
+    let body = body.collect().await?.to_bytes();
+    let body = match compression::decode_request_body(&parts.headers, body) {
+        Ok(body) => body,
+        Err(err) => return respond_with_error(format, &err.to_string(), request_id, start_time, err.status()),
+    };
+    let content_type = parts.headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok());
+    let task_patch = match TaskPatch::parse(content_type, &body) {
+        Ok(task_patch) => task_patch,
+        Err(patch::ParseError::UnsupportedType) => {
+            let mut response = respond_with_error(
+                format,
+                "Unsupported patch format",
+                request_id,
+                start_time,
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            )?;
+            response.headers_mut().insert("accept-patch", HeaderValue::from_static(patch::ACCEPT_PATCH));
+            return Ok(response);
+        }
+        Err(patch::ParseError::Malformed(_)) => {
+            return respond_with_error(format, "Invalid patch document", request_id, start_time, StatusCode::BAD_REQUEST)
+        }
+    };
+
+    let if_match = EntityTags::if_match(&parts.headers);
+    let result = store
+        .modify_task(task_id, |task| {
+            if !precondition_holds(&if_match, task) {
+                return Err(StoreError::PreconditionFailed);
+            }
+            task_patch.apply(task)
+        })
+        .await;
+    match result {
+        Ok(task) => {
+            let etag = conditional::etag(&task, format);
+            let body = TaskBody {
+                task,
+                message: Some("Task patched successfully"),
+            };
+            Ok(with_etag(respond_with_payload(format, StatusCode::OK, body, request_id, start_time), &etag))
+        }
+        Err(err) => respond_with_store_error(err, if_match.is_some(), format, request_id, start_time),
+    }
+}
+
+/// Handler for `POST /tasks/batch`.
+///
+/// The body is a `BatchRequest`: a list of create, update and delete
+/// operations, applied in order under a single store lock.  The response
+/// lists one result per operation with its own status code and either the
+/// task (as created, updated or deleted) or an error.
+///
+/// An atomic batch (the default) returns 200 OK if every operation applied.
+/// Otherwise nothing is applied and the response status is that of the
+/// failed operation; the others report 424 Failed Dependency.  A non-atomic
+/// batch returns 200 OK if everything applied and 207 Multi-Status if
+/// anything failed.  More than `MAX_BATCH_OPERATIONS` operations is a 413
+/// Payload Too Large.
+#[utoipa::path(
+    post,
+    path = "/tasks/batch",
+    operation_id = "batchTasks",
+    tag = "tasks",
+    summary = "Apply a batch of changes",
+    description = "Applies the operations in order. An atomic batch (the default) applies all of them or none.",
+    request_body = BatchRequest,
+    responses(
+        (status = 200, description = "Every operation was applied", body = Envelope<BatchBody>),
+        (status = 207, description = "Some operations of a non-atomic batch failed", body = Envelope<BatchBody>),
+        (status = 400, description = "The body is invalid", body = ErrorEnvelope),
+        (status = 413, description = "The batch has too many operations", body = ErrorEnvelope),
+        (status = 404, description = "An atomic batch names a missing task; nothing was applied", body = Envelope<BatchBody>),
+        (status = 409, description = "An atomic batch conflicts with the stored tasks; nothing was applied", body = Envelope<BatchBody>),
+        (status = 412, description = "An atomic batch has a stale version; nothing was applied", body = Envelope<BatchBody>),
+        (status = 422, description = "An atomic batch writes an invalid task; nothing was applied", body = Envelope<BatchBody>),
+    )
+)]
+#[instrument(skip_all)]
+pub async fn handle_batch_tasks(
+    req: Request<Incoming>,
+    store: Arc<Store>,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let batch = match read_body::<BatchRequest>(req, format, request_id, start_time).await? {
+        Ok(batch) => batch,
+        Err(response) => return Ok(response),
+    };
+    if batch.operations.len() > MAX_BATCH_OPERATIONS {
+        let message = format!("A batch can contain at most {} operations", MAX_BATCH_OPERATIONS);
+        return respond_with_error(format, &message, request_id, start_time, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let success_statuses: Vec<StatusCode> = batch
+        .operations
+        .iter()
+        .map(|operation| match operation {
+            BatchOperation::Create { .. } => StatusCode::CREATED,
+            BatchOperation::Update { .. } | BatchOperation::Delete { .. } => StatusCode::OK,
+        })
+        .collect();
+    let atomic = batch.atomic;
+    let outcomes = store.apply_batch(batch.operations, atomic).await;
+
+    let mut status = StatusCode::OK;
+    let results: Vec<BatchResult> = outcomes
+        .into_iter()
+        .zip(success_statuses)
+        .enumerate()
+        .map(|(index, (outcome, success))| match outcome {
+            Ok(task) => BatchResult {
+                index,
+                status: success.as_u16(),
+                task: Some(task),
+                error: None,
+            },
+            Err(err) => {
+                let item_status = store_error_status(&err, false);
+                if !atomic {
+                    status = StatusCode::MULTI_STATUS;
+                } else if err != StoreError::Aborted {
+                    status = item_status;
+                }
+                BatchResult {
+                    index,
+                    status: item_status.as_u16(),
+                    task: None,
+                    error: Some(err.to_string()),
+                }
+            }
+        })
+        .collect();
+
+    let body = BatchBody { atomic, results };
+    Ok(respond_with_payload(format, status, body, request_id, start_time))
+}
+
+/// Collect a request body, undo any gzip `Content-Encoding`, and decode it
+/// according to its `Content-Type`.
+///
+/// The outer error is a failure reading the body from the connection.  The
+/// inner `Err` is a ready-made 4xx response for a body that is in an
+/// unsupported format or encoding, or does not decode.
+pub(super) async fn read_body<T: serde::de::DeserializeOwned>(
+    req: Request<Incoming>,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Result<T, Response<Full<Bytes>>>, hyper::Error> {
+    match read_raw_body(req, format, request_id, start_time).await? {
+        Ok((body_format, body)) => decode_body(body_format, &body, format, request_id, start_time),
+        Err(response) => Ok(Err(response)),
+    }
+}
+
+/// Collect a request body and undo any gzip `Content-Encoding`, returning it
+/// with the format its `Content-Type` declares.
+///
+/// Errors are as for `read_body`.
+async fn read_raw_body(
+    req: Request<Incoming>,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Result<(Format, Bytes), Response<Full<Bytes>>>, hyper::Error> {
+    let Some(body_format) = Format::from_request(req.headers()) else {
+        return respond_with_error(
+            format,
+            "Unsupported content type",
+            request_id,
+            start_time,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        )
+        .map(Err);
+    };
+
+    let (parts, body) = req.into_parts();
+    let body = body.collect().await?.to_bytes();
+    match compression::decode_request_body(&parts.headers, body) {
+        Ok(body) => Ok(Ok((body_format, body))),
+        Err(err) => respond_with_error(format, &err.to_string(), request_id, start_time, err.status()).map(Err),
+    }
+}
+
+/// Decode a collected body according to its format.
+///
+/// Errors are as for `read_body`.
+fn decode_body<T: serde::de::DeserializeOwned>(
+    body_format: Format,
+    body: &[u8],
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Result<T, Response<Full<Bytes>>>, hyper::Error> {
+    match body_format.decode::<T>(body) {
+        Ok(value) => Ok(Ok(value)),
+        Err(_) => respond_with_error(
+            format,
+            "Invalid request body",
+            request_id,
+            start_time,
+            StatusCode::BAD_REQUEST,
+        )
+        .map(Err),
+    }
+}
+
+/// Encode `body` in the negotiated format and wrap it in a response.
+///
+/// If the body cannot be encoded, a 500 Internal Server Error response is
+/// returned instead.
+pub(super) fn respond<T: Serialize>(format: Format, status: StatusCode, body: &T) -> Response<Full<Bytes>> {
+    match format.encode(body) {
+        Ok(bytes) => Response::builder()
+            .status(status)
+            .header("content-type", format.content_type())
+            .body(Full::new(bytes))
+            .unwrap(),
+        Err(err) => {
+            error!(?format, error = %err, "Failed to encode response");
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Full::new(Bytes::from("Internal Server Error")))
+                .unwrap()
         }
-        None => respond_with_error("Task not found", request_id, start_time, StatusCode::NOT_FOUND),
     }
 }
 
+/// Respond with an endpoint's payload and the request's metadata, shaped
+/// for the API version of the request: side by side in v1, and as the
+/// `data` and `meta` of an envelope from v2.
+pub(super) fn respond_with_payload<T: Serialize>(
+    format: Format,
+    status: StatusCode,
+    payload: T,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Response<Full<Bytes>> {
+    let body = Body::success(versioning::current(), payload, Meta::new(request_id, start_time));
+    respond(format, status, &body)
+}
+
+/// Respond with a failure and the request's metadata, shaped for the API
+/// version of the request as for `respond_with_payload`.
+pub(super) fn respond_with_failure(
+    format: Format,
+    status: StatusCode,
+    error: ErrorBody,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Response<Full<Bytes>> {
+    // Error bodies are never CSV; fall back to JSON for the CSV export.
+    let format = if format == Format::Csv { Format::Json } else { format };
+    let body = Body::failure(versioning::current(), error, Meta::new(request_id, start_time));
+    respond(format, status, &body)
+}
+
 /// Return a response with an error message and the given status code.
 ///
 /// The response will also contain the request ID and a timestamp.
-fn respond_with_error(
+pub(super) fn respond_with_error(
+    format: Format,
     error: &str,
     request_id: Uuid,
     start_time: Instant,
     status: StatusCode,
 ) -> Result<Response<Full<Bytes>>, hyper::Error> {
-    let response = json!({
-        "error": error,
-        "request_id": request_id.to_string(),
-        "timestamp": chrono::Utc::now().to_rfc3339(),
-        "processing_time_ms": start_time.elapsed().as_millis(),
-    });
-    let body = Bytes::from(response.to_string());
+    Ok(respond_with_failure(format, status, ErrorBody::new(error), request_id, start_time))
+}
+
+/// Return a 422 Unprocessable Entity response listing the invalid fields.
+pub(super) fn respond_with_validation_errors(
+    format: Format,
+    errors: &BTreeMap<&'static str, String>,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let error = ErrorBody {
+        fields: Some(errors.iter().map(|(field, message)| (field.to_string(), message.clone())).collect()),
+        ..ErrorBody::new("Validation failed")
+    };
+    Ok(respond_with_failure(format, StatusCode::UNPROCESSABLE_ENTITY, error, request_id, start_time))
+}
+
+/// Handler for requests whose `Accept` header matches no supported format.
+///
+/// Returns a 406 Not Acceptable response in JSON listing the media types the
+/// endpoint can produce.
+pub async fn handle_not_acceptable(
+    supported: &[Format],
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let error = ErrorBody {
+        supported: Some(supported.iter().map(|f| f.content_type().to_string()).collect()),
+        ..ErrorBody::new("Not acceptable")
+    };
+    Ok(respond_with_failure(Format::Json, StatusCode::NOT_ACCEPTABLE, error, request_id, start_time))
+}
 
-    Ok(Response::builder()
-        .status(status)
-        .header("content-type", "application/json")
-        .body(Full::new(body))
-        .unwrap())
+/// Handler for requests whose `/v1` or `/v2` path prefix selects an API
+/// version that their `Accept` header rules out.
+///
+/// Returns a 406 Not Acceptable response in JSON listing the media types of
+/// the version the path selects.
+pub fn handle_version_not_acceptable(message: &str, request_id: Uuid, start_time: Instant) -> Response<Full<Bytes>> {
+    let error = ErrorBody {
+        supported: Some(TASK_FORMATS.iter().map(|f| f.content_type().to_string()).collect()),
+        ..ErrorBody::new(message)
+    };
+    let mut response = respond_with_failure(Format::Json, StatusCode::NOT_ACCEPTABLE, error, request_id, start_time);
+    response.headers_mut().append(VARY, HeaderValue::from_static("accept"));
+    response
 }
 
 // Handler for deleting a task
+//
+// The task is moved to the trash rather than removed; `POST
+// /tasks/{id}/restore` brings it back until it is purged. Honors `If-Match`
+// the same way as `handle_update_task`.
+#[utoipa::path(
+    delete,
+    path = "/tasks/{id}",
+    operation_id = "deleteTask",
+    tag = "tasks",
+    summary = "Delete a task",
+    description = "Moves the task to the trash, from which it can be restored until it is purged.",
+    params(
+        ("id" = u64, Path, description = "Task ID"),
+        ("If-Match" = Option<String>, Header, description = "Only delete the task if its ETag matches"),
+    ),
+    responses(
+        (status = 200, description = "The task was moved to the trash", body = Envelope<MessageBody>),
+        (status = 400, description = "The ID is invalid", body = ErrorEnvelope),
+        (status = 404, description = "There is no such task", body = ErrorEnvelope),
+        (status = 409, description = "The task has subtasks or blocks other tasks", body = ErrorEnvelope),
+        (status = 412, description = "A precondition failed", body = ErrorEnvelope),
+    )
+)]
+#[instrument(skip(headers, store))]
 pub async fn handle_delete_task(
+    headers: &HeaderMap,
     store: Arc<Store>,
     task_id_str: &str,
+    format: Format,
     request_id: Uuid,
     start_time: Instant,
 ) -> Result<Response<Full<Bytes>>, hyper::Error> {
     let task_id = match task_id_str.parse::<u64>() {
         Ok(id) => id,
-        Err(_) => {
-            let error_response = json!({
-                "error": "Invalid task ID",
-                "request_id": request_id.to_string(),
-                "timestamp": chrono::Utc::now().to_rfc3339(),
-                "processing_time_ms": start_time.elapsed().as_millis()
-            });
-            let error_bytes = Bytes::from(error_response.to_string());
-            return Ok(Response::builder()
-                .status(StatusCode::BAD_REQUEST)
-                .header("content-type", "application/json")
-                .body(Full::new(error_bytes))
-                .unwrap());
-        }
+        Err(_) => return respond_with_error(format, "Invalid task ID", request_id, start_time, StatusCode::BAD_REQUEST),
     };
 
-    match store.delete_task(task_id).await {
-        Some(_) => {
-            let success_response = json!({
-                "message": "Task deleted successfully",
-                "request_id": request_id.to_string(),
-                "timestamp": chrono::Utc::now().to_rfc3339(),
-                "processing_time_ms": start_time.elapsed().as_millis()
-            });
-            let success_bytes = Bytes::from(success_response.to_string());
-            Ok(Response::builder()
-                .status(StatusCode::OK)
-                .header("content-type", "application/json")
-                .body(Full::new(success_bytes))
-                .unwrap())
+    let if_match = EntityTags::if_match(headers);
+    let result = match &if_match {
+        Some(tags) => {
+            store
+                .delete_task_if(task_id, |task| tags.matches_version(task))
+                .await
         }
-        None => {
-            let error_response = json!({
-                "error": "Task not found",
-                "request_id": request_id.to_string(),
-                "timestamp": chrono::Utc::now().to_rfc3339(),
-                "processing_time_ms": start_time.elapsed().as_millis()
-            });
-            let error_bytes = Bytes::from(error_response.to_string());
-            Ok(Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .header("content-type", "application/json")
-                .body(Full::new(error_bytes))
-                .unwrap())
+        None => store.delete_task(task_id).await,
+    };
+    match result {
+        Ok(_) => {
+            let body = MessageBody {
+                message: "Task deleted successfully",
+            };
+            Ok(respond_with_payload(format, StatusCode::OK, body, request_id, start_time))
         }
+        Err(err) => respond_with_store_error(err, if_match.is_some(), format, request_id, start_time),
     }
 }
 
 // Handler for listing all tasks
-pub async fn handle_list_tasks(store: Arc<Store>, request_id: Uuid) -> Result<Response<Full<Bytes>>, hyper::Error> {
+//
+// The query string can filter and sort the list, as described on
+// `ListQuery`; an invalid value is a 400 Bad Request. Without one, tasks are
+// listed by ID.
+//
+// With `Format::Csv` the body is a plain CSV export of the tasks, without the
+// request metadata the other formats carry.
+#[utoipa::path(
+    get,
+    path = "/tasks",
+    operation_id = "listTasks",
+    tag = "tasks",
+    summary = "List tasks",
+    description = "Lists the tasks, filtered and sorted by the query parameters.",
+    params(
+        ("tag" = Option<Vec<String>>, Query, description = "Only tasks with any of these tags"),
+        ("tag_match" = Option<String>, Query, description = "`all` to only list tasks with every tag"),
+        ("overdue" = Option<bool>, Query, description = "Only open tasks past their due date"),
+        ("include_deleted" = Option<bool>, Query, description = "Also list the tasks in the trash"),
+        ("sort" = Option<String>, Query, description = "`id`, `due_at` or `priority`, with a leading `-` to reverse"),
+    ),
+    responses(
+        (status = 200, description = "The matching tasks", content(
+            (Envelope<TaskListBody> = "application/json"),
+            (String = "text/csv"),
+        )),
+        (status = 400, description = "A query parameter is invalid", body = ErrorEnvelope),
+    )
+)]
+pub async fn handle_list_tasks(
+    store: Arc<Store>,
+    query: Option<&str>,
+    format: Format,
+    request_id: Uuid,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
     let start_time = Instant::now();
-    let tasks = store.list_tasks().await;
+    let list_query = match ListQuery::parse(query) {
+        Ok(list_query) => list_query,
+        Err(message) => {
+            return respond_with_error(format, &message, request_id, start_time, StatusCode::BAD_REQUEST)
+        }
+    };
+    let tasks = store.query_tasks(&list_query, chrono::Utc::now()).await;
 
-    let response = json!({
-        "tasks": tasks,
-        "request_id": request_id.to_string(),
-        "timestamp": chrono::Utc::now().to_rfc3339(),
-        "processing_time_ms": start_time.elapsed().as_millis()
-    });
+    if format == Format::Csv {
+        return match formats::encode_tasks_csv(&tasks) {
+            Ok(csv) => Ok(Response::builder()
+                .status(StatusCode::OK)
+                .header("content-type", format.content_type())
+                .body(Full::new(csv))
+                .unwrap()),
+            Err(err) => {
+                error!(error = %err, "Failed to encode tasks as CSV");
+                respond_with_error(format, "Failed to export tasks", request_id, start_time, StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        };
+    }
 
-    Ok(Response::builder()
-        .status(StatusCode::OK)
-        .header("content-type", "application/json")
-        .body(Full::new(Bytes::from(response.to_string())))
-        .unwrap())
+    Ok(respond_with_payload(format, StatusCode::OK, TaskListBody { tasks }, request_id, start_time))
 }
 
+// Handler for fetching a single task
+//
+// The response carries the task's `ETag`. If it matches the request's
+// `If-None-Match`, the response is 304 Not Modified with no body.
+#[utoipa::path(
+    get,
+    path = "/tasks/{id}",
+    operation_id = "getTask",
+    tag = "tasks",
+    summary = "Get a task",
+    description = "Returns the task with its `ETag`.",
+    params(
+        ("id" = u64, Path, description = "Task ID"),
+        ("If-None-Match" = Option<String>, Header, description = "Answer 304 if the task's ETag matches"),
+    ),
+    responses(
+        (status = 200, description = "The task", body = Envelope<TaskBody>),
+        (status = 304, description = "The task has not changed"),
+        (status = 400, description = "The ID is invalid", body = ErrorEnvelope),
+        (status = 404, description = "There is no such task", body = ErrorEnvelope),
+    )
+)]
 pub async fn handle_get_task(
+    headers: &HeaderMap,
     store: Arc<Store>,
     task_id_str: &str,
+    format: Format,
     request_id: Uuid,
     start_time: Instant,
 ) -> Result<Response<Full<Bytes>>, hyper::Error> {
     let task_id = match task_id_str.parse::<u64>() {
         Ok(id) => id,
-        Err(_) => {
-            let error_response = json!({
-                "error": "Invalid task ID",
-                "request_id": request_id.to_string(),
-                "timestamp": chrono::Utc::now().to_rfc3339(),
-                "processing_time_ms": start_time.elapsed().as_millis()
-            });
-            let error_bytes = Bytes::from(error_response.to_string());
-            return Ok(Response::builder()
-                .status(StatusCode::BAD_REQUEST)
-                .header("content-type", "application/json")
-                .body(Full::new(error_bytes))
-                .unwrap());
-        }
+        Err(_) => return respond_with_error(format, "Invalid task ID", request_id, start_time, StatusCode::BAD_REQUEST),
     };
 
     match store.get_task(task_id).await {
         Some(task) => {
-            let success_response = json!({
-                "task": task,
-                "request_id": request_id.to_string(),
-                "timestamp": chrono::Utc::now().to_rfc3339(),
-                "processing_time_ms": start_time.elapsed().as_millis()
-            });
-            let success_bytes = Bytes::from(success_response.to_string());
-            Ok(Response::builder()
-                .status(StatusCode::OK)
-                .header("content-type", "application/json")
-                .body(Full::new(success_bytes))
-                .unwrap())
-        }
-        None => {
-            let error_response = json!({
-                "error": "Task not found",
-                "request_id": request_id.to_string(),
-                "timestamp": chrono::Utc::now().to_rfc3339(),
-                "processing_time_ms": start_time.elapsed().as_millis()
-            });
-            let error_bytes = Bytes::from(error_response.to_string());
-            Ok(Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .header("content-type", "application/json")
-                .body(Full::new(error_bytes))
-                .unwrap())
+            let etag = conditional::etag(&task, format);
+            if EntityTags::if_none_match(headers).is_some_and(|tags| tags.matches_weak(&etag)) {
+                let not_modified = Response::builder()
+                    .status(StatusCode::NOT_MODIFIED)
+                    .body(Full::new(Bytes::new()))
+                    .unwrap();
+                return Ok(with_etag(not_modified, &etag));
+            }
+
+            let body = TaskBody { task, message: None };
+            Ok(with_etag(respond_with_payload(format, StatusCode::OK, body, request_id, start_time), &etag))
         }
+        None => respond_with_error(format, "Task not found", request_id, start_time, StatusCode::NOT_FOUND),
     }
-}
\ No newline at end of file
+}
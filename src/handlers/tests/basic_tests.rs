@@ -1,5 +1,5 @@
 use crate::handlers::*;
-use hyper::{body::Incoming, Request, Response, StatusCode};
+use hyper::{Response, StatusCode};
 use serde_json::Value;
 use tokio::time::Instant;
 use uuid::Uuid;
@@ -0,0 +1,105 @@
+use crate::conditional;
+use crate::formats::Format;
+use crate::handlers::{handle_delete_task, handle_get_task};
+use crate::models::CreateTask;
+use crate::store::Store;
+use hyper::header::{HeaderMap, HeaderValue, ETAG, IF_MATCH, IF_NONE_MATCH};
+use hyper::StatusCode;
+use std::sync::Arc;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+async fn store_with_task() -> (Arc<Store>, String) {
+    let store = Arc::new(Store::new());
+    let id = store
+        .create_task(CreateTask {
+            title: "Test Task".to_string(),
+            description: "Test Description".to_string(),
+            due_at: None,
+            priority: None,
+            tags: Default::default(),
+            parent_id: None,
+            blocked_by: Default::default(),
+        })
+        .await.unwrap().id;
+    (store, id.to_string())
+}
+
+fn header(name: hyper::header::HeaderName, value: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(name, HeaderValue::from_str(value).unwrap());
+    headers
+}
+
+/// Test that GET returns a strong ETag and honors If-None-Match.
+///
+/// Verifies that:
+///
+/// * The first response carries the task's ETag.
+/// * Repeating the request with that ETag returns 304 Not Modified and no body.
+/// * A different ETag, or the same one for another format, returns the full
+///   200 response.
+#[tokio::test]
+async fn test_get_task_etag() {
+    let (store, id) = store_with_task().await;
+    let task = store.get_task(id.parse().unwrap()).await.unwrap();
+    let etag = format!("\"{}-{}-1-v1-json\"", task.id, task.created_at.timestamp_micros());
+
+    let response = handle_get_task(&HeaderMap::new(), store.clone(), &id, Format::Json, Uuid::new_v4(), Instant::now())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[ETAG], etag.as_str());
+
+    let headers = header(IF_NONE_MATCH, &etag);
+    let response = handle_get_task(&headers, store.clone(), &id, Format::Json, Uuid::new_v4(), Instant::now())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[ETAG], etag.as_str());
+    assert!(response.headers().get("content-type").is_none());
+
+    let response = handle_get_task(&headers, store.clone(), &id, Format::Cbor, Uuid::new_v4(), Instant::now())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[ETAG], conditional::etag(&task, Format::Cbor).as_str());
+
+    let headers = header(IF_NONE_MATCH, "\"0\"");
+    let response = handle_get_task(&headers, store, &id, Format::Json, Uuid::new_v4(), Instant::now())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+/// Test that DELETE honors If-Match.
+///
+/// Verifies that:
+///
+/// * A stale ETag returns 412 Precondition Failed and keeps the task.
+/// * The current ETag deletes the task.
+/// * If-Match on a missing task returns 412 rather than 404.
+#[tokio::test]
+async fn test_delete_task_if_match() {
+    let (store, id) = store_with_task().await;
+
+    let stale = header(IF_MATCH, "\"7\"");
+    let response = handle_delete_task(&stale, store.clone(), &id, Format::Json, Uuid::new_v4(), Instant::now())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    assert!(store.get_task(id.parse().unwrap()).await.is_some());
+
+    let task = store.get_task(id.parse().unwrap()).await.unwrap();
+    let current = header(IF_MATCH, &conditional::etag(&task, Format::Json));
+    let response = handle_delete_task(&current, store.clone(), &id, Format::Json, Uuid::new_v4(), Instant::now())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let any = header(IF_MATCH, "*");
+    let response = handle_delete_task(&any, store, &id, Format::Json, Uuid::new_v4(), Instant::now())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+}
@@ -0,0 +1,116 @@
+use crate::formats::{Format, TASK_FORMATS};
+use crate::handlers::{handle_get_task, handle_list_tasks, handle_not_acceptable};
+use crate::models::CreateTask;
+use crate::store::Store;
+use crate::testing::TestServer;
+use http_body_util::BodyExt;
+use hyper::header::HeaderValue;
+use hyper::{HeaderMap, StatusCode};
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+async fn store_with_task() -> (Arc<Store>, u64) {
+    let store = Arc::new(Store::new());
+    let id = store
+        .create_task(CreateTask {
+            title: "Test Task".to_string(),
+            description: "Test Description".to_string(),
+            due_at: None,
+            priority: None,
+            tags: Default::default(),
+            parent_id: None,
+            blocked_by: Default::default(),
+        })
+        .await.unwrap().id;
+    (store, id)
+}
+
+/// Test that a task can be fetched as CBOR and decodes to the same fields.
+#[tokio::test]
+async fn test_get_task_as_cbor() {
+    let (store, id) = store_with_task().await;
+
+    let response = handle_get_task(&HeaderMap::new(), store, &id.to_string(), Format::Cbor, Uuid::new_v4(), Instant::now())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/cbor");
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body: Value = Format::Cbor.decode(&body).unwrap();
+    assert_eq!(body["task"]["title"], "Test Task");
+}
+
+/// Test that errors are encoded in the negotiated format too.
+#[tokio::test]
+async fn test_get_missing_task_as_msgpack() {
+    let store = Arc::new(Store::new());
+
+    let response = handle_get_task(&HeaderMap::new(), store, "42", Format::MessagePack, Uuid::new_v4(), Instant::now())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(response.headers()["content-type"], "application/msgpack");
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body: Value = Format::MessagePack.decode(&body).unwrap();
+    assert_eq!(body["error"], "Task not found");
+}
+
+#[tokio::test]
+async fn test_list_tasks_as_csv() {
+    let (store, id) = store_with_task().await;
+
+    let response = handle_list_tasks(store, None, Format::Csv, Uuid::new_v4()).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/csv; charset=utf-8");
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let mut lines = std::str::from_utf8(&body).unwrap().lines();
+    assert_eq!(
+        lines.next().unwrap(),
+        "id,title,description,completed,priority,due_at,created_at,updated_at,completed_at,\
+         tags,parent_id,blocked_by,deleted_at"
+    );
+    assert!(lines
+        .next()
+        .unwrap()
+        .starts_with(&format!("{},Test Task,Test Description,false,,,", id)));
+    assert!(lines.next().is_none());
+}
+
+#[tokio::test]
+async fn test_handle_not_acceptable() {
+    let response = handle_not_acceptable(TASK_FORMATS, Uuid::new_v4(), Instant::now()).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+    assert_eq!(response.headers()["content-type"], "application/json");
+
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body["supported"][1], "application/cbor");
+}
+
+/// Test that every response whose body depends on Accept says so, whether the
+/// path names the version or not.
+#[tokio::test]
+async fn test_accept_dependent_responses_vary_with_accept() {
+    let server = TestServer::start().await;
+    for path in ["/tasks", "/v1/tasks", "/v2/tasks", "/"] {
+        let response = server.get(path).header("Accept", "text/html").send().await;
+        let vary: Vec<_> = response.headers.get_all("vary").iter().collect();
+        assert!(vary.contains(&&HeaderValue::from_static("accept")), "{}: {:?}", path, vary);
+    }
+
+    let response = server.get("/v1/tasks").header("Accept", "application/vnd.rws.v2+json").send().await;
+    assert_eq!(response.status, StatusCode::NOT_ACCEPTABLE);
+    assert_eq!(response.header("vary"), "accept");
+
+    let response = server.get("/health").send().await;
+    assert!(response.headers.get_all("vary").iter().all(|vary| vary != "accept"));
+}
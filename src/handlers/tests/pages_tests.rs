@@ -0,0 +1,276 @@
+use crate::handlers::{handle_edit_task_page, handle_index_page, handle_new_task_page};
+use crate::models::CreateTask;
+use crate::store::Store;
+use crate::testing::{TestResponse, TestServer};
+use crate::utils::prefers_html;
+use bytes::Bytes;
+use flate2::write::GzEncoder;
+use http_body_util::{BodyExt, Full};
+use hyper::header::{ACCEPT, COOKIE, SET_COOKIE};
+use hyper::{HeaderMap, Response, StatusCode};
+use std::io::Write;
+use std::sync::Arc;
+use tokio::time::Instant;
+use uuid::Uuid;
+
+/// Collect the body of a hyper::Response into a String.
+///
+/// # Panics
+/// If the body is not valid UTF-8, the function will panic.
+async fn get_body_text(response: Response<Full<Bytes>>) -> String {
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+fn accept(value: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT, value.parse().unwrap());
+    headers
+}
+
+/// Test that the index page renders every task inside the base layout.
+///
+/// Verifies that:
+///
+/// * The response status is 200 OK with an HTML content type.
+/// * The layout from `base.html` wraps the page content.
+/// * Task titles are rendered and HTML in them is escaped.
+#[tokio::test]
+async fn test_handle_index_page() {
+    let store = Arc::new(Store::new());
+    store
+        .create_task(CreateTask {
+            title: "Write docs".to_string(),
+            description: "For the task API".to_string(),
+            due_at: None,
+            priority: None,
+            tags: Default::default(),
+            parent_id: None,
+            blocked_by: Default::default(),
+        })
+        .await.unwrap();
+    store
+        .create_task(CreateTask {
+            title: "<script>alert(1)</script>".to_string(),
+            description: "Escaped".to_string(),
+            due_at: None,
+            priority: None,
+            tags: Default::default(),
+            parent_id: None,
+            blocked_by: Default::default(),
+        })
+        .await.unwrap();
+
+    let request_id = Uuid::new_v4();
+    let response = handle_index_page(store, request_id, Instant::now()).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "text/html; charset=utf-8");
+
+    let body = get_body_text(response).await;
+
+    assert!(body.contains("<title>Tasks - Rust Web Server</title>"));
+    assert!(body.contains(&request_id.to_string()));
+    assert!(body.contains("Write docs"));
+    assert!(body.contains("&lt;script&gt;"));
+    assert!(!body.contains("<script>"));
+}
+
+#[tokio::test]
+async fn test_handle_index_page_empty() {
+    let store = Arc::new(Store::new());
+    let response = handle_index_page(store, Uuid::new_v4(), Instant::now()).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(get_body_text(response).await.contains("No tasks yet."));
+}
+
+/// Test that the new task form issues a CSRF cookie and embeds the same token.
+#[tokio::test]
+async fn test_handle_new_task_page() {
+    let response = handle_new_task_page(&HeaderMap::new(), Uuid::new_v4()).await.unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let cookie = response.headers()[SET_COOKIE].to_str().unwrap().to_string();
+    let token = cookie
+        .strip_prefix("csrf_token=")
+        .and_then(|rest| rest.split(';').next())
+        .unwrap()
+        .to_string();
+    assert!(cookie.contains("SameSite=Strict"));
+
+    let body = get_body_text(response).await;
+    assert!(body.contains(r#"action="/tasks""#));
+    assert!(body.contains(&format!(r#"name="csrf_token" value="{}""#, token)));
+}
+
+/// Test that the edit form is pre-filled and reuses an existing CSRF cookie.
+#[tokio::test]
+async fn test_handle_edit_task_page() {
+    let store = Arc::new(Store::new());
+    let id = store
+        .create_task(CreateTask {
+            title: "Existing".to_string(),
+            description: "Already stored".to_string(),
+            due_at: None,
+            priority: None,
+            tags: Default::default(),
+            parent_id: None,
+            blocked_by: Default::default(),
+        })
+        .await.unwrap().id;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(COOKIE, "csrf_token=known".parse().unwrap());
+    let response = handle_edit_task_page(&headers, store.clone(), &id.to_string(), Uuid::new_v4())
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers().get(SET_COOKIE).is_none());
+
+    let body = get_body_text(response).await;
+    assert!(body.contains(&format!(r#"action="/tasks/{}/edit""#, id)));
+    assert!(body.contains(r#"value="Existing""#));
+    assert!(body.contains(r#"value="known""#));
+
+    let response = handle_edit_task_page(&headers, store, "999", Uuid::new_v4()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+/// Submit a form to `path` as a browser holding the CSRF cookie `cookie`.
+async fn submit(server: &TestServer, path: &str, cookie: &str, fields: &[(&str, &str)]) -> TestResponse {
+    let body = form_urlencoded::Serializer::new(String::new()).extend_pairs(fields).finish();
+    server
+        .post(path)
+        .header("cookie", &format!("csrf_token={}", cookie))
+        .body("application/x-www-form-urlencoded", body)
+        .send()
+        .await
+}
+
+/// Test that submitting the new task form creates the task and redirects,
+/// and that forged or invalid submissions create nothing.
+#[tokio::test]
+async fn test_handle_create_task_form() {
+    let server = TestServer::start().await;
+
+    let response = submit(&server, "/tasks", "token", &[("csrf_token", "forged"), ("title", "Forged")]).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    let response = server
+        .post("/tasks")
+        .body("application/x-www-form-urlencoded", "csrf_token=token&title=Uncookied")
+        .send()
+        .await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+    let fields = [("csrf_token", "token"), ("title", " "), ("description", "Kept as <typed>")];
+    let response = submit(&server, "/tasks", "token", &fields).await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response.header("content-type"), "text/html; charset=utf-8");
+    assert!(response.text().contains("Title is required"));
+    assert!(response.text().contains("Kept as &lt;typed&gt;"));
+    assert!(response.text().contains(r#"name="csrf_token" value="token""#));
+    assert!(server.store().list_tasks().await.is_empty());
+
+    let fields = [("csrf_token", "token"), ("title", "From a form"), ("completed", "on")];
+    let response = submit(&server, "/tasks", "token", &fields).await;
+    assert_eq!(response.status, StatusCode::SEE_OTHER);
+    assert_eq!(response.header("location"), "/");
+    let tasks = server.store().list_tasks().await;
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].title, "From a form");
+}
+
+/// Test that a compressed form submission is decoded before it is read.
+#[tokio::test]
+async fn test_handle_create_task_form_gzip() {
+    let server = TestServer::start().await;
+    let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(b"csrf_token=token&title=Compressed").unwrap();
+    let body = encoder.finish().unwrap();
+    let send = |encoding: &str, body: Vec<u8>| {
+        server
+            .post("/tasks")
+            .header("cookie", "csrf_token=token")
+            .header("content-encoding", encoding)
+            .body("application/x-www-form-urlencoded", body)
+            .send()
+    };
+
+    let response = send("br", body.clone()).await;
+    assert_eq!(response.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert!(server.store().list_tasks().await.is_empty());
+
+    let response = send("gzip", body).await;
+    assert_eq!(response.status, StatusCode::SEE_OTHER);
+    assert_eq!(server.store().list_tasks().await[0].title, "Compressed");
+}
+
+/// Test that submitting the edit form replaces the task and redirects, and
+/// that a missing task is not found even when the submission is invalid.
+#[tokio::test]
+async fn test_handle_edit_task_form() {
+    let server = TestServer::start().await;
+    let id = server
+        .store()
+        .create_task(CreateTask {
+            title: "Existing".to_string(),
+            description: String::new(),
+            due_at: None,
+            priority: None,
+            tags: Default::default(),
+            parent_id: None,
+            blocked_by: Default::default(),
+        })
+        .await
+        .unwrap()
+        .id;
+    let path = format!("/tasks/{}/edit", id);
+
+    let response = submit(&server, &path, "token", &[("csrf_token", "forged"), ("title", "Forged")]).await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+    let response = submit(&server, &path, "token", &[("csrf_token", "token"), ("title", "")]).await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert!(response.text().contains("Title is required"));
+
+    let response = submit(&server, "/tasks/999/edit", "token", &[("csrf_token", "token"), ("title", "")]).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+    let fields = [("csrf_token", "token"), ("title", "Edited"), ("completed", "on")];
+    let response = submit(&server, &path, "token", &fields).await;
+    assert_eq!(response.status, StatusCode::SEE_OTHER);
+    assert_eq!(response.header("location"), "/");
+    let task = server.store().get_task(id).await.unwrap();
+    assert_eq!((task.title.as_str(), task.completed), ("Edited", true));
+
+    // Completing a task blocked by an open one is a conflict, not bad input
+    let task = |title: &str, blocked_by: &[u64]| CreateTask {
+        title: title.to_string(),
+        description: String::new(),
+        due_at: None,
+        priority: None,
+        tags: Default::default(),
+        parent_id: None,
+        blocked_by: blocked_by.iter().copied().collect(),
+    };
+    let blocker = server.store().create_task(task("Blocker", &[])).await.unwrap().id;
+    let blocked = server.store().create_task(task("Blocked", &[blocker])).await.unwrap().id;
+    let fields = [("csrf_token", "token"), ("title", "Blocked"), ("completed", "on")];
+    let response = submit(&server, &format!("/tasks/{}/edit", blocked), "token", &fields).await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+    assert!(response.text().contains("cannot be completed while blocked"));
+    assert!(!server.store().get_task(blocked).await.unwrap().completed);
+}
+
+#[test]
+fn test_prefers_html() {
+    assert!(prefers_html(&accept("text/html,application/xhtml+xml,*/*;q=0.8")));
+    assert!(prefers_html(&accept("application/json;q=0.5, text/html")));
+    assert!(!prefers_html(&HeaderMap::new()));
+    assert!(!prefers_html(&accept("*/*")));
+    assert!(!prefers_html(&accept("application/json")));
+    assert!(!prefers_html(&accept("text/html, application/json")));
+    assert!(!prefers_html(&accept("text/html;q=0")));
+}
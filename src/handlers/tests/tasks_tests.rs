@@ -1,66 +1,22 @@
-use crate::handlers::{handle_create_task, handle_update_task, handle_delete_task, handle_list_tasks};
-use crate::handlers::handle_get_task;
-use crate::store::Store;
-use crate::models::{CreateTask, UpdateTask};
-use hyper::{Request, Response, StatusCode};
-
-use serde_json::Value;
-use tokio::time::Instant;
-use uuid::Uuid;
-use std::sync::Arc;
-use bytes::Bytes;
-use http_body_util::{Full, BodyExt};
-/**
-Create a hyper::Request from the given method, uri, and body. The body is serialized to json
-before being added to the request. The request is given a content-type header of application/json.
-
-# Panics
-If the body cannot be serialized to json, the function will panic.
-
-# Returns
-The created request.
-**/
-fn create_json_request<T: serde::Serialize>(method: hyper::Method, uri: &str, body: &T) -> Request<hyper::body::Body> {
-    let to_string = serde_json::to_string(body)?;
-    let body_str = to_string;
-    let bytes = Bytes::from(body_str);
-    let full_body = hyper::body::Body::from(bytes);
-
-    let request = Request::builder()
-        .method(method)
-        .uri(uri)
-        .header("content-type", "application/json")
-        .body(full_body)?;
-
-    request
-}
-
-#[tokio::test]
-async fn test_handle_get_nonexistent_task() {
-    let store = Arc::new(Store::new());
-    let request_id = Uuid::new_v4();
-    let start_time = Instant::now();
-
-
-    // First create a task
-    let create_task = CreateTask {
-        title: "Test Task".to_string(),
+use crate::models::{CreateTask, MAX_BATCH_OPERATIONS};
+use crate::testing::TestServer;
+use hyper::StatusCode;
+use serde_json::{json, Value};
+
+fn create_task(title: &str) -> CreateTask {
+    CreateTask {
+        title: title.to_string(),
         description: "Test Description".to_string(),
-    };
-    let id = store.create_task(create_task).await;
-
-    // Then get it
-    let request = create_json_request(hyper::Method::GET, &format!("/tasks/{}", id), &());
-    let response = handle_list_tasks(store.clone(), request_id).await.unwrap();
-
-    assert_eq!(response.status(), StatusCode::OK);
-    assert_eq!(response.headers()["content-type"], "application/json");
-
-    let body = get_body_json(response).await;
+        due_at: None,
+        priority: None,
+        tags: Default::default(),
+        parent_id: None,
+        blocked_by: Default::default(),
+    }
+}
 
-    assert_eq!(body["id"], id.to_string());
-    assert_eq!(body["title"], "Test Task");
-    assert_eq!(body["description"], "Test Description");
+/// Assert that a v1 body carries the request's metadata alongside its fields.
+fn assert_has_meta(body: &Value) {
     assert!(body["request_id"].is_string());
     assert!(body["timestamp"].is_string());
     assert!(body["processing_time_ms"].is_number());
@@ -68,227 +24,329 @@ async fn test_handle_get_nonexistent_task() {
 
 #[tokio::test]
 async fn test_handle_get_task() {
-    let store = Arc::new(Store::new());
-    let request_id = Uuid::new_v4();
-    let start_time = Instant::now();
-
-    // Try to get a nonexistent task
-    let request = create_json_request(hyper::Method::GET, "/tasks/nonexistent", &());
-    let response = handle_get_task(store.clone(), request, "nonexistent", request_id).await.unwrap();
+    let server = TestServer::start().await;
+    let id = server.store().create_task(create_task("Test Task")).await.unwrap().id;
+
+    let response = server.get(&format!("/tasks/{}", id)).send().await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("content-type"), "application/json");
+    let body: Value = response.json();
+    assert_eq!(body["task"]["id"], id);
+    assert_eq!(body["task"]["title"], "Test Task");
+    assert_eq!(body["task"]["description"], "Test Description");
+    assert_has_meta(&body);
+}
 
-    assert_eq!(response.status(), StatusCode::NOT_FOUND);
-    assert_eq!(response.headers()["content-type"], "application/json");
+#[tokio::test]
+async fn test_handle_get_nonexistent_task() {
+    let server = TestServer::start().await;
 
-    let body = get_body_json(response).await;
+    let response = server.get("/tasks/42").send().await;
 
-    assert_eq!(body["message"], "Task not found");
-    assert!(body["request_id"].is_string());
-    assert!(body["timestamp"].is_string());
-    assert!(body["processing_time_ms"].is_number());
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    assert_eq!(response.header("content-type"), "application/json");
+    let body: Value = response.json();
+    assert_eq!(body["error"], "Task not found");
+    assert_has_meta(&body);
 }
 
 #[tokio::test]
 async fn test_handle_create_task_invalid_body() {
-    let store = Arc::new(Store::new());
-    let request_id = Uuid::new_v4();
-    let start_time = Instant::now();
+    let server = TestServer::start().await;
 
-    // Create a task with an invalid body
-    let invalid_body = "invalid body";
-    let request = create_json_request(hyper::Method::POST, "/tasks", &invalid_body).unwrap();
-    let response = handle_create_task(request, store.clone(), request_id, start_time).await.unwrap();
+    let response = server.post("/tasks").json("invalid body").send().await;
 
-    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
-    assert_eq!(response.headers()["content-type"], "application/json");
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.header("content-type"), "application/json");
+    let body: Value = response.json();
+    assert_eq!(body["error"], "Invalid request body");
+    assert_has_meta(&body);
+    assert!(server.store().list_tasks().await.is_empty());
+}
 
-    let body = get_body_json(response).await;
+#[tokio::test]
+async fn test_handle_create_task_invalid_fields() {
+    let server = TestServer::start().await;
 
-    assert_eq!(body["message"], "Invalid request body");
-    assert!(body["request_id"].is_string());
-    assert!(body["timestamp"].is_string());
-    assert!(body["processing_time_ms"].is_number());
-}
+    let task = json!({ "title": "", "description": "d".repeat(10_000), "tags": ["no spaces"] });
+    let response = server.post("/tasks").json(&task).send().await;
 
-/// Deserialize the body of a hyper::Response into a serde_json::Value.
-///
-/// The response body is collected and then deserialized into a serde_json::Value.
-///
-/// # Panics
-/// If the body cannot be deserialized into a serde_json::Value, the function will panic.
-async fn get_body_json(response: Response<Full<Bytes>>) -> Value {
-    let body = response.into_body().collect().await.unwrap().to_bytes();
-    serde_json::from_slice(&body).unwrap()
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    let body: Value = response.json();
+    let fields: Vec<&String> = body["fields"].as_object().unwrap().keys().collect();
+    assert_eq!(fields, ["description", "tags", "title"]);
+    assert!(server.store().list_tasks().await.is_empty());
 }
 
 /// Test that a task can be created successfully.
 ///
 /// Verifies that:
 ///
-/// * The response status is 201 Created.
-/// * The response body is JSON with the expected structure.
-/// * The response body contains the expected request ID, timestamp, and processing time.
-///
-/// # Panics
-/// If the response body cannot be deserialized into a serde_json::Value, the function will panic.
+/// * The response status is 201 Created, with the task's `Location`.
+/// * The response body holds the created task as stored.
+/// * The response body contains the request ID, timestamp, and processing time.
 #[tokio::test]
 async fn test_handle_create_task() {
-    let store = Arc::new(Store::new());
-    let request_id = Uuid::new_v4();
-    let start_time = Instant::now();
+    let server = TestServer::start().await;
 
-    let create_task = CreateTask {
-        title: "Test Task".to_string(),
-        description: "Test Description".to_string(),
-    };
+    let response = server.post("/tasks").json(&create_task("Test Task")).send().await;
 
-    let request = create_json_request(hyper::Method::POST, "/tasks", &create_task).unwrap();
-    let response = handle_create_task(request, store.clone(), request_id, start_time).await.unwrap();
+    assert_eq!(response.status, StatusCode::CREATED);
+    assert_eq!(response.header("content-type"), "application/json");
+    assert_eq!(response.header("location"), "/tasks/1");
+    let body: Value = response.json();
+    assert_eq!(body["id"], 1);
+    assert_eq!(body["message"], "Task created successfully");
+    assert_has_meta(&body);
+    assert_eq!(body["task"], json!(server.store().get_task(1).await.unwrap()));
+}
 
-    assert_eq!(response.status(), StatusCode::CREATED);
-    assert_eq!(response.headers()["content-type"], "application/json");
+/// Test that a task can be replaced successfully.
+///
+/// Verifies that:
+///
+/// * The response status is 200 OK.
+/// * The response body holds the task as replaced.
+/// * The response body contains the request ID, timestamp, and processing time.
+#[tokio::test]
+async fn test_handle_update_task() {
+    let server = TestServer::start().await;
+    let task = server.create_task(&create_task("Test Task")).await;
 
-    let body = get_body_json(response).await;
+    let replacement = json!({ "title": "Updated Task", "description": "Updated Description", "completed": true });
+    let response = server.put(&format!("/tasks/{}", task.id)).json(&replacement).send().await;
 
-    assert!(body["id"].is_number());
-    assert_eq!(body["message"], "Task created successfully");
-    assert!(body["request_id"].is_string());
-    assert!(body["timestamp"].is_string());
-    assert!(body["processing_time_ms"].is_number());
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("content-type"), "application/json");
+    let body: Value = response.json();
+    assert_eq!(body["message"], "Task updated successfully");
+    assert_eq!(body["task"]["title"], "Updated Task");
+    assert_eq!(body["task"]["completed"], true);
+    assert_has_meta(&body);
 }
 
-    /// Test that a task can be updated successfully.
-    ///
-    /// Verifies that:
-    ///
-    /// * The response status is 200 OK.
-    /// * The response body is JSON with the expected structure.
-    /// * The response body contains the expected request ID, timestamp, and processing time.
-    ///
+/// Test that a task can be deleted successfully.
+///
+/// Verifies that:
+///
+/// * The response status is 200 OK.
+/// * The response body contains the request ID, timestamp, and processing time.
+/// * The task is deleted and subsequent deletion attempts result in a 404 Not Found response.
 #[tokio::test]
-async fn test_handle_update_task() {
-    let store = Arc::new(Store::new());
-    let request_id = Uuid::new_v4();
-    let start_time = Instant::now();
+async fn test_handle_delete_task() {
+    let server = TestServer::start().await;
+    let task = server.create_task(&create_task("Test Task")).await;
+    let path = format!("/tasks/{}", task.id);
 
-    // First create a task
-    let create_task = CreateTask {
-        title: "Test Task".to_string(),
-        description: "Test Description".to_string(),
-    };
-    let id = store.create_task(create_task).await;
+    let response = server.delete(&path).send().await;
 
-    // Then update it
-    let update_task = UpdateTask {
-        title: Some("Updated Task".to_string()),
-        description: Some("Updated Description".to_string()),
-        completed: Some(true),
-    };
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("content-type"), "application/json");
+    let body: Value = response.json();
+    assert_eq!(body["message"], "Task deleted successfully");
+    assert_has_meta(&body);
+
+    assert_eq!(server.delete(&path).send().await.status, StatusCode::NOT_FOUND);
+    assert_eq!(server.get(&path).send().await.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_handle_list_tasks() {
+    let server = TestServer::start().await;
+    server.create_task(&create_task("Test Task 1")).await;
+    server.create_task(&create_task("Test Task 2")).await;
+
+    let response = server.get("/tasks").send().await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("content-type"), "application/json");
+    let body: Value = response.json();
+    let titles: Vec<&str> = body["tasks"].as_array().unwrap().iter().map(|t| t["title"].as_str().unwrap()).collect();
+    assert_eq!(titles, ["Test Task 1", "Test Task 2"]);
+    assert_has_meta(&body);
+}
 
-    let request = create_json_request(hyper::Method::PUT, &format!("/tasks/{}", id), &update_task);
-    let response = handle_update_task(request, store.clone(), &id.to_string(), request_id, start_time).await.unwrap();
+#[tokio::test]
+async fn test_invalid_task_id() {
+    let server = TestServer::start().await;
 
-    assert_eq!(response.status(), StatusCode::OK);
-    assert_eq!(response.headers()["content-type"], "application/json");
+    let replacement = json!({ "title": "Updated Task", "description": "", "completed": true });
+    let response = server.put("/tasks/invalid").json(&replacement).send().await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.json::<Value>()["error"], "Invalid task ID");
 
-    let body = get_body_json(response).await;
+    let response = server.delete("/tasks/invalid").send().await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
 
-    assert_eq!(body["message"], "Task updated successfully");
-    assert!(body["request_id"].is_string());
-    assert!(body["timestamp"].is_string());
-    assert!(body["processing_time_ms"].is_number());
+    // No ID can follow the last one, so it cannot be chosen
+    let response = server.put(&format!("/tasks/{}", u64::MAX)).json(&replacement).send().await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response.json::<Value>()["error"], "ID out of range");
+    assert_eq!(server.get("/tasks").send().await.status, StatusCode::OK);
 }
 
-    /// Test that a task can be deleted successfully.
-    ///
-    /// Verifies that:
-    ///
-    /// * The response status is 200 OK.
-    /// * The response body is JSON with the expected structure.
-    /// * The response body contains the expected request ID, timestamp, and processing time.
-    /// * The task is deleted and subsequent deletion attempts result in a 404 Not Found response.
+/// Test that the version prefix selects the shape of the body, and that
+/// paths outside the API are not found.
 #[tokio::test]
-async fn test_handle_delete_task() {
-    let store = Arc::new(Store::new());
-    let request_id = Uuid::new_v4();
-    let start_time = Instant::now();
+async fn test_routing() {
+    let server = TestServer::start().await;
+    let task = server.create_task(&create_task("Test Task")).await;
 
-    // First create a task
-    let create_task = CreateTask {
-        title: "Test Task".to_string(),
-        description: "Test Description".to_string(),
-    };
-    let id = store.create_task(create_task).await;
+    let response = server.get(&format!("/v2/tasks/{}", task.id)).send().await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.data::<Value>()["task"]["title"], "Test Task");
+    assert!(response.headers.get("deprecation").is_none());
 
-    // Then delete it
-    let response = handle_delete_task(store.clone(), &id.to_string(), request_id, start_time).await.unwrap();
+    let response = server.get(&format!("/v1/tasks/{}", task.id)).send().await;
+    assert_eq!(response.json::<Value>()["task"]["title"], "Test Task");
+    assert!(response.headers.get("deprecation").is_none());
 
-    assert_eq!(response.status(), StatusCode::OK);
-    assert_eq!(response.headers()["content-type"], "application/json");
+    let response = server.get("/v2/tasks/42").send().await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    assert_eq!(response.json::<Value>()["errors"][0]["message"], "Task not found");
 
-    let body = get_body_json(response).await;
+    assert_eq!(server.get("/v2/health").send().await.status, StatusCode::NOT_FOUND);
+    assert_eq!(server.post("/tasks/1").send().await.status, StatusCode::NOT_FOUND);
+}
 
-    assert_eq!(body["message"], "Task deleted successfully");
-    assert!(body["request_id"].is_string());
-    assert!(body["timestamp"].is_string());
-    assert!(body["processing_time_ms"].is_number());
+/// Test that PATCH applies a merge patch only while `If-Match` holds.
+#[tokio::test]
+async fn test_handle_patch_task_if_match() {
+    let server = TestServer::start().await;
+    let task = server.create_task(&create_task("Test Task")).await;
+    let path = format!("/tasks/{}", task.id);
+    let patch = json!({ "completed": true }).to_string();
+
+    let response = server
+        .patch(&path)
+        .header("if-match", "\"7\"")
+        .body("application/merge-patch+json", patch.clone())
+        .send()
+        .await;
+    assert_eq!(response.status, StatusCode::PRECONDITION_FAILED);
+
+    // The tag may come from another representation of the same version
+    let etag = server.get(&format!("/v2{}", path)).header("accept", "application/cbor").send().await;
+    let etag = etag.header("etag").to_string();
+    let response = server
+        .patch(&path)
+        .header("if-match", &etag)
+        .body("application/merge-patch+json", patch)
+        .send()
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.json::<Value>()["task"]["completed"], true);
+    assert_ne!(response.header("etag"), etag);
+}
 
-    // Verify task is deleted
-    let response = handle_delete_task(store.clone(), &id.to_string(), request_id, start_time).await.unwrap();
-    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+/// The `(status, title)` of each result of a batch response.
+fn batch_results(body: &Value) -> Vec<(u64, Option<&str>)> {
+    let results = body["results"].as_array().unwrap();
+    results.iter().map(|result| (result["status"].as_u64().unwrap(), result["task"]["title"].as_str())).collect()
 }
 
 #[tokio::test]
-async fn test_handle_list_tasks() {
-    let store = Arc::new(Store::new());
-    let request_id = Uuid::new_v4();
-    let start_time = Instant::now();
-
-    // First create some tasks
-    let create_task1 = CreateTask {
-        title: "Test Task 1".to_string(),
-        description: "Test Description 1".to_string(),
-    };
-    let create_task2 = CreateTask {
-        title: "Test Task 2".to_string(),
-        description: "Test Description 2".to_string(),
-    };
-    store.create_task(create_task1).await;
-    store.create_task(create_task2).await;
+async fn test_handle_batch_tasks() {
+    let server = TestServer::start().await;
+    let task = server.create_task(&create_task("Existing")).await;
+
+    let batch = json!({
+        "operations": [
+            { "op": "create", "task": { "title": "Created", "description": "" } },
+            { "op": "update", "id": task.id, "task": { "completed": true }, "version": task.version },
+        ],
+    });
+    let response = server.post("/tasks/batch").json(&batch).send().await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    let body: Value = response.json();
+    assert_eq!(body["atomic"], true);
+    assert_eq!(batch_results(&body), [(201, Some("Created")), (200, Some("Existing"))]);
+    assert_has_meta(&body);
+    assert!(server.store().get_task(task.id).await.unwrap().completed);
+    assert_eq!(server.store().list_tasks().await.len(), 2);
+}
 
-    // Then list them
-    let response = handle_list_tasks(store.clone(), request_id).await.unwrap();
+#[tokio::test]
+async fn test_handle_batch_tasks_atomic_failure() {
+    let server = TestServer::start().await;
+
+    let batch = json!({
+        "operations": [
+            { "op": "create", "task": { "title": "Rolled back", "description": "" } },
+            { "op": "delete", "id": 42 },
+            { "op": "create", "task": { "title": "Never tried", "description": "" } },
+        ],
+    });
+    let response = server.post("/tasks/batch").json(&batch).send().await;
+
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    let body: Value = response.json();
+    assert_eq!(batch_results(&body), [(424, None), (404, None), (424, None)]);
+    assert_eq!(body["results"][1]["error"], "Task not found");
+    assert!(server.store().list_tasks().await.is_empty());
+}
 
-    assert_eq!(response.status(), StatusCode::OK);
-    assert_eq!(response.headers()["content-type"], "application/json");
+#[tokio::test]
+async fn test_handle_batch_tasks_partial_failure() {
+    let server = TestServer::start().await;
+
+    let batch = json!({
+        "atomic": false,
+        "operations": [
+            { "op": "create", "task": { "title": "Applied", "description": "" } },
+            { "op": "delete", "id": 42 },
+        ],
+    });
+    let response = server.post("/tasks/batch").json(&batch).send().await;
+
+    assert_eq!(response.status, StatusCode::MULTI_STATUS);
+    let body: Value = response.json();
+    assert_eq!(body["atomic"], false);
+    assert_eq!(batch_results(&body), [(201, Some("Applied")), (404, None)]);
+    assert_eq!(server.store().list_tasks().await.len(), 1);
+}
 
-    let body = get_body_json(response).await;
+#[tokio::test]
+async fn test_handle_batch_tasks_too_many_operations() {
+    let server = TestServer::start().await;
 
-    assert!(body["tasks"].is_array());
-    let tasks = body["tasks"].as_array().unwrap();
-    assert_eq!(tasks.len(), 2);
-    assert!(body["request_id"].is_string());
-    assert!(body["timestamp"].is_string());
-    assert!(body["processing_time_ms"].is_number());
+    let operation = json!({ "op": "create", "task": { "title": "One too many", "description": "" } });
+    let batch = json!({ "operations": vec![operation; MAX_BATCH_OPERATIONS + 1] });
+    let response = server.post("/tasks/batch").json(&batch).send().await;
+
+    assert_eq!(response.status, StatusCode::PAYLOAD_TOO_LARGE);
+    let body: Value = response.json();
+    assert_eq!(body["error"], format!("A batch can contain at most {} operations", MAX_BATCH_OPERATIONS));
+    assert!(server.store().list_tasks().await.is_empty());
 }
 
+/// Test that a key is replayed only for the same version and format of the
+/// response it was first used for.
 #[tokio::test]
-async fn test_invalid_task_id() {
-    let store = Arc::new(Store::new());
-    let request_id = Uuid::new_v4();
-    let start_time = Instant::now();
-
-    let update_task = UpdateTask {
-        title: Some("Updated Task".to_string()),
-        description: Some("Updated Description".to_string()),
-        completed: Some(true),
+async fn test_handle_create_task_idempotency_key() {
+    let server = TestServer::start().await;
+    let task = create_task("Once");
+    let send = |path: &'static str, accept: &'static str| {
+        let task = &task;
+        let server = &server;
+        async move {
+            let request = server.post(path).header("idempotency-key", "k1").header("accept", accept);
+            request.json(task).send().await
+        }
     };
 
-    // Test invalid task ID for update
-    let request = create_json_request(hyper::Method::PUT, "/tasks/invalid", &update_task);
-    let response = handle_update_task(request, store.clone(), "invalid", request_id, start_time).await.unwrap();
-    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
-
-    // Test invalid task ID for delete
-    let response = handle_delete_task(store.clone(), "invalid", request_id, start_time).await.unwrap();
-    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let first = send("/v1/tasks", "application/json").await;
+    assert_eq!(first.status, StatusCode::CREATED);
+    let replayed = send("/v1/tasks", "application/json").await;
+    assert_eq!(replayed.status, StatusCode::CREATED);
+    assert_eq!(replayed.header("idempotent-replayed"), "true");
+    assert_eq!(replayed.body, first.body);
+
+    for (path, accept) in [("/v2/tasks", "application/json"), ("/v1/tasks", "application/cbor")] {
+        let response = send(path, accept).await;
+        assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY, "{} as {}", path, accept);
+    }
+    assert_eq!(server.store().list_tasks().await.len(), 1);
 }
@@ -0,0 +1,71 @@
+use crate::config::Config;
+use crate::handlers::AllowedOrigins;
+use crate::testing::{self, TestRequest, TestServer};
+use hyper::header::{HOST, ORIGIN};
+use hyper::{HeaderMap, StatusCode};
+
+/// A WebSocket handshake for `path`, offering the GraphQL subprotocol so
+/// that it is also accepted by `/graphql`.
+fn handshake<'a>(server: &'a TestServer, path: &str) -> TestRequest<'a> {
+    server
+        .get(path)
+        .header("Connection", "Upgrade")
+        .header("Upgrade", "websocket")
+        .header("Sec-WebSocket-Version", "13")
+        .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+        .header("Sec-WebSocket-Protocol", "graphql-transport-ws")
+}
+
+fn headers(origin: Option<&str>, host: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(HOST, host.parse().unwrap());
+    if let Some(origin) = origin {
+        headers.insert(ORIGIN, origin.parse().unwrap());
+    }
+    headers
+}
+
+#[test]
+fn test_allowed_origins() {
+    let origins = AllowedOrigins::new(["https://App.example.com/".to_string()]);
+    assert!(origins.allow(&headers(None, "api.example.com")));
+    assert!(origins.allow(&headers(Some("http://api.example.com"), "api.example.com")));
+    assert!(origins.allow(&headers(Some("https://API.example.com"), "api.example.com")));
+    assert!(origins.allow(&headers(Some("https://app.example.com"), "api.example.com")));
+
+    assert!(!origins.allow(&headers(Some("https://evil.example.com"), "api.example.com")));
+    assert!(!origins.allow(&headers(Some("https://api.example.com:8443"), "api.example.com")));
+    assert!(!origins.allow(&headers(Some("http://app.example.com"), "api.example.com")));
+    assert!(!origins.allow(&headers(Some("null"), "api.example.com")));
+}
+
+#[tokio::test]
+async fn test_websocket_handshake_checks_origin() {
+    let server = TestServer::start().await;
+    let own = server.url();
+    for path in ["/ws", "/graphql"] {
+        let response = handshake(&server, path).send().await;
+        assert_eq!(response.status, StatusCode::SWITCHING_PROTOCOLS, "{}", path);
+
+        let response = handshake(&server, path).header("Origin", &own).send().await;
+        assert_eq!(response.status, StatusCode::SWITCHING_PROTOCOLS, "{}", path);
+
+        let response = handshake(&server, path).header("Origin", "https://evil.example.com").send().await;
+        assert_eq!(response.status, StatusCode::FORBIDDEN, "{}", path);
+    }
+}
+
+#[tokio::test]
+async fn test_websocket_handshake_from_configured_origin() {
+    let config = Config {
+        ws_allowed_origins: vec!["https://app.example.com".to_string()],
+        ..testing::config()
+    };
+    let server = TestServer::start_configured(config, |server| server).await;
+
+    let response = handshake(&server, "/ws").header("Origin", "https://app.example.com").send().await;
+    assert_eq!(response.status, StatusCode::SWITCHING_PROTOCOLS);
+
+    let response = handshake(&server, "/ws").header("Origin", "https://other.example.com").send().await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+}
@@ -0,0 +1,99 @@
+use super::tasks::{respond_with_error, respond_with_payload, respond_with_store_error, with_etag};
+use crate::conditional::{self, EntityTags};
+use crate::formats::Format;
+use crate::responses::{Envelope, ErrorEnvelope, TaskBody, TaskListBody};
+use crate::store::Store;
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::header::HeaderMap;
+use hyper::{Response, StatusCode};
+use std::sync::Arc;
+use tokio::time::Instant;
+use tracing::instrument;
+use uuid::Uuid;
+
+/// Handler for `GET /tasks/trash`.
+///
+/// Lists the deleted tasks that have not been purged yet, by ID.
+#[utoipa::path(
+    get,
+    path = "/tasks/trash",
+    operation_id = "listTrash",
+    tag = "tasks",
+    summary = "List deleted tasks",
+    description = "Lists the tasks in the trash, which can still be restored.",
+    responses((status = 200, description = "The deleted tasks", body = Envelope<TaskListBody>))
+)]
+#[instrument(skip(store))]
+pub async fn handle_list_trash(
+    store: Arc<Store>,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let body = TaskListBody {
+        tasks: store.list_trash().await,
+    };
+    Ok(respond_with_payload(format, StatusCode::OK, body, request_id, start_time))
+}
+
+/// Handler for `POST /tasks/{id}/restore`.
+///
+/// Moves a deleted task back out of the trash and returns it with its new
+/// `ETag`.  Returns 404 Not Found if the task is not in the trash, and 409
+/// Conflict if its parent or a task blocking it has since been deleted.
+/// `If-Match` is honored against the deleted task's `ETag`.
+#[utoipa::path(
+    post,
+    path = "/tasks/{id}/restore",
+    operation_id = "restoreTask",
+    tag = "tasks",
+    summary = "Restore a deleted task",
+    description = "Moves the task out of the trash.",
+    params(
+        ("id" = u64, Path, description = "Task ID"),
+        ("If-Match" = Option<String>, Header, description = "Only restore the task if its ETag matches"),
+    ),
+    responses(
+        (status = 200, description = "The task was restored", body = Envelope<TaskBody>),
+        (status = 400, description = "The ID is invalid", body = ErrorEnvelope),
+        (status = 404, description = "There is no such task in the trash", body = ErrorEnvelope),
+        (status = 409, description = "The task's parent or a task blocking it has been deleted", body = ErrorEnvelope),
+        (status = 412, description = "A precondition failed", body = ErrorEnvelope),
+    )
+)]
+#[instrument(skip(headers, store))]
+pub async fn handle_restore_task(
+    headers: &HeaderMap,
+    store: Arc<Store>,
+    task_id_str: &str,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let task_id = match task_id_str.parse::<u64>() {
+        Ok(id) => id,
+        Err(_) => return respond_with_error(format, "Invalid task ID", request_id, start_time, StatusCode::BAD_REQUEST),
+    };
+
+    let if_match = EntityTags::if_match(headers);
+    let result = match &if_match {
+        Some(tags) => {
+            store
+                .restore_task_if(task_id, |task| tags.matches_version(task))
+                .await
+        }
+        None => store.restore_task(task_id).await,
+    };
+    match result {
+        Ok(task) => {
+            let etag = conditional::etag(&task, format);
+            let body = TaskBody {
+                task,
+                message: Some("Task restored successfully"),
+            };
+            Ok(with_etag(respond_with_payload(format, StatusCode::OK, body, request_id, start_time), &etag))
+        }
+        Err(err) => respond_with_store_error(err, if_match.is_some(), format, request_id, start_time),
+    }
+}
@@ -0,0 +1,212 @@
+use super::tasks::{read_body, respond_with_error, respond_with_payload, respond_with_validation_errors};
+use crate::formats::Format;
+use crate::responses::{DeadLettersBody, DeliveriesBody, Envelope, ErrorEnvelope, WebhookBody, WebhookListBody};
+use crate::webhooks::{CreateWebhook, Webhooks};
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::body::Incoming;
+use hyper::{Request, Response, StatusCode};
+use std::sync::Arc;
+use tokio::time::Instant;
+use tracing::instrument;
+use uuid::Uuid;
+
+/// Handler for `POST /webhooks`.
+///
+/// Subscribes a URL to task changes.  The body names the `url`, the event
+/// `events` to deliver (every event if omitted) and the `secret` deliveries
+/// are signed with; the secret is never returned.  Invalid fields are a 422
+/// Unprocessable Entity, as is a URL whose host is not public and not
+/// allowed by the configuration.
+#[utoipa::path(
+    post,
+    path = "/webhooks",
+    operation_id = "createWebhook",
+    tag = "webhooks",
+    summary = "Create a webhook",
+    description = "Subscribes a URL to task changes, delivered as signed POST requests.",
+    request_body = CreateWebhook,
+    responses(
+        (status = 201, description = "The webhook was created", body = Envelope<WebhookBody>),
+        (status = 400, description = "The body is invalid", body = ErrorEnvelope),
+        (status = 415, description = "The body is in an unsupported format", body = ErrorEnvelope),
+        (status = 422, description = "A field is invalid, or the URL's host is not allowed", body = ErrorEnvelope),
+    )
+)]
+#[instrument(skip(req, webhooks))]
+pub async fn handle_create_webhook(
+    req: Request<Incoming>,
+    webhooks: Arc<Webhooks>,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let create = match read_body::<CreateWebhook>(req, format, request_id, start_time).await? {
+        Ok(create) => create,
+        Err(response) => return Ok(response),
+    };
+    let mut errors = create.validate();
+    if errors.is_empty() {
+        if let Err(message) = webhooks.check_destination(&create.url).await {
+            errors.insert("url", message);
+        }
+    }
+    if !errors.is_empty() {
+        return respond_with_validation_errors(format, &errors, request_id, start_time);
+    }
+
+    let webhook = webhooks.create(create);
+    let body = WebhookBody {
+        webhook,
+        message: Some("Webhook created successfully"),
+    };
+    Ok(respond_with_payload(format, StatusCode::CREATED, body, request_id, start_time))
+}
+
+/// Handler for `GET /webhooks`.
+#[utoipa::path(
+    get,
+    path = "/webhooks",
+    operation_id = "listWebhooks",
+    tag = "webhooks",
+    summary = "List webhooks",
+    responses((status = 200, description = "The webhooks", body = Envelope<WebhookListBody>))
+)]
+#[instrument(skip(webhooks))]
+pub async fn handle_list_webhooks(
+    webhooks: Arc<Webhooks>,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let body = WebhookListBody {
+        webhooks: webhooks.list(),
+    };
+    Ok(respond_with_payload(format, StatusCode::OK, body, request_id, start_time))
+}
+
+/// Handler for `GET /webhooks/{id}`.
+#[utoipa::path(
+    get,
+    path = "/webhooks/{id}",
+    operation_id = "getWebhook",
+    tag = "webhooks",
+    summary = "Get a webhook",
+    params(("id" = u64, Path, description = "Webhook ID")),
+    responses(
+        (status = 200, description = "The webhook", body = Envelope<WebhookBody>),
+        (status = 404, description = "There is no such webhook", body = ErrorEnvelope),
+    )
+)]
+#[instrument(skip(webhooks))]
+pub async fn handle_get_webhook(
+    webhooks: Arc<Webhooks>,
+    webhook_id_str: &str,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let webhook = match webhook_id_str.parse::<u64>().ok().and_then(|id| webhooks.get(id)) {
+        Some(webhook) => webhook,
+        None => return respond_with_error(format, "Webhook not found", request_id, start_time, StatusCode::NOT_FOUND),
+    };
+    let body = WebhookBody { webhook, message: None };
+    Ok(respond_with_payload(format, StatusCode::OK, body, request_id, start_time))
+}
+
+/// Handler for `DELETE /webhooks/{id}`.
+///
+/// Stops deliveries to the webhook, including retries already scheduled.
+#[utoipa::path(
+    delete,
+    path = "/webhooks/{id}",
+    operation_id = "deleteWebhook",
+    tag = "webhooks",
+    summary = "Delete a webhook",
+    description = "Stops deliveries to the webhook, including scheduled retries.",
+    params(("id" = u64, Path, description = "Webhook ID")),
+    responses(
+        (status = 200, description = "The webhook was deleted", body = Envelope<WebhookBody>),
+        (status = 404, description = "There is no such webhook", body = ErrorEnvelope),
+    )
+)]
+#[instrument(skip(webhooks))]
+pub async fn handle_delete_webhook(
+    webhooks: Arc<Webhooks>,
+    webhook_id_str: &str,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let webhook = match webhook_id_str.parse::<u64>().ok().and_then(|id| webhooks.delete(id)) {
+        Some(webhook) => webhook,
+        None => return respond_with_error(format, "Webhook not found", request_id, start_time, StatusCode::NOT_FOUND),
+    };
+    let body = WebhookBody {
+        webhook,
+        message: Some("Webhook deleted successfully"),
+    };
+    Ok(respond_with_payload(format, StatusCode::OK, body, request_id, start_time))
+}
+
+/// Handler for `GET /webhooks/{id}/deliveries`.
+///
+/// Lists the recent delivery attempts to the webhook, oldest first, with
+/// the receiver's status or the error for each.
+#[utoipa::path(
+    get,
+    path = "/webhooks/{id}/deliveries",
+    operation_id = "listDeliveries",
+    tag = "webhooks",
+    summary = "List a webhook's deliveries",
+    description = "Lists the recent delivery attempts to the webhook, oldest first.",
+    params(("id" = u64, Path, description = "Webhook ID")),
+    responses(
+        (status = 200, description = "The delivery attempts", body = Envelope<DeliveriesBody>),
+        (status = 404, description = "There is no such webhook", body = ErrorEnvelope),
+    )
+)]
+#[instrument(skip(webhooks))]
+pub async fn handle_list_deliveries(
+    webhooks: Arc<Webhooks>,
+    webhook_id_str: &str,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let webhook = match webhook_id_str.parse::<u64>().ok().and_then(|id| webhooks.get(id)) {
+        Some(webhook) => webhook,
+        None => return respond_with_error(format, "Webhook not found", request_id, start_time, StatusCode::NOT_FOUND),
+    };
+    let body = DeliveriesBody {
+        webhook_id: webhook.id,
+        deliveries: webhooks.deliveries(webhook.id),
+    };
+    Ok(respond_with_payload(format, StatusCode::OK, body, request_id, start_time))
+}
+
+/// Handler for `GET /webhooks/dead-letters`.
+///
+/// Lists the events that could not be delivered within the allowed
+/// attempts, oldest first.
+#[utoipa::path(
+    get,
+    path = "/webhooks/dead-letters",
+    operation_id = "listDeadLetters",
+    tag = "webhooks",
+    summary = "List undeliverable events",
+    description = "Lists the events that could not be delivered within the allowed attempts, oldest first.",
+    responses((status = 200, description = "The undelivered events", body = Envelope<DeadLettersBody>))
+)]
+#[instrument(skip(webhooks))]
+pub async fn handle_list_dead_letters(
+    webhooks: Arc<Webhooks>,
+    format: Format,
+    request_id: Uuid,
+    start_time: Instant,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let body = DeadLettersBody {
+        dead_letters: webhooks.dead_letters(),
+    };
+    Ok(respond_with_payload(format, StatusCode::OK, body, request_id, start_time))
+}
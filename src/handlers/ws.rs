@@ -0,0 +1,152 @@
+use crate::audit::AuditContext;
+use crate::store::Store;
+use crate::ws;
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::header::{
+    HeaderMap, HeaderValue, CONNECTION, HOST, ORIGIN, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_VERSION,
+    UPGRADE,
+};
+use hyper::{body::Incoming, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::WebSocketStream;
+use tracing::{error, info, instrument};
+use uuid::Uuid;
+
+/// Handler for the WebSocket endpoint, `GET /ws`.
+///
+/// Upgrades the connection and hands it to `ws::serve`, which speaks the
+/// JSON protocol described on `ws::ClientMessage` and `ws::ServerMessage`.
+/// Changes made over the socket are audited under the upgrade request's
+/// `X-Actor`.
+///
+/// A request that is not a WebSocket handshake gets 400 Bad Request, or 426
+/// Upgrade Required if it asks for an unsupported protocol version.  A
+/// handshake from a page on another origin than the server's, which is not
+/// in `origins`, gets 403 Forbidden.
+#[instrument(skip_all)]
+pub async fn handle_websocket(
+    req: Request<Incoming>,
+    store: Arc<Store>,
+    origins: &AllowedOrigins,
+    request_id: Uuid,
+) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    let accept = match accept_key(req.headers(), origins) {
+        Ok(accept) => accept,
+        Err(response) => return Ok(*response),
+    };
+    let actor = AuditContext::from_request(req.headers(), request_id).actor;
+
+    tokio::task::spawn(async move {
+        match hyper::upgrade::on(req).await {
+            Ok(upgraded) => {
+                info!(%request_id, "WebSocket connection opened");
+                let socket = WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None).await;
+                ws::serve(socket, store, actor).await;
+            }
+            Err(err) => error!(error = %err, "WebSocket upgrade failed"),
+        }
+    });
+
+    Ok(switching_protocols(accept))
+}
+
+/// The origins besides the server's own whose pages may open a WebSocket.
+///
+/// Browsers let any page open a WebSocket to any host, sending the user's
+/// cookies along, so handshakes are only accepted from pages on the server's
+/// own origin or on one of these.  Clients that are not browsers send no
+/// `Origin` and are always accepted.
+#[derive(Debug, Default)]
+pub struct AllowedOrigins {
+    /// Lowercase origins without a trailing slash, as in
+    /// `https://app.example.com`.
+    origins: BTreeSet<String>,
+}
+
+impl AllowedOrigins {
+    pub fn new(origins: impl IntoIterator<Item = String>) -> Self {
+        AllowedOrigins {
+            origins: origins.into_iter().map(|origin| origin.trim_end_matches('/').to_ascii_lowercase()).collect(),
+        }
+    }
+
+    /// Whether a handshake with `headers` comes from an allowed page.
+    ///
+    /// The server's own origin is that of its `Host` header, over either
+    /// scheme as it may be behind a proxy terminating TLS.
+    pub fn allow(&self, headers: &HeaderMap) -> bool {
+        let Some(origin) = headers.get(ORIGIN) else {
+            return true;
+        };
+        let Ok(origin) = origin.to_str().map(str::to_ascii_lowercase) else {
+            return false;
+        };
+        if self.origins.contains(&origin) {
+            return true;
+        }
+        let host = origin.strip_prefix("http://").or_else(|| origin.strip_prefix("https://"));
+        let own_host = headers.get(HOST).and_then(|host| host.to_str().ok());
+        matches!((host, own_host), (Some(host), Some(own_host)) if host.eq_ignore_ascii_case(own_host))
+    }
+}
+
+/// Check a WebSocket handshake, returning the `Sec-WebSocket-Accept` value
+/// to answer it with, or the response rejecting it.
+pub(super) fn accept_key(
+    headers: &HeaderMap,
+    origins: &AllowedOrigins,
+) -> Result<String, Box<Response<Full<Bytes>>>> {
+    if !is_upgrade(headers) {
+        return Err(Box::new(plain_response(StatusCode::BAD_REQUEST, "Expected a WebSocket upgrade")));
+    }
+    if !origins.allow(headers) {
+        let message = "WebSocket connections from this origin are not allowed";
+        return Err(Box::new(plain_response(StatusCode::FORBIDDEN, message)));
+    }
+    if headers.get(SEC_WEBSOCKET_VERSION).is_none_or(|version| version != "13") {
+        let mut response = plain_response(StatusCode::UPGRADE_REQUIRED, "Unsupported WebSocket version");
+        response.headers_mut().insert(SEC_WEBSOCKET_VERSION, HeaderValue::from_static("13"));
+        return Err(Box::new(response));
+    }
+    match headers.get(SEC_WEBSOCKET_KEY) {
+        Some(key) => Ok(derive_accept_key(key.as_bytes())),
+        None => Err(Box::new(plain_response(StatusCode::BAD_REQUEST, "Missing Sec-WebSocket-Key"))),
+    }
+}
+
+/// The 101 Switching Protocols response completing a handshake.
+pub(super) fn switching_protocols(accept: String) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(CONNECTION, "upgrade")
+        .header(UPGRADE, "websocket")
+        .header(SEC_WEBSOCKET_ACCEPT, accept)
+        .body(Full::new(Bytes::new()))
+        .unwrap()
+}
+
+/// Whether the request asks to upgrade the connection to WebSocket.
+pub(super) fn is_upgrade(headers: &HeaderMap) -> bool {
+    let has_token = |name, token: &str| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|value| value.trim().eq_ignore_ascii_case(token))
+    };
+    has_token(CONNECTION, "upgrade") && has_token(UPGRADE, "websocket")
+}
+
+pub(super) fn plain_response(status: StatusCode, message: &str) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "text/plain; charset=utf-8")
+        .body(Full::new(Bytes::from(message.to_string())))
+        .unwrap()
+}
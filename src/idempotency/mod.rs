@@ -0,0 +1,314 @@
+use crate::formats::Format;
+use crate::versioning::ApiVersion;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use hyper::{Response, StatusCode};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Request header carrying the client's idempotency key.
+pub const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// Response header marking a replayed response.
+pub const IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+/// Longest idempotency key accepted, in bytes.
+pub const MAX_KEY_LEN: usize = 255;
+
+/// Most entries an `IdempotencyCache` holds by default.
+pub const MAX_ENTRIES: usize = 100_000;
+
+/// Most entries an `IdempotencyCache` holds for one principal by default.
+pub const MAX_ENTRIES_PER_PRINCIPAL: usize = 1_000;
+
+/// Principal used for requests without an `Authorization` header.
+const ANONYMOUS: &str = "anonymous";
+
+/// Read the `Idempotency-Key` header, if present.
+///
+/// Returns an error message for a key that is empty, too long or not
+/// visible ASCII.
+pub fn key(headers: &HeaderMap) -> Result<Option<String>, &'static str> {
+    let Some(value) = headers.get(IDEMPOTENCY_KEY) else {
+        return Ok(None);
+    };
+    let key = value.to_str().map_err(|_| "Idempotency-Key must be visible ASCII")?.trim();
+    if key.is_empty() || key.len() > MAX_KEY_LEN {
+        return Err("Idempotency-Key must be between 1 and 255 characters");
+    }
+    Ok(Some(key.to_string()))
+}
+
+/// Identify who sent the request, so keys from different clients never
+/// collide.
+///
+/// The `Authorization` header is hashed rather than kept, so credentials are
+/// not held in memory for the lifetime of the cache.
+pub fn principal(headers: &HeaderMap) -> String {
+    match headers.get(AUTHORIZATION) {
+        Some(value) => hex::encode(Sha256::digest(value.as_bytes())),
+        None => ANONYMOUS.to_string(),
+    }
+}
+
+/// Hash a request body together with the format it was sent in, and the API
+/// version and format its response is sent in.
+///
+/// A stored response is replayed as it was, so a retry asking for another
+/// version or format of it does not match.
+pub fn fingerprint(version: ApiVersion, response_format: Format, format: Format, body: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(version.name());
+    hasher.update([0]);
+    hasher.update(response_format.content_type());
+    hasher.update([0]);
+    hasher.update(format.content_type());
+    hasher.update([0]);
+    hasher.update(body);
+    hasher.finalize().into()
+}
+
+/// A response kept for replay.
+#[derive(Clone, Debug)]
+struct StoredResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl StoredResponse {
+    fn replay(&self) -> Response<Full<Bytes>> {
+        let mut response = Response::new(Full::new(self.body.clone()));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response.headers_mut().insert(IDEMPOTENT_REPLAYED, HeaderValue::from_static("true"));
+        response
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    /// The first request is still being handled.
+    InFlight,
+    /// The first request finished with this response.
+    Done(StoredResponse),
+}
+
+#[derive(Debug)]
+struct Entry {
+    fingerprint: [u8; 32],
+    /// Tells this reservation of the key apart from earlier ones that were
+    /// released or evicted while their request was still being handled.
+    serial: u64,
+    state: State,
+}
+
+/// A reservation in the expiry queue.
+#[derive(Debug)]
+struct Queued {
+    expires_at: Instant,
+    serial: u64,
+    id: (String, String),
+}
+
+/// The entries of an `IdempotencyCache`, with what it needs to expire and
+/// evict them without scanning them all.
+#[derive(Debug, Default)]
+struct Entries {
+    by_key: HashMap<(String, String), Entry>,
+    /// Every reservation in the order it was made, which with a single TTL
+    /// is also the order they expire in. Reservations that were released or
+    /// evicted stay queued until they reach the front or the queue is
+    /// compacted; their serial no longer matches the entry's, if any.
+    queue: VecDeque<Queued>,
+    per_principal: HashMap<String, usize>,
+    next_serial: u64,
+}
+
+impl Entries {
+    /// Whether a queued reservation still holds its key.
+    fn is_live(&self, queued: &Queued) -> bool {
+        self.by_key.get(&queued.id).is_some_and(|entry| entry.serial == queued.serial)
+    }
+
+    /// Remove the entry for `id` if it is still reservation `serial`.
+    fn remove(&mut self, id: &(String, String), serial: u64) {
+        if self.by_key.get(id).is_none_or(|entry| entry.serial != serial) {
+            return;
+        }
+        self.by_key.remove(id);
+        if let Some(count) = self.per_principal.get_mut(&id.0) {
+            *count -= 1;
+            if *count == 0 {
+                self.per_principal.remove(&id.0);
+            }
+        }
+    }
+
+    /// Drop the entries that expired by `now`, oldest first.
+    fn expire(&mut self, now: Instant) {
+        while self.queue.front().is_some_and(|queued| queued.expires_at <= now) {
+            if let Some(queued) = self.queue.pop_front() {
+                self.remove(&queued.id, queued.serial);
+            }
+        }
+    }
+
+    /// Drop the oldest entry, or the oldest of `principal`'s.
+    fn evict_oldest(&mut self, principal: Option<&str>) {
+        let oldest = self
+            .queue
+            .iter()
+            .position(|queued| principal.is_none_or(|principal| queued.id.0 == principal) && self.is_live(queued));
+        if let Some(queued) = oldest.and_then(|index| self.queue.remove(index)) {
+            self.remove(&queued.id, queued.serial);
+        }
+    }
+}
+
+/// What to do with a request carrying an idempotency key.
+pub enum Begin<'a> {
+    /// The key is new: handle the request and record its response.
+    Proceed(Reservation<'a>),
+    /// An identical request already finished; send its response again.
+    Replay(Response<Full<Bytes>>),
+    /// The key was used for a different request: another body, or another
+    /// version or format of the response.
+    Mismatch,
+    /// An identical request is still being handled.
+    InProgress,
+}
+
+/// Responses to `POST /tasks` keyed by principal and idempotency key.
+///
+/// Entries expire after the configured TTL, oldest first, as keys are looked
+/// up. The cache holds at most `max_entries`, and `max_per_principal` for any
+/// one principal; a new key past either limit evicts the oldest entry, or the
+/// principal's oldest, so one busy client cannot crowd out the others.
+pub struct IdempotencyCache {
+    ttl: Duration,
+    max_entries: usize,
+    max_per_principal: usize,
+    entries: Mutex<Entries>,
+}
+
+impl IdempotencyCache {
+    /// Creates a cache keeping responses for `ttl`, with the default limits
+    /// of `MAX_ENTRIES` and `MAX_ENTRIES_PER_PRINCIPAL`.
+    pub fn new(ttl: Duration) -> Self {
+        IdempotencyCache::with_limits(ttl, MAX_ENTRIES, MAX_ENTRIES_PER_PRINCIPAL)
+    }
+
+    /// Creates a cache keeping responses for `ttl` and holding at most
+    /// `max_entries`, and `max_per_principal` for any one principal.
+    pub fn with_limits(ttl: Duration, max_entries: usize, max_per_principal: usize) -> Self {
+        IdempotencyCache {
+            ttl,
+            max_entries: max_entries.max(1),
+            max_per_principal: max_per_principal.max(1),
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// Look up a key, reserving it if it is new.
+    pub fn begin(&self, principal: &str, key: &str, fingerprint: [u8; 32]) -> Begin<'_> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.expire(now);
+
+        let id = (principal.to_string(), key.to_string());
+        match entries.by_key.get(&id) {
+            Some(entry) if entry.fingerprint != fingerprint => return Begin::Mismatch,
+            Some(Entry { state: State::InFlight, .. }) => return Begin::InProgress,
+            Some(Entry { state: State::Done(stored), .. }) => return Begin::Replay(stored.replay()),
+            None => {}
+        }
+
+        if entries.per_principal.get(principal).is_some_and(|&count| count >= self.max_per_principal) {
+            entries.evict_oldest(Some(principal));
+        }
+        if entries.by_key.len() >= self.max_entries {
+            entries.evict_oldest(None);
+        }
+        // Released and evicted reservations leave stale queue items behind;
+        // compact once they outnumber the live ones
+        if entries.queue.len() >= 2 * self.max_entries {
+            let queue = std::mem::take(&mut entries.queue);
+            entries.queue = queue.into_iter().filter(|queued| entries.is_live(queued)).collect();
+        }
+
+        let serial = entries.next_serial;
+        entries.next_serial += 1;
+        entries.by_key.insert(
+            id.clone(),
+            Entry {
+                fingerprint,
+                serial,
+                state: State::InFlight,
+            },
+        );
+        *entries.per_principal.entry(id.0.clone()).or_default() += 1;
+        entries.queue.push_back(Queued {
+            expires_at: now + self.ttl,
+            serial,
+            id: id.clone(),
+        });
+        Begin::Proceed(Reservation {
+            cache: self,
+            id: Some(id),
+            serial,
+        })
+    }
+}
+
+/// A key reserved by `IdempotencyCache::begin`.
+///
+/// Dropping it without calling `complete` releases the key, so a request
+/// that failed before doing anything can be retried.
+pub struct Reservation<'a> {
+    cache: &'a IdempotencyCache,
+    id: Option<(String, String)>,
+    serial: u64,
+}
+
+impl Reservation<'_> {
+    /// Record the response for replay and pass it through.
+    ///
+    /// If the key was evicted in the meantime, the response is passed
+    /// through without being kept.
+    pub async fn complete(mut self, response: Response<Full<Bytes>>) -> Response<Full<Bytes>> {
+        let (parts, body) = response.into_parts();
+        // `Full` never fails and is already in memory, so this resolves at once
+        let body = match body.collect().await {
+            Ok(collected) => collected.to_bytes(),
+            Err(never) => match never {},
+        };
+
+        if let Some(id) = self.id.take() {
+            let stored = StoredResponse {
+                status: parts.status,
+                headers: parts.headers.clone(),
+                body: body.clone(),
+            };
+            let mut entries = self.cache.entries.lock().unwrap();
+            if let Some(entry) = entries.by_key.get_mut(&id).filter(|entry| entry.serial == self.serial) {
+                entry.state = State::Done(stored);
+            }
+        }
+        Response::from_parts(parts, Full::new(body))
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id.take() {
+            self.cache.entries.lock().unwrap().remove(&id, self.serial);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
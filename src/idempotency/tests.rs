@@ -0,0 +1,160 @@
+use super::*;
+use crate::versioning::ApiVersion;
+
+fn created(body: &'static str) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(StatusCode::CREATED)
+        .header("content-type", "application/json")
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
+#[test]
+fn test_key() {
+    let mut headers = HeaderMap::new();
+    assert_eq!(key(&headers), Ok(None));
+
+    headers.insert(IDEMPOTENCY_KEY, HeaderValue::from_static(" abc "));
+    assert_eq!(key(&headers), Ok(Some("abc".to_string())));
+
+    headers.insert(IDEMPOTENCY_KEY, HeaderValue::from_static(""));
+    assert!(key(&headers).is_err());
+
+    headers.insert(IDEMPOTENCY_KEY, HeaderValue::from_str(&"k".repeat(MAX_KEY_LEN + 1)).unwrap());
+    assert!(key(&headers).is_err());
+}
+
+#[test]
+fn test_principal() {
+    let mut headers = HeaderMap::new();
+    assert_eq!(principal(&headers), ANONYMOUS);
+
+    headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+    let hashed = principal(&headers);
+    assert_ne!(hashed, ANONYMOUS);
+    assert!(!hashed.contains("secret"));
+}
+
+/// The fingerprint of a v1 JSON request for a JSON response.
+fn request(body: &[u8]) -> [u8; 32] {
+    fingerprint(ApiVersion::V1, Format::Json, Format::Json, body)
+}
+
+#[test]
+fn test_fingerprint() {
+    let json = request(b"{}");
+    assert_eq!(json, request(b"{}"));
+    assert_ne!(json, request(b"{ }"));
+    assert_ne!(json, fingerprint(ApiVersion::V1, Format::Json, Format::MessagePack, b"{}"));
+    assert_ne!(json, fingerprint(ApiVersion::V1, Format::Cbor, Format::Json, b"{}"));
+    assert_ne!(json, fingerprint(ApiVersion::V2, Format::Json, Format::Json, b"{}"));
+}
+
+#[tokio::test]
+async fn test_replay_after_complete() {
+    let cache = IdempotencyCache::new(Duration::from_secs(60));
+    let print = request(b"{}");
+
+    let Begin::Proceed(reservation) = cache.begin("alice", "k1", print) else {
+        panic!("expected a new key");
+    };
+    assert!(matches!(cache.begin("alice", "k1", print), Begin::InProgress));
+    reservation.complete(created("first")).await;
+
+    let Begin::Replay(replayed) = cache.begin("alice", "k1", print) else {
+        panic!("expected a replay");
+    };
+    assert_eq!(replayed.status(), StatusCode::CREATED);
+    assert_eq!(replayed.headers()[IDEMPOTENT_REPLAYED], "true");
+    let body = replayed.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, "first");
+
+    let other = request(b"{\"title\":\"x\"}");
+    assert!(matches!(cache.begin("alice", "k1", other), Begin::Mismatch));
+
+    // Keys are scoped to the principal
+    assert!(matches!(cache.begin("bob", "k1", other), Begin::Proceed(_)));
+}
+
+#[test]
+fn test_dropped_reservation_releases_key() {
+    let cache = IdempotencyCache::new(Duration::from_secs(60));
+    let print = request(b"{}");
+
+    drop(cache.begin("alice", "k1", print));
+    assert!(matches!(cache.begin("alice", "k1", request(b"[]")), Begin::Proceed(_)));
+}
+
+#[tokio::test]
+async fn test_entries_expire() {
+    let cache = IdempotencyCache::new(Duration::ZERO);
+    let print = request(b"{}");
+
+    let Begin::Proceed(reservation) = cache.begin("alice", "k1", print) else {
+        panic!("expected a new key");
+    };
+    reservation.complete(created("first")).await;
+    assert!(matches!(cache.begin("alice", "k1", print), Begin::Proceed(_)));
+}
+
+#[tokio::test]
+async fn test_entries_per_principal_are_capped() {
+    let cache = IdempotencyCache::with_limits(Duration::from_secs(60), 10, 2);
+    let print = request(b"{}");
+
+    for key in ["k1", "k2", "k3"] {
+        let Begin::Proceed(reservation) = cache.begin("alice", key, print) else {
+            panic!("expected a new key");
+        };
+        reservation.complete(created(key)).await;
+    }
+    let Begin::Proceed(reservation) = cache.begin("bob", "k1", print) else {
+        panic!("expected a new key");
+    };
+    reservation.complete(created("bob")).await;
+
+    // Alice's oldest key made way for her third; Bob's is untouched
+    assert!(matches!(cache.begin("alice", "k1", request(b"[]")), Begin::Proceed(_)));
+    assert!(matches!(cache.begin("alice", "k3", print), Begin::Replay(_)));
+    assert!(matches!(cache.begin("bob", "k1", print), Begin::Replay(_)));
+}
+
+#[tokio::test]
+async fn test_entries_are_capped() {
+    let cache = IdempotencyCache::with_limits(Duration::from_secs(60), 2, 2);
+    let print = request(b"{}");
+
+    for principal in ["alice", "bob", "carol"] {
+        let Begin::Proceed(reservation) = cache.begin(principal, "k1", print) else {
+            panic!("expected a new key");
+        };
+        reservation.complete(created(principal)).await;
+    }
+    assert!(matches!(cache.begin("alice", "k1", request(b"[]")), Begin::Proceed(_)));
+    assert!(matches!(cache.begin("carol", "k1", print), Begin::Replay(_)));
+}
+
+#[tokio::test]
+async fn test_evicted_reservation_leaves_the_key_alone() {
+    let cache = IdempotencyCache::with_limits(Duration::from_secs(60), 10, 1);
+    let print = request(b"{}");
+
+    let Begin::Proceed(evicted) = cache.begin("alice", "k1", print) else {
+        panic!("expected a new key");
+    };
+    // Evicts the first reservation, then takes the key again
+    drop(cache.begin("alice", "k2", print));
+    let Begin::Proceed(current) = cache.begin("alice", "k1", print) else {
+        panic!("expected a new key");
+    };
+
+    // The evicted request finishing neither stores its response nor
+    // releases the key reserved since
+    evicted.complete(created("evicted")).await;
+    assert!(matches!(cache.begin("alice", "k1", print), Begin::InProgress));
+    current.complete(created("current")).await;
+    let Begin::Replay(replayed) = cache.begin("alice", "k1", print) else {
+        panic!("expected a replay");
+    };
+    assert_eq!(replayed.into_body().collect().await.unwrap().to_bytes(), "current");
+}
@@ -0,0 +1,33 @@
+mod accept;
+mod audit;
+mod compression;
+mod conditional;
+pub mod config;
+mod events;
+mod formats;
+mod forms;
+mod graphql;
+mod grpc;
+mod handlers;
+mod idempotency;
+pub mod models;
+mod openapi;
+mod patch;
+mod query;
+mod responses;
+mod routes;
+mod search;
+pub mod server;
+pub mod store;
+mod templates;
+#[cfg(test)]
+mod testing;
+mod utils;
+mod versioning;
+mod webhooks;
+mod ws;
+
+pub use config::Config;
+pub use server::{Bound, Next, Server};
+pub use store::Store;
+pub use versioning::Deprecation;
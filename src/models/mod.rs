@@ -1,65 +1,295 @@
-use serde::{Deserialize, Serialize};
-use std::sync::atomic::{AtomicU64, Ordering};
+use chrono::{DateTime, Utc};
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use utoipa::ToSchema;
 
-static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+/// Longest title a task may have, in characters.
+pub const MAX_TITLE_LEN: usize = 200;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Longest description a task may have, in characters.
+pub const MAX_DESCRIPTION_LEN: usize = 2000;
+
+/// Most tags a task may have.
+pub const MAX_TAGS: usize = 20;
+
+/// Longest tag allowed, in characters.
+pub const MAX_TAG_LEN: usize = 50;
+
+/// Most operations accepted in one `POST /tasks/batch` request.
+pub const MAX_BATCH_OPERATIONS: usize = 1000;
+
+/// How urgent a task is, from least to most.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Low,
+    Medium,
+    High,
+    Urgent,
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Priority::Low => "low",
+            Priority::Medium => "medium",
+            Priority::High => "high",
+            Priority::Urgent => "urgent",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
 pub struct Task {
     pub id: u64,
     pub title: String,
     pub description: String,
     pub completed: bool,
+    /// Incremented on every change; exposed to clients as the task's `ETag`.
+    pub version: u64,
+    #[serde(default)]
+    pub due_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub priority: Option<Priority>,
+    #[serde(default, deserialize_with = "tags")]
+    pub tags: BTreeSet<String>,
+    /// The task this one is a subtask of.
+    #[serde(default)]
+    pub parent_id: Option<u64>,
+    /// Tasks that must be completed before this one can be.
+    #[serde(default)]
+    pub blocked_by: BTreeSet<u64>,
+    /// Set by the store when the task is created.
+    pub created_at: DateTime<Utc>,
+    /// Set by the store on every change.
+    pub updated_at: DateTime<Utc>,
+    /// Set by the store when the task is completed, and cleared if it is
+    /// reopened.
+    #[serde(default)]
+    pub completed_at: Option<DateTime<Utc>>,
+    /// Set by the store when the task is moved to the trash, and cleared if
+    /// it is restored.
+    #[serde(default)]
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct CreateTask {
     pub title: String,
     pub description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<Priority>,
+    #[serde(default, deserialize_with = "tags", skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub blocked_by: BTreeSet<u64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// The complete representation of a task sent with `PUT /tasks/{id}`.
+///
+/// Every writable field is required except `due_at`, `priority`, `tags`,
+/// `parent_id` and `blocked_by`, which are cleared when left out, and unknown
+/// fields are rejected. `id` and `version` may be echoed back from a `GET`;
+/// `id` must match the URL and `version` is ignored, since `If-Match` is how
+/// clients guard against concurrent changes. The store-maintained timestamps
+/// may be echoed back too, and are ignored.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ReplaceTask {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u64>,
+    pub title: String,
+    pub description: String,
+    pub completed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub due_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<Priority>,
+    #[serde(default, deserialize_with = "tags", skip_serializing_if = "BTreeSet::is_empty")]
+    pub tags: BTreeSet<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub blocked_by: BTreeSet<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u64>,
+    #[serde(default, rename = "created_at", skip_serializing)]
+    pub _created_at: Option<IgnoredAny>,
+    #[serde(default, rename = "updated_at", skip_serializing)]
+    pub _updated_at: Option<IgnoredAny>,
+    #[serde(default, rename = "completed_at", skip_serializing)]
+    pub _completed_at: Option<IgnoredAny>,
+    #[serde(default, rename = "deleted_at", skip_serializing)]
+    pub _deleted_at: Option<IgnoredAny>,
+}
+
+/// A partial update: `None` leaves a field unchanged.
+///
+/// `due_at`, `priority` and `parent_id` can also be cleared, by sending them
+/// as `null`. `tags` and `blocked_by`, if present, replace the whole set.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct UpdateTask {
     pub title: Option<String>,
     pub description: Option<String>,
     pub completed: Option<bool>,
+    #[serde(default, deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub due_at: Option<Option<DateTime<Utc>>>,
+    #[serde(default, deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub priority: Option<Option<Priority>>,
+    #[serde(default, deserialize_with = "optional_tags", skip_serializing_if = "Option::is_none")]
+    pub tags: Option<BTreeSet<String>>,
+    #[serde(default, deserialize_with = "present", skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<Option<u64>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blocked_by: Option<BTreeSet<u64>>,
+}
+
+/// A task's dependency graph, as returned by `GET /tasks/{id}/dependencies`.
+///
+/// `tasks` holds the task itself, every task it is transitively blocked by
+/// and every task transitively blocked by it; `edges` links them.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DependencyGraph {
+    pub tasks: Vec<Task>,
+    pub edges: Vec<Dependency>,
+}
+
+/// One `blocked_by` relation: `task_id` cannot be completed before
+/// `blocked_by`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+pub struct Dependency {
+    pub task_id: u64,
+    pub blocked_by: u64,
+}
+
+/// The body of `POST /tasks/{id}/tags`: tags to add to a task.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AddTags {
+    #[serde(deserialize_with = "tags")]
+    pub tags: BTreeSet<String>,
+}
+
+/// Deserialize a tag list, normalizing each tag with `normalize_tag`.
+fn tags<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeSet<String>, D::Error> {
+    let tags = Vec::<String>::deserialize(deserializer)?;
+    Ok(tags.iter().map(|tag| normalize_tag(tag)).collect())
+}
+
+/// Deserialize an optional tag list, as for `tags`.
+fn optional_tags<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<BTreeSet<String>>, D::Error> {
+    tags(deserializer).map(Some)
+}
+
+/// Deserialize a field that is present, even as `null`, to `Some`.
+///
+/// Together with `#[serde(default)]` this tells a missing field (`None`) from
+/// a `null` one (`Some(None)`).
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// One operation in a `POST /tasks/batch` request.
+///
+/// `update` is a partial update like the form edit route. `update` and
+/// `delete` may carry the `version` the client last saw; the operation fails
+/// if the task has changed since.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum BatchOperation {
+    Create {
+        task: CreateTask,
+    },
+    Update {
+        id: u64,
+        task: UpdateTask,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        version: Option<u64>,
+    },
+    Delete {
+        id: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        version: Option<u64>,
+    },
+}
+
+/// The body of a `POST /tasks/batch` request.
+///
+/// With `atomic` (the default) either every operation is applied or none
+/// are. Without it, each operation succeeds or fails on its own.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BatchRequest {
+    pub operations: Vec<BatchOperation>,
+    #[serde(default = "default_atomic")]
+    pub atomic: bool,
+}
+
+fn default_atomic() -> bool {
+    true
 }
 
 impl Task {
-/// Creates a new `Task` instance from a `CreateTask` object.
-/// 
-/// This function assigns a unique ID to the task using an atomic counter and
-/// initializes the task with the provided title and description. The task is
-/// marked as not completed by default.
-/// 
-/// # Arguments
-/// 
-/// * `create_task` - A `CreateTask` object containing the title and description
-///   for the new task.
-/// 
-/// # Returns
-/// 
-/// A new `Task` instance with a unique ID and the specified title and description.
-
-    pub fn new(create_task: CreateTask) -> Self {
-        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    /// Creates a new `Task` instance from a `CreateTask` object.
+    ///
+    /// The ID and creation time are supplied by the caller (normally
+    /// `Store`). The task is initialized with the provided title,
+    /// description, due date and priority and is marked as not completed by
+    /// default.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The unique ID for the new task.
+    /// * `create_task` - A `CreateTask` object containing the title and description
+    ///   for the new task.
+    /// * `now` - The creation time, used for both `created_at` and `updated_at`.
+    ///
+    /// # Returns
+    ///
+    /// A new `Task` instance with the given ID and the specified title and description.
+    pub fn new(id: u64, create_task: CreateTask, now: DateTime<Utc>) -> Self {
         Task {
             id,
             title: create_task.title,
             description: create_task.description,
             completed: false,
+            version: 1,
+            due_at: create_task.due_at,
+            priority: create_task.priority,
+            tags: create_task.tags,
+            parent_id: create_task.parent_id,
+            blocked_by: create_task.blocked_by,
+            created_at: now,
+            updated_at: now,
+            completed_at: None,
+            deleted_at: None,
         }
     }
 
+    /// Returns true if the task is open and its due date has passed.
+    pub fn is_overdue(&self, now: DateTime<Utc>) -> bool {
+        !self.completed && self.due_at.is_some_and(|due_at| due_at < now)
+    }
+
     /// Updates a task with the given details.
     ///
-    /// This function updates a task with the given title, description, and
-    /// completed status. If the value for any of these fields is `None`, the
-    /// corresponding field on the task will not be updated.
+    /// Every field that is present in `update` replaces the one on the task;
+    /// absent fields are left as they are. For the nullable fields (`due_at`,
+    /// `priority` and `parent_id`), `Some(None)` clears the value. The version
+    /// is always incremented; the timestamps are left to the store.
     ///
     /// # Arguments
     ///
-    /// * `update` - An `UpdateTask` object containing the new title, description,
-    ///   and completed status for the task.
+    /// * `update` - An `UpdateTask` object holding the fields to change.
     pub fn update(&mut self, update: UpdateTask) {
         if let Some(title) = update.title {
             self.title = title;
@@ -70,5 +300,133 @@ impl Task {
         if let Some(completed) = update.completed {
             self.completed = completed;
         }
+        if let Some(due_at) = update.due_at {
+            self.due_at = due_at;
+        }
+        if let Some(priority) = update.priority {
+            self.priority = priority;
+        }
+        if let Some(tags) = update.tags {
+            self.tags = tags;
+        }
+        if let Some(parent_id) = update.parent_id {
+            self.parent_id = parent_id;
+        }
+        if let Some(blocked_by) = update.blocked_by {
+            self.blocked_by = blocked_by;
+        }
+        self.version += 1;
+    }
+}
+
+impl BatchOperation {
+    /// Validate the task fields the operation would write.
+    pub fn validate(&self) -> BTreeMap<&'static str, String> {
+        match self {
+            BatchOperation::Create { task } => task.validate(),
+            BatchOperation::Update { task, .. } => task.validate(),
+            BatchOperation::Delete { .. } => BTreeMap::new(),
+        }
+    }
+}
+
+impl Task {
+    /// Validate the task as a whole, such as after a patch was applied to it.
+    pub fn validate(&self) -> BTreeMap<&'static str, String> {
+        validate_present(Some(&self.title), Some(&self.description), Some(&self.tags))
+    }
+}
+
+impl CreateTask {
+    /// Validate the new task, returning an error message per invalid field.
+    pub fn validate(&self) -> BTreeMap<&'static str, String> {
+        validate_present(Some(&self.title), Some(&self.description), Some(&self.tags))
+    }
+}
+
+impl ReplaceTask {
+    /// Validate the replacement, returning an error message per invalid field.
+    pub fn validate(&self) -> BTreeMap<&'static str, String> {
+        validate_present(Some(&self.title), Some(&self.description), Some(&self.tags))
+    }
+}
+
+impl UpdateTask {
+    /// Validate the fields the update sets, returning an error message per
+    /// invalid field. Fields it leaves out keep their stored, valid values.
+    pub fn validate(&self) -> BTreeMap<&'static str, String> {
+        validate_present(self.title.as_deref(), self.description.as_deref(), self.tags.as_ref())
+    }
+}
+
+/// Check a title and description against the task schema.
+///
+/// Returns an error message keyed by field name for each invalid field; an
+/// empty map means both are valid.
+pub fn validate_fields(title: &str, description: &str) -> BTreeMap<&'static str, String> {
+    validate_present(Some(title), Some(description), None)
+}
+
+/// Check those of a title, description and tags that are given, in the shape
+/// of `validate_fields`.
+fn validate_present(
+    title: Option<&str>,
+    description: Option<&str>,
+    tags: Option<&BTreeSet<String>>,
+) -> BTreeMap<&'static str, String> {
+    let errors = [
+        title.and_then(validate_title),
+        description.and_then(validate_description),
+        tags.and_then(validate_tags),
+    ];
+    errors.into_iter().flatten().collect()
+}
+
+/// Check a title, returning the `title` field error, if any.
+fn validate_title(title: &str) -> Option<(&'static str, String)> {
+    if title.trim().is_empty() {
+        Some(("title", "Title is required".to_string()))
+    } else if title.chars().count() > MAX_TITLE_LEN {
+        Some(("title", format!("Title must be at most {} characters", MAX_TITLE_LEN)))
+    } else {
+        None
+    }
+}
+
+/// Check a description, returning the `description` field error, if any.
+fn validate_description(description: &str) -> Option<(&'static str, String)> {
+    (description.chars().count() > MAX_DESCRIPTION_LEN).then(|| {
+        ("description", format!("Description must be at most {} characters", MAX_DESCRIPTION_LEN))
+    })
+}
+
+/// Canonical form of a tag: trimmed and lowercased.
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+/// Check a set of normalized tags.
+///
+/// Tags are limited to ASCII letters, digits, `-`, `_`, `.` and `:`, so they
+/// can appear in URLs unescaped. Returns the `tags` field error, if any, in
+/// the same shape as `validate_fields`.
+pub fn validate_tags(tags: &BTreeSet<String>) -> Option<(&'static str, String)> {
+    let valid = |tag: &str| {
+        !tag.is_empty()
+            && tag.len() <= MAX_TAG_LEN
+            && tag.chars().all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c))
+    };
+    if tags.len() > MAX_TAGS {
+        Some(("tags", format!("A task can have at most {} tags", MAX_TAGS)))
+    } else if let Some(tag) = tags.iter().find(|tag| !valid(tag)) {
+        Some((
+            "tags",
+            format!(
+                "Invalid tag {:?}: tags are 1 to {} letters, digits, '-', '_', '.' or ':'",
+                tag, MAX_TAG_LEN
+            ),
+        ))
+    } else {
+        None
     }
 }
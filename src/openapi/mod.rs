@@ -0,0 +1,129 @@
+use crate::audit::{AuditAction, AuditEvent, FieldChange};
+use crate::events::TaskEvent;
+use crate::handlers;
+use crate::models::{
+    AddTags, BatchOperation, BatchRequest, CreateTask, Dependency, Priority, ReplaceTask, Task, UpdateTask,
+};
+use crate::responses::{
+    AuditLogBody, BatchBody, BatchResult, CreatedTaskBody, DeadLettersBody, DeliveriesBody, DependencyGraphBody,
+    ErrorDetail, ErrorEnvelope, Highlights, HistoryBody, MessageBody, Meta, SearchBody, SearchResult, SubtasksBody,
+    TagCount, TagsBody, TaskBody, TaskListBody, WebhookBody, WebhookListBody,
+};
+use crate::webhooks::{CreateWebhook, DeadLetter, Delivery, Webhook};
+use std::sync::OnceLock;
+use utoipa::{OpenApi, ToSchema};
+
+/// The OpenAPI document for the task API, assembled from the
+/// `#[utoipa::path]` attributes on the handlers and the model schemas.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Tasks API",
+        description = "Create, list, update and delete tasks. Every endpoint also reads and writes \
+                       CBOR (`application/cbor`) and MessagePack (`application/msgpack`) in the shapes \
+                       given here for JSON, and `GET /tasks` can export CSV (`text/csv`).\n\n\
+                       This describes v2, where every body is an envelope of `data`, `meta` and \
+                       `errors`. Without a `/v2` prefix, `Accept: application/vnd.rws.v2+json` selects \
+                       v2 and anything else v1, whose bodies are the fields of `data` and `meta` side \
+                       by side, with an `error` message in place of `errors`. Once v1 is scheduled \
+                       for retirement, it answers with `Deprecation` and `Sunset` headers."
+    ),
+    servers(
+        (url = "/v2", description = "API v2"),
+        (url = "/v1", description = "API v1, with the fields of `data` and `meta` side by side"),
+    ),
+    paths(
+        handlers::handle_list_tasks,
+        handlers::handle_create_task,
+        handlers::handle_batch_tasks,
+        handlers::handle_search_tasks,
+        handlers::handle_list_trash,
+        handlers::handle_task_events,
+        handlers::handle_get_task,
+        handlers::handle_update_task,
+        handlers::handle_patch_task,
+        handlers::handle_delete_task,
+        handlers::handle_restore_task,
+        handlers::handle_add_tags,
+        handlers::handle_remove_tag,
+        handlers::handle_list_subtasks,
+        handlers::handle_dependency_graph,
+        handlers::handle_task_history,
+        handlers::handle_list_tags,
+        handlers::handle_audit_log,
+        handlers::handle_create_webhook,
+        handlers::handle_list_webhooks,
+        handlers::handle_list_dead_letters,
+        handlers::handle_get_webhook,
+        handlers::handle_delete_webhook,
+        handlers::handle_list_deliveries,
+    ),
+    components(schemas(
+        Task,
+        Priority,
+        CreateTask,
+        ReplaceTask,
+        UpdateTask,
+        JsonPatchOperation,
+        BatchRequest,
+        BatchOperation,
+        Meta,
+        ErrorDetail,
+        ErrorEnvelope,
+        TaskBody,
+        TaskListBody,
+        CreatedTaskBody,
+        MessageBody,
+        BatchBody,
+        BatchResult,
+        AddTags,
+        Dependency,
+        SearchBody,
+        SearchResult,
+        Highlights,
+        TagsBody,
+        TagCount,
+        SubtasksBody,
+        DependencyGraphBody,
+        TaskEvent,
+        AuditAction,
+        AuditEvent,
+        FieldChange,
+        HistoryBody,
+        AuditLogBody,
+        CreateWebhook,
+        Webhook,
+        Delivery,
+        DeadLetter,
+        WebhookBody,
+        WebhookListBody,
+        DeliveriesBody,
+        DeadLettersBody,
+    )),
+    tags(
+        (name = "tasks", description = "Tasks and their lifecycle"),
+        (name = "audit", description = "Who changed what, and when"),
+        (name = "webhooks", description = "Deliveries of task changes to other services"),
+    )
+)]
+pub struct ApiDoc;
+
+/// The document as served at `/openapi.json`, rendered once.
+pub fn spec_json() -> &'static str {
+    static SPEC: OnceLock<String> = OnceLock::new();
+    SPEC.get_or_init(|| ApiDoc::openapi().to_json().expect("the OpenAPI document should serialize"))
+}
+
+/// One operation of a JSON Patch (RFC 6902) document.
+#[allow(dead_code)]
+#[derive(ToSchema)]
+pub struct JsonPatchOperation {
+    /// `add`, `remove`, `replace`, `move`, `copy` or `test`.
+    op: String,
+    path: String,
+    from: Option<String>,
+    value: Option<serde_json::Value>,
+}
+
+#[cfg(test)]
+mod tests;
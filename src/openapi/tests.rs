@@ -0,0 +1,338 @@
+use super::*;
+use crate::config::Config;
+use crate::routes::API_ROUTES;
+use crate::testing::{self, TestServer};
+use chrono::DateTime;
+use http_body_util::BodyExt;
+use hyper::Method;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+
+/// Sends requests to a served router and checks each JSON response against
+/// the schema the spec documents for its route and status.
+struct Exchanges {
+    spec: Value,
+    server: TestServer,
+    /// The `(path, method)` of every operation exercised.
+    covered: BTreeSet<(String, String)>,
+}
+
+impl Exchanges {
+    async fn new() -> Self {
+        // Webhooks can then be created without a public host to point at
+        let config = Config {
+            webhook_allowed_hosts: vec!["localhost".to_string()],
+            ..testing::config()
+        };
+        Exchanges {
+            spec: serde_json::from_str(spec_json()).unwrap(),
+            server: TestServer::start_configured(config, |server| server).await,
+            covered: BTreeSet::new(),
+        }
+    }
+
+    /// Send a request for the operation at `route` and check the response,
+    /// which must have `status`. Returns the response body.
+    ///
+    /// `uri` is relative to the spec's server, `/v2`.
+    async fn check(
+        &mut self,
+        method: Method,
+        route: &str,
+        uri: &str,
+        body: Option<(&str, String)>,
+        status: u16,
+    ) -> Value {
+        let mut request = self.server.request(method.clone(), &format!("/v2{}", uri));
+        if let Some((content_type, body)) = body {
+            request = request.body(content_type, body);
+        }
+        let response = request.send().await;
+        let body: Value = response.json();
+        assert_eq!(response.status, status, "{} {}: {}", method, uri, body);
+
+        let method = method.as_str().to_lowercase();
+        let operation = &self.spec["paths"][route][&method];
+        assert!(operation.is_object(), "{} {} is not in the spec", method, route);
+        let schema = &operation["responses"][status.to_string()]["content"]["application/json"]["schema"];
+        assert!(schema.is_object(), "{} {} does not document a {} response", method, route, status);
+        let errors = conforms(&self.spec, schema, &body, "$");
+        assert!(errors.is_empty(), "{} {} answered {} contrary to the spec: {:?}", method, uri, body, errors);
+
+        self.covered.insert((route.to_string(), method));
+        body
+    }
+
+    async fn json(&mut self, method: Method, route: &str, uri: &str, body: Value, status: u16) -> Value {
+        self.check(method, route, uri, Some(("application/json", body.to_string())), status).await
+    }
+
+    /// Open the event stream at `route`, which must answer 200 in a content
+    /// type the spec documents. Returns the first message.
+    async fn stream(&mut self, route: &str, uri: &str) -> String {
+        let response = self.server.get(&format!("/v2{}", uri)).open().await;
+        assert_eq!(response.status(), 200, "GET {}", uri);
+        let content_type = response.headers()["content-type"].to_str().unwrap().to_string();
+        let documented = &self.spec["paths"][route]["get"]["responses"]["200"]["content"][&content_type];
+        assert!(documented.is_object(), "GET {} does not document {}", route, content_type);
+
+        let frame = response.into_body().frame().await.unwrap().unwrap();
+        self.covered.insert((route.to_string(), "get".to_string()));
+        String::from_utf8(frame.into_data().unwrap().to_vec()).unwrap()
+    }
+}
+
+/// The `(path, method)` of every operation in the spec.
+fn operations(spec: &Value) -> BTreeSet<(String, String)> {
+    spec["paths"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .flat_map(|(path, operations)| {
+            operations.as_object().unwrap().keys().map(move |method| (path.clone(), method.clone()))
+        })
+        .collect()
+}
+
+/// Check `value` against a schema of the subset of JSON Schema the spec uses,
+/// returning where it does not conform. Objects may only have the properties
+/// their schema lists, so fields added to a response without documenting them
+/// are caught as well as documented fields that go missing.
+fn conforms(spec: &Value, schema: &Value, value: &Value, at: &str) -> Vec<String> {
+    if let Some(reference) = schema["$ref"].as_str() {
+        let name = reference.trim_start_matches("#/components/schemas/");
+        return conforms(spec, &spec["components"]["schemas"][name], value, at);
+    }
+    if let Some(branches) = schema["oneOf"].as_array() {
+        let matching = branches.iter().filter(|branch| conforms(spec, branch, value, at).is_empty()).count();
+        return match matching {
+            1 => Vec::new(),
+            n => vec![format!("{}: matches {} of the oneOf schemas", at, n)],
+        };
+    }
+
+    let types: Vec<&str> = match &schema["type"] {
+        Value::String(name) => vec![name.as_str()],
+        Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    let type_matches = |name: &str| match name {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_u64() || value.is_i64(),
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => false,
+    };
+    if !types.is_empty() && !types.iter().any(|name| type_matches(name)) {
+        return vec![format!("{}: {} is not of type {:?}", at, value, types)];
+    }
+    if let Some(allowed) = schema["enum"].as_array() {
+        if !allowed.contains(value) {
+            return vec![format!("{}: {} is not one of {:?}", at, value, allowed)];
+        }
+    }
+
+    let mut errors = Vec::new();
+    match value {
+        Value::String(text) if schema["format"] == "date-time" && DateTime::parse_from_rfc3339(text).is_err() => {
+            errors.push(format!("{}: {:?} is not a date-time", at, text));
+        }
+        Value::Array(items) if schema["items"].is_object() => {
+            for (index, item) in items.iter().enumerate() {
+                errors.extend(conforms(spec, &schema["items"], item, &format!("{}[{}]", at, index)));
+            }
+        }
+        Value::Object(fields) => {
+            let properties = schema["properties"].as_object();
+            for (name, field) in fields {
+                let at = format!("{}.{}", at, name);
+                match properties.and_then(|properties| properties.get(name)) {
+                    Some(property) => errors.extend(conforms(spec, property, field, &at)),
+                    None if schema["additionalProperties"].is_object() => {
+                        errors.extend(conforms(spec, &schema["additionalProperties"], field, &at))
+                    }
+                    None => errors.push(format!("{}: not in the schema", at)),
+                }
+            }
+            for required in schema["required"].as_array().into_iter().flatten().filter_map(Value::as_str) {
+                if !fields.contains_key(required) {
+                    errors.push(format!("{}.{}: required but missing", at, required));
+                }
+            }
+        }
+        _ => {}
+    }
+    errors
+}
+
+#[test]
+fn test_spec_describes_the_task_api() {
+    let spec: Value = serde_json::from_str(spec_json()).unwrap();
+    assert_eq!(spec["openapi"], "3.1.0");
+    assert_eq!(spec["servers"][0]["url"], "/v2");
+    assert_eq!(spec["servers"][1]["url"], "/v1");
+    for schema in ["Task", "CreateTask", "UpdateTask", "ReplaceTask"] {
+        assert!(spec["components"]["schemas"][schema].is_object(), "{} is missing", schema);
+    }
+}
+
+/// Walks the router's table of REST routes, so that a route added without
+/// documenting it fails here, as does a documented route that is not served.
+#[test]
+fn test_spec_documents_every_route() {
+    let spec: Value = serde_json::from_str(spec_json()).unwrap();
+    let documented = operations(&spec);
+    let routes: BTreeSet<(String, String)> = API_ROUTES
+        .iter()
+        .map(|(method, path)| (path.to_string(), method.as_str().to_lowercase()))
+        .collect();
+
+    let undocumented: Vec<_> = routes.difference(&documented).collect();
+    assert!(undocumented.is_empty(), "routes missing from the spec: {:?}", undocumented);
+    let unrouted: Vec<_> = documented.difference(&routes).collect();
+    assert!(unrouted.is_empty(), "documented operations not in the route table: {:?}", unrouted);
+}
+
+/// Exercises every documented operation against the real router, checking
+/// each response against the spec, so the spec fails this test when it
+/// drifts from what the handlers send.
+#[tokio::test]
+async fn test_responses_match_the_spec() {
+    let mut api = Exchanges::new().await;
+    let task = json!({
+        "title": "Write docs",
+        "description": "For the API",
+        "due_at": "2030-01-01T00:00:00Z",
+        "priority": "high",
+        "tags": ["Docs"],
+    });
+
+    api.json(Method::POST, "/tasks", "/tasks", task.clone(), 201).await;
+    api.json(Method::POST, "/tasks", "/tasks", json!({ "title": "Child", "description": "", "parent_id": 1 }), 201)
+        .await;
+    api.json(Method::POST, "/tasks", "/tasks", json!({ "title": "T", "description": "", "tags": ["no spaces"] }), 422)
+        .await;
+    api.check(Method::POST, "/tasks", "/tasks", Some(("application/json", "{".to_string())), 400).await;
+    api.check(Method::POST, "/tasks", "/tasks", Some(("text/plain", "task".to_string())), 415).await;
+
+    let list = api.check(Method::GET, "/tasks", "/tasks?tag=docs&sort=-priority", None, 200).await;
+    assert_eq!(list["data"]["tasks"][0]["tags"], json!(["docs"]));
+    api.check(Method::GET, "/tasks", "/tasks?sort=size", None, 400).await;
+
+    api.check(Method::GET, "/tasks/{id}", "/tasks/1", None, 200).await;
+    api.check(Method::GET, "/tasks/{id}", "/tasks/one", None, 400).await;
+    api.check(Method::GET, "/tasks/{id}", "/tasks/99", None, 404).await;
+
+    let replacement = json!({ "title": "Write the docs", "description": "", "completed": true, "tags": ["docs"] });
+    api.json(Method::PUT, "/tasks/{id}", "/tasks/1", replacement.clone(), 200).await;
+    api.json(Method::PUT, "/tasks/{id}", "/tasks/7", replacement, 201).await;
+    api.json(Method::PUT, "/tasks/{id}", "/tasks/1", json!({ "title": "Untitled" }), 400).await;
+    let cycle = json!({ "title": "Write the docs", "description": "", "completed": false, "parent_id": 2 });
+    api.json(Method::PUT, "/tasks/{id}", "/tasks/1", cycle, 409).await;
+    api.json(Method::PUT, "/tasks/{id}", "/tasks/1", json!({ "title": "", "description": "", "completed": false }), 422)
+        .await;
+
+    let merge = Some(("application/merge-patch+json", json!({ "due_at": null }).to_string()));
+    api.check(Method::PATCH, "/tasks/{id}", "/tasks/1", merge, 200).await;
+    let test = json!([{ "op": "test", "path": "/title", "value": "Other" }]).to_string();
+    api.check(Method::PATCH, "/tasks/{id}", "/tasks/1", Some(("application/json-patch+json", test)), 409).await;
+    api.check(Method::PATCH, "/tasks/{id}", "/tasks/1", Some(("text/plain", String::new())), 415).await;
+
+    let batch = |atomic: bool| {
+        json!({
+            "atomic": atomic,
+            "operations": [
+                { "op": "create", "task": { "title": "Batched", "description": "" } },
+                { "op": "update", "id": 2, "task": { "completed": true } },
+                { "op": "delete", "id": 42 },
+            ],
+        })
+    };
+    api.json(Method::POST, "/tasks/batch", "/tasks/batch", batch(true), 404).await;
+    api.json(Method::POST, "/tasks/batch", "/tasks/batch", batch(false), 207).await;
+    let batch = json!({ "operations": [{ "op": "update", "id": 2, "task": { "title": "Renamed" } }] });
+    api.json(Method::POST, "/tasks/batch", "/tasks/batch", batch, 200).await;
+
+    api.check(Method::DELETE, "/tasks/{id}", "/tasks/1", None, 409).await;
+    api.check(Method::DELETE, "/tasks/{id}", "/tasks/2", None, 200).await;
+    api.check(Method::DELETE, "/tasks/{id}", "/tasks/2", None, 404).await;
+
+    let trash = api.check(Method::GET, "/tasks/trash", "/tasks/trash", None, 200).await;
+    assert_eq!(trash["data"]["tasks"][0]["id"], 2);
+    api.check(Method::POST, "/tasks/{id}/restore", "/tasks/2/restore", None, 200).await;
+    api.check(Method::POST, "/tasks/{id}/restore", "/tasks/2/restore", None, 404).await;
+    api.check(Method::POST, "/tasks/{id}/restore", "/tasks/two/restore", None, 400).await;
+
+    let tags = json!({ "tags": ["Urgent"] });
+    api.json(Method::POST, "/tasks/{id}/tags", "/tasks/1/tags", tags.clone(), 200).await;
+    api.json(Method::POST, "/tasks/{id}/tags", "/tasks/99/tags", tags, 404).await;
+    api.json(Method::POST, "/tasks/{id}/tags", "/tasks/1/tags", json!({ "tags": ["no spaces"] }), 422).await;
+    api.check(Method::DELETE, "/tasks/{id}/tags/{tag}", "/tasks/1/tags/Urgent", None, 200).await;
+    api.check(Method::DELETE, "/tasks/{id}/tags/{tag}", "/tasks/1/tags/urgent", None, 404).await;
+    let tags = api.check(Method::GET, "/tags", "/tags", None, 200).await;
+    assert_eq!(tags["data"]["tags"][0], json!({ "tag": "docs", "count": 2 }));
+
+    api.json(Method::POST, "/tasks", "/tasks", json!({ "title": "Then", "description": "", "blocked_by": [1] }), 201)
+        .await;
+    let subtasks = api.check(Method::GET, "/tasks/{id}/subtasks", "/tasks/1/subtasks", None, 200).await;
+    assert_eq!(subtasks["data"]["tasks"][0]["id"], 2);
+    api.check(Method::GET, "/tasks/{id}/subtasks", "/tasks/99/subtasks", None, 404).await;
+    let graph = api.check(Method::GET, "/tasks/{id}/dependencies", "/tasks/1/dependencies", None, 200).await;
+    assert_eq!(graph["data"]["edges"].as_array().unwrap().len(), 1);
+    api.check(Method::GET, "/tasks/{id}/dependencies", "/tasks/one/dependencies", None, 400).await;
+
+    api.check(Method::GET, "/tasks/search", "/tasks/search?q=docs", None, 200).await;
+    api.check(Method::GET, "/tasks/search", "/tasks/search", None, 400).await;
+
+    let history = api.check(Method::GET, "/tasks/{id}/history", "/tasks/2/history", None, 200).await;
+    assert_eq!(history["data"]["events"][0]["action"], "created");
+    api.check(Method::GET, "/tasks/{id}/history", "/tasks/99/history", None, 404).await;
+    let audit = api.check(Method::GET, "/audit", "/audit?limit=2", None, 200).await;
+    assert!(audit["data"]["next_before"].is_u64());
+    api.check(Method::GET, "/audit", "/audit?since=yesterday", None, 400).await;
+
+    let webhook = json!({ "url": "http://localhost:9/hooks", "events": ["created"], "secret": "s3cret" });
+    api.json(Method::POST, "/webhooks", "/webhooks", webhook, 201).await;
+    api.json(Method::POST, "/webhooks", "/webhooks", json!({ "url": "ftp://localhost", "secret": "" }), 422).await;
+    api.check(Method::GET, "/webhooks", "/webhooks", None, 200).await;
+    api.check(Method::GET, "/webhooks/{id}", "/webhooks/1", None, 200).await;
+    api.check(Method::GET, "/webhooks/{id}/deliveries", "/webhooks/1/deliveries", None, 200).await;
+    api.check(Method::GET, "/webhooks/dead-letters", "/webhooks/dead-letters", None, 200).await;
+    api.check(Method::DELETE, "/webhooks/{id}", "/webhooks/1", None, 200).await;
+    api.check(Method::GET, "/webhooks/{id}", "/webhooks/1", None, 404).await;
+    api.check(Method::DELETE, "/webhooks/{id}", "/webhooks/1", None, 404).await;
+    api.check(Method::GET, "/webhooks/{id}/deliveries", "/webhooks/1/deliveries", None, 404).await;
+
+    let first = api.stream("/tasks/events", "/tasks/events").await;
+    assert!(first.starts_with("retry: "), "{}", first);
+
+    let documented = operations(&api.spec);
+    assert_eq!(api.covered, documented);
+}
+
+#[test]
+fn test_conforms_catches_drift() {
+    let spec: Value = serde_json::from_str(spec_json()).unwrap();
+    let schema = json!({ "$ref": "#/components/schemas/ErrorEnvelope" });
+    let response = json!({
+        "data": null,
+        "meta": {
+            "request_id": "d6c4a7f4-1b1e-4c55-9d7b-2bfb0a1f0e0b",
+            "timestamp": "2024-05-01T12:00:00Z",
+            "processing_time_ms": 0,
+        },
+        "errors": [{ "message": "Task not found" }],
+    });
+    assert!(conforms(&spec, &schema, &response, "$").is_empty());
+
+    let mut extra = response.clone();
+    extra["error"] = json!("Task not found");
+    assert_eq!(conforms(&spec, &schema, &extra, "$"), ["$.error: not in the schema"]);
+
+    let mut missing = response;
+    missing["meta"].as_object_mut().unwrap().remove("request_id");
+    assert_eq!(conforms(&spec, &schema, &missing, "$"), ["$.meta.request_id: required but missing"]);
+}
@@ -0,0 +1,92 @@
+use crate::models::Task;
+use crate::store::StoreError;
+use json_patch::{Patch, PatchErrorKind};
+use serde_json::Value;
+
+/// Media type for RFC 7396 JSON Merge Patch documents.
+pub const MERGE_PATCH: &str = "application/merge-patch+json";
+
+/// Media type for RFC 6902 JSON Patch documents.
+pub const JSON_PATCH: &str = "application/json-patch+json";
+
+/// Value of the `Accept-Patch` header advertising the supported formats.
+pub const ACCEPT_PATCH: &str = "application/merge-patch+json, application/json-patch+json";
+
+/// Fields the server maintains, which a patch must leave as they are.
+const READ_ONLY_FIELDS: &[&str] = &["id", "version", "created_at", "updated_at", "completed_at", "deleted_at"];
+
+/// A parsed `PATCH /tasks/{id}` request body.
+#[derive(Debug)]
+pub enum TaskPatch {
+    /// An RFC 7396 merge patch: a partial task where `null` removes a field.
+    Merge(Value),
+    /// An RFC 6902 list of operations, including `test` preconditions.
+    Json(Patch),
+}
+
+/// Why a patch document could not be read.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// The `Content-Type` is not a supported patch format.
+    UnsupportedType,
+    /// The body is not a valid document of its declared format.
+    Malformed(String),
+}
+
+impl TaskPatch {
+    /// Parse a patch body according to its `Content-Type`.
+    pub fn parse(content_type: Option<&str>, body: &[u8]) -> Result<TaskPatch, ParseError> {
+        let essence = content_type
+            .and_then(|ct| ct.split(';').next())
+            .map(|ct| ct.trim().to_ascii_lowercase())
+            .unwrap_or_default();
+
+        match essence.as_str() {
+            MERGE_PATCH => serde_json::from_slice(body)
+                .map(TaskPatch::Merge)
+                .map_err(|e| ParseError::Malformed(e.to_string())),
+            JSON_PATCH => serde_json::from_slice(body)
+                .map(TaskPatch::Json)
+                .map_err(|e| ParseError::Malformed(e.to_string())),
+            _ => Err(ParseError::UnsupportedType),
+        }
+    }
+
+    /// Apply the patch to a task, returning the patched copy.
+    ///
+    /// A JSON Patch is all-or-nothing: if any operation fails, including a
+    /// `test`, none of them take effect. A failed `test` is a
+    /// `StoreError::Conflict`; anything that would change the task's `id` or
+    /// `version`, or leave it without a valid value for every field, is a
+    /// `StoreError::Invalid`.
+    pub fn apply(&self, task: &Task) -> Result<Task, StoreError> {
+        let mut doc = serde_json::to_value(task).map_err(|e| StoreError::Invalid(e.to_string()))?;
+
+        match self {
+            TaskPatch::Merge(patch) => json_patch::merge(&mut doc, patch),
+            TaskPatch::Json(patch) => json_patch::patch(&mut doc, patch).map_err(|e| match e.kind {
+                PatchErrorKind::TestFailed => StoreError::Conflict(format!("Patch test failed: {}", e)),
+                _ => StoreError::Invalid(format!("Patch could not be applied: {}", e)),
+            })?,
+        }
+
+        let original = serde_json::to_value(task).map_err(|e| StoreError::Invalid(e.to_string()))?;
+        for field in READ_ONLY_FIELDS {
+            if doc.get(field) != original.get(field) {
+                return Err(StoreError::Invalid(format!("Task {} cannot be changed", field)));
+            }
+        }
+
+        let patched: Task = serde_json::from_value(doc)
+            .map_err(|e| StoreError::Invalid(format!("Patched task is invalid: {}", e)))?;
+        let errors = patched.validate();
+        if errors.is_empty() {
+            Ok(patched)
+        } else {
+            Err(StoreError::Invalid(errors.into_values().collect::<Vec<_>>().join("; ")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
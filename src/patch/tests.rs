@@ -0,0 +1,122 @@
+use super::*;
+use serde_json::json;
+use chrono::Utc;
+
+fn task() -> Task {
+    Task {
+        id: 1,
+        title: "Test Task".to_string(),
+        description: "Test Description".to_string(),
+        completed: false,
+        version: 3,
+        due_at: None,
+        priority: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        completed_at: None,
+        deleted_at: None,
+        tags: Default::default(),
+        parent_id: None,
+        blocked_by: Default::default(),
+    }
+}
+
+fn parse(content_type: &str, body: Value) -> TaskPatch {
+    TaskPatch::parse(Some(content_type), body.to_string().as_bytes()).unwrap()
+}
+
+#[test]
+fn test_parse_rejects_other_content_types() {
+    assert_eq!(
+        TaskPatch::parse(Some("application/json"), b"{}").unwrap_err(),
+        ParseError::UnsupportedType
+    );
+    assert_eq!(TaskPatch::parse(None, b"{}").unwrap_err(), ParseError::UnsupportedType);
+    assert!(matches!(
+        TaskPatch::parse(Some(JSON_PATCH), b"{\"op\":\"add\"}").unwrap_err(),
+        ParseError::Malformed(_)
+    ));
+}
+
+#[test]
+fn test_merge_patch() {
+    let patched = parse(MERGE_PATCH, json!({"title": "Merged", "completed": true}))
+        .apply(&task())
+        .unwrap();
+
+    assert_eq!(patched.title, "Merged");
+    assert_eq!(patched.description, "Test Description");
+    assert!(patched.completed);
+}
+
+#[test]
+fn test_merge_patch_cannot_remove_required_fields() {
+    let err = parse(MERGE_PATCH, json!({"completed": null})).apply(&task()).unwrap_err();
+    assert!(matches!(err, StoreError::Invalid(_)));
+}
+
+#[test]
+fn test_patch_validates_the_patched_task() {
+    let err = parse(MERGE_PATCH, json!({"title": ""})).apply(&task()).unwrap_err();
+    assert_eq!(err, StoreError::Invalid("Title is required".to_string()));
+
+    let long = "d".repeat(10_000);
+    let err = parse(JSON_PATCH, json!([{"op": "replace", "path": "/description", "value": long}]))
+        .apply(&task())
+        .unwrap_err();
+    assert!(matches!(err, StoreError::Invalid(message) if message.starts_with("Description must be at most")));
+}
+
+#[test]
+fn test_json_patch_with_test_operation() {
+    let patch = parse(
+        JSON_PATCH,
+        json!([
+            {"op": "test", "path": "/title", "value": "Test Task"},
+            {"op": "replace", "path": "/title", "value": "Patched"},
+            {"op": "replace", "path": "/completed", "value": true}
+        ]),
+    );
+
+    let patched = patch.apply(&task()).unwrap();
+    assert_eq!(patched.title, "Patched");
+    assert!(patched.completed);
+}
+
+#[test]
+fn test_json_patch_failed_test_is_a_conflict() {
+    let patch = parse(
+        JSON_PATCH,
+        json!([
+            {"op": "replace", "path": "/title", "value": "Patched"},
+            {"op": "test", "path": "/completed", "value": true}
+        ]),
+    );
+
+    assert!(matches!(patch.apply(&task()).unwrap_err(), StoreError::Conflict(_)));
+}
+
+#[test]
+fn test_patch_cannot_change_id_or_version() {
+    let err = parse(MERGE_PATCH, json!({"id": 99})).apply(&task()).unwrap_err();
+    assert_eq!(err, StoreError::Invalid("Task id cannot be changed".to_string()));
+
+    let err = parse(JSON_PATCH, json!([{"op": "replace", "path": "/version", "value": 1}]))
+        .apply(&task())
+        .unwrap_err();
+    assert_eq!(err, StoreError::Invalid("Task version cannot be changed".to_string()));
+}
+
+#[test]
+fn test_patch_rejects_timestamp_changes() {
+    let err = parse(MERGE_PATCH, json!({"created_at": "2000-01-01T00:00:00Z"}))
+        .apply(&task())
+        .unwrap_err();
+    assert_eq!(err, StoreError::Invalid("Task created_at cannot be changed".to_string()));
+
+    let patched = parse(MERGE_PATCH, json!({"priority": "urgent", "due_at": "2030-01-01T00:00:00Z"}))
+        .apply(&task())
+        .unwrap();
+    assert_eq!(patched.priority, Some(crate::models::Priority::Urgent));
+    assert!(patched.due_at.is_some());
+}
@@ -0,0 +1,144 @@
+use crate::models::{self, Task};
+use chrono::{DateTime, Utc};
+use std::cmp::Ordering;
+
+/// Field a task list can be sorted by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    Id,
+    DueAt,
+    Priority,
+}
+
+/// Filters and ordering for `GET /tasks`, read from the query string.
+///
+/// * `tag=a&tag=b` keeps tasks with any of the tags; with `tag_match=all`,
+///   only tasks with every one of them. The store's tag index does this
+///   filtering, so `apply` leaves tags alone.
+/// * `overdue=true` keeps only open tasks whose due date has passed.
+/// * `include_deleted=true` lists the tasks in the trash along with the live
+///   ones; the store keeps them apart, so the handler merges them in.
+/// * `sort=id|due_at|priority` orders the list, ascending; a leading `-`
+///   (`sort=-priority`) reverses it. Tasks without a due date or priority
+///   always come last, and ties are broken by ID.
+///
+/// Unknown parameters are ignored.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ListQuery {
+    /// Normalized tags to filter by; empty for no tag filter.
+    pub tags: Vec<String>,
+    /// Whether tasks need all of `tags` rather than any of them.
+    pub all_tags: bool,
+    pub overdue: bool,
+    pub include_deleted: bool,
+    pub sort: SortKey,
+    pub descending: bool,
+}
+
+impl Default for ListQuery {
+    fn default() -> Self {
+        ListQuery {
+            tags: Vec::new(),
+            all_tags: false,
+            overdue: false,
+            include_deleted: false,
+            sort: SortKey::Id,
+            descending: false,
+        }
+    }
+}
+
+impl ListQuery {
+    /// Parse a request's query string, returning an error message for an
+    /// invalid value.
+    pub fn parse(query: Option<&str>) -> Result<ListQuery, String> {
+        let mut list_query = ListQuery::default();
+        let Some(query) = query else {
+            return Ok(list_query);
+        };
+
+        for (name, value) in form_urlencoded::parse(query.as_bytes()) {
+            match name.as_ref() {
+                "tag" => list_query.tags.push(models::normalize_tag(&value)),
+                "tag_match" => {
+                    list_query.all_tags = match value.as_ref() {
+                        "any" => false,
+                        "all" => true,
+                        _ => return Err(format!("Invalid tag_match value: {}", value)),
+                    }
+                }
+                "overdue" => list_query.overdue = parse_bool(&name, &value)?,
+                "include_deleted" => list_query.include_deleted = parse_bool(&name, &value)?,
+                "sort" => {
+                    let (descending, field) = match value.strip_prefix('-') {
+                        Some(field) => (true, field),
+                        None => (false, value.as_ref()),
+                    };
+                    list_query.sort = match field {
+                        "id" => SortKey::Id,
+                        "due_at" => SortKey::DueAt,
+                        "priority" => SortKey::Priority,
+                        _ => return Err(format!("Invalid sort field: {}", field)),
+                    };
+                    list_query.descending = descending;
+                }
+                _ => {}
+            }
+        }
+        Ok(list_query)
+    }
+
+    /// Whether a task passes the tag filter, for lists that do not come
+    /// from the tag index.
+    pub fn has_tags(&self, task: &Task) -> bool {
+        let mut tags = self.tags.iter();
+        match self.all_tags {
+            _ if self.tags.is_empty() => true,
+            true => tags.all(|tag| task.tags.contains(tag)),
+            false => tags.any(|tag| task.tags.contains(tag)),
+        }
+    }
+
+    /// Filter and sort `tasks`, judging overdue tasks against `now`.
+    pub fn apply(&self, mut tasks: Vec<Task>, now: DateTime<Utc>) -> Vec<Task> {
+        if self.overdue {
+            tasks.retain(|task| task.is_overdue(now));
+        }
+        tasks.sort_by(|a, b| {
+            let ordering = match self.sort {
+                SortKey::Id => a.id.cmp(&b.id),
+                SortKey::DueAt => self.compare_optional(a.due_at, b.due_at),
+                SortKey::Priority => self.compare_optional(a.priority, b.priority),
+            };
+            ordering.then(a.id.cmp(&b.id))
+        });
+        if self.descending && self.sort == SortKey::Id {
+            tasks.reverse();
+        }
+        tasks
+    }
+
+    /// Compare two optional sort values in the requested direction, with
+    /// missing values last either way.
+    fn compare_optional<T: Ord>(&self, a: Option<T>, b: Option<T>) -> Ordering {
+        match (a, b) {
+            (Some(a), Some(b)) if self.descending => b.cmp(&a),
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+}
+
+/// Parse a boolean query parameter.
+fn parse_bool(name: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(format!("Invalid {} value: {}", name, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,91 @@
+use super::*;
+use crate::models::{CreateTask, Priority};
+use chrono::Duration;
+
+fn task(id: u64, due_in_days: Option<i64>, priority: Option<Priority>, now: DateTime<Utc>) -> Task {
+    Task::new(
+        id,
+        CreateTask {
+            title: format!("Task {}", id),
+            description: String::new(),
+            due_at: due_in_days.map(|days| now + Duration::days(days)),
+            priority,
+            tags: Default::default(),
+            parent_id: None,
+            blocked_by: Default::default(),
+        },
+        now,
+    )
+}
+
+fn ids(tasks: &[Task]) -> Vec<u64> {
+    tasks.iter().map(|task| task.id).collect()
+}
+
+#[test]
+fn test_parse() {
+    assert_eq!(ListQuery::parse(None).unwrap(), ListQuery::default());
+    assert_eq!(
+        ListQuery::parse(Some("overdue=true&sort=-priority&page=2&tag=Home&tag=work&tag_match=all")).unwrap(),
+        ListQuery {
+            tags: vec!["home".to_string(), "work".to_string()],
+            all_tags: true,
+            overdue: true,
+            include_deleted: false,
+            sort: SortKey::Priority,
+            descending: true,
+        }
+    );
+    assert!(ListQuery::parse(Some("include_deleted=1")).unwrap().include_deleted);
+    assert!(ListQuery::parse(Some("overdue=maybe")).is_err());
+    assert!(ListQuery::parse(Some("include_deleted=yes")).is_err());
+    assert!(ListQuery::parse(Some("sort=title")).is_err());
+    assert!(ListQuery::parse(Some("tag_match=some")).is_err());
+}
+
+#[test]
+fn test_overdue_filter() {
+    let now = Utc::now();
+    let mut done = task(3, Some(-1), None, now);
+    done.completed = true;
+    let tasks = vec![task(1, Some(-1), None, now), task(2, Some(1), None, now), done, task(4, None, None, now)];
+
+    let query = ListQuery::parse(Some("overdue=true")).unwrap();
+    assert_eq!(ids(&query.apply(tasks, now)), vec![1]);
+}
+
+#[test]
+fn test_sort_missing_values_last() {
+    let now = Utc::now();
+    let tasks = || {
+        vec![
+            task(1, None, Some(Priority::Low), now),
+            task(2, Some(5), None, now),
+            task(3, Some(1), Some(Priority::Urgent), now),
+            task(4, Some(5), Some(Priority::Low), now),
+        ]
+    };
+
+    let by_due = ListQuery::parse(Some("sort=due_at")).unwrap();
+    assert_eq!(ids(&by_due.apply(tasks(), now)), vec![3, 2, 4, 1]);
+
+    let by_due_desc = ListQuery::parse(Some("sort=-due_at")).unwrap();
+    assert_eq!(ids(&by_due_desc.apply(tasks(), now)), vec![2, 4, 3, 1]);
+
+    let by_priority_desc = ListQuery::parse(Some("sort=-priority")).unwrap();
+    assert_eq!(ids(&by_priority_desc.apply(tasks(), now)), vec![3, 1, 4, 2]);
+
+    let by_id_desc = ListQuery::parse(Some("sort=-id")).unwrap();
+    assert_eq!(ids(&by_id_desc.apply(tasks(), now)), vec![4, 3, 2, 1]);
+}
+
+#[test]
+fn test_has_tags() {
+    let now = Utc::now();
+    let mut tagged = task(1, None, None, now);
+    tagged.tags = ["home".to_string()].into();
+
+    assert!(ListQuery::default().has_tags(&tagged));
+    assert!(ListQuery::parse(Some("tag=home&tag=work")).unwrap().has_tags(&tagged));
+    assert!(!ListQuery::parse(Some("tag=home&tag=work&tag_match=all")).unwrap().has_tags(&tagged));
+}
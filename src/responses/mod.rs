@@ -0,0 +1,323 @@
+use crate::audit::AuditEvent;
+use crate::models::{Dependency, Task};
+use crate::versioning::ApiVersion;
+use crate::webhooks::{DeadLetter, Delivery, Webhook};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tokio::time::Instant;
+use utoipa::openapi::schema::{ObjectBuilder, Type};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// What every response says about the request it answers.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Meta {
+    pub request_id: String,
+    /// When the response was sent, in RFC 3339.
+    pub timestamp: String,
+    pub processing_time_ms: u64,
+}
+
+impl Meta {
+    /// The metadata of a request with the given ID, started at
+    /// `start_time`, as of now.
+    pub fn new(request_id: Uuid, start_time: Instant) -> Self {
+        Meta {
+            request_id: request_id.to_string(),
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            processing_time_ms: start_time.elapsed().as_millis() as u64,
+        }
+    }
+}
+
+/// One thing wrong with a request.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorDetail {
+    pub message: String,
+    /// The request field the error is about, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+}
+
+/// The v2 body of a successful response: the endpoint's payload in `data`,
+/// and no `errors`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Envelope<T> {
+    pub data: T,
+    pub meta: Meta,
+    pub errors: Vec<ErrorDetail>,
+}
+
+/// The v2 body of a failed response: null `data`, and at least one error.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorEnvelope {
+    #[schema(schema_with = null)]
+    pub data: Option<()>,
+    pub meta: Meta,
+    pub errors: Vec<ErrorDetail>,
+}
+
+fn null() -> ObjectBuilder {
+    ObjectBuilder::new().schema_type(Type::Null)
+}
+
+/// The v1 body: the payload's fields alongside the metadata's.
+#[derive(Debug, Serialize)]
+pub struct Flat<T> {
+    #[serde(flatten)]
+    pub payload: T,
+    #[serde(flatten)]
+    pub meta: Meta,
+}
+
+/// A response body in the shape of a version of the API.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum Body<T> {
+    V1(Flat<T>),
+    V2(Envelope<T>),
+    V2Error(ErrorEnvelope),
+}
+
+impl<T> Body<T> {
+    /// The body of a successful response carrying `payload`.
+    pub fn success(version: ApiVersion, payload: T, meta: Meta) -> Self {
+        match version {
+            ApiVersion::V1 => Body::V1(Flat { payload, meta }),
+            ApiVersion::V2 => Body::V2(Envelope {
+                data: payload,
+                meta,
+                errors: Vec::new(),
+            }),
+        }
+    }
+}
+
+impl Body<ErrorBody> {
+    /// The body of a failed response.
+    pub fn failure(version: ApiVersion, error: ErrorBody, meta: Meta) -> Self {
+        match version {
+            ApiVersion::V1 => Body::V1(Flat { payload: error, meta }),
+            ApiVersion::V2 => Body::V2Error(ErrorEnvelope {
+                data: None,
+                meta,
+                errors: error.details(),
+            }),
+        }
+    }
+}
+
+/// Why a request failed, as v1 reports it.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub error: String,
+    /// The message for each invalid field, on 422 Unprocessable Entity.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fields: Option<BTreeMap<String, String>>,
+    /// The media types the endpoint can produce, on 406 Not Acceptable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supported: Option<Vec<String>>,
+}
+
+impl ErrorBody {
+    pub fn new(error: impl Into<String>) -> Self {
+        ErrorBody {
+            error: error.into(),
+            ..ErrorBody::default()
+        }
+    }
+
+    /// The errors as v2 lists them: one per invalid field, or the message
+    /// alone if no field is at fault.
+    pub fn details(self) -> Vec<ErrorDetail> {
+        let supported = self.supported.map(|types| format!("{}; supported: {}", self.error, types.join(", ")));
+        match self.fields {
+            Some(fields) if !fields.is_empty() => fields
+                .into_iter()
+                .map(|(field, message)| ErrorDetail {
+                    message,
+                    field: Some(field),
+                })
+                .collect(),
+            _ => vec![ErrorDetail {
+                message: supported.unwrap_or(self.error),
+                field: None,
+            }],
+        }
+    }
+}
+
+/// `GET /`.
+#[derive(Debug, Serialize)]
+pub struct WelcomeBody {
+    pub message: &'static str,
+}
+
+/// `GET /health`.
+#[derive(Debug, Serialize)]
+pub struct HealthBody {
+    pub status: &'static str,
+    pub memory_usage: MemoryUsage,
+}
+
+/// The host's memory, in kilobytes.
+#[derive(Debug, Serialize)]
+pub struct MemoryUsage {
+    pub total: u64,
+    pub free: u64,
+    pub available: u64,
+    pub buffers: u64,
+    pub cached: u64,
+    pub swap_total: u64,
+    pub swap_free: u64,
+}
+
+/// A single task: `GET`, `PUT` and `PATCH /tasks/{id}`, restoring a task
+/// and changing its tags.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TaskBody {
+    pub task: Task,
+    /// Present when the request changed the task.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<&'static str>,
+}
+
+/// `POST /tasks`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedTaskBody {
+    pub id: u64,
+    pub task: Task,
+    pub message: &'static str,
+}
+
+/// A change that returns nothing but confirmation: `DELETE /tasks/{id}`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MessageBody {
+    pub message: &'static str,
+}
+
+/// A list of tasks: `GET /tasks` and `GET /tasks/trash`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TaskListBody {
+    pub tasks: Vec<Task>,
+}
+
+/// `POST /tasks/batch`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchBody {
+    pub atomic: bool,
+    /// The outcome of each operation, in order.
+    pub results: Vec<BatchResult>,
+}
+
+/// One batch operation's status, with the task it wrote or why it failed.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchResult {
+    pub index: usize,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task: Option<Task>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// `GET /tasks/{id}/subtasks`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SubtasksBody {
+    pub parent_id: u64,
+    pub tasks: Vec<Task>,
+}
+
+/// `GET /tasks/{id}/dependencies`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DependencyGraphBody {
+    pub task_id: u64,
+    pub tasks: Vec<Task>,
+    pub edges: Vec<Dependency>,
+}
+
+/// `GET /tasks/{id}/history`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HistoryBody {
+    pub task_id: u64,
+    pub events: Vec<AuditEvent>,
+}
+
+/// `GET /audit`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditLogBody {
+    /// Newest first.
+    pub events: Vec<AuditEvent>,
+    /// The `before` value that fetches the next, older page; present when
+    /// there is one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_before: Option<u64>,
+}
+
+/// `GET /tasks/search`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchBody {
+    pub query: String,
+    /// Best first.
+    pub results: Vec<SearchResult>,
+}
+
+/// A task matching a search, with how well it matches.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchResult {
+    pub score: f64,
+    pub highlights: Highlights,
+    pub task: Task,
+}
+
+/// A search result's text as escaped HTML, with the matching words in
+/// `<mark>`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Highlights {
+    pub title: String,
+    pub description: String,
+}
+
+/// `GET /tags`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TagsBody {
+    pub tags: Vec<TagCount>,
+}
+
+/// A tag in use, and on how many tasks.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: usize,
+}
+
+/// A single webhook: creating, getting and deleting one.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhookBody {
+    pub webhook: Webhook,
+    /// Present when the request changed the webhook.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<&'static str>,
+}
+
+/// `GET /webhooks`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhookListBody {
+    pub webhooks: Vec<Webhook>,
+}
+
+/// `GET /webhooks/{id}/deliveries`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeliveriesBody {
+    pub webhook_id: u64,
+    pub deliveries: Vec<Delivery>,
+}
+
+/// `GET /webhooks/dead-letters`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeadLettersBody {
+    pub dead_letters: Vec<DeadLetter>,
+}
+
+#[cfg(test)]
+mod tests;
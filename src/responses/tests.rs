@@ -0,0 +1,82 @@
+use super::*;
+use crate::formats::Format;
+use insta::assert_json_snapshot;
+use serde_json::json;
+
+fn task() -> Task {
+    serde_json::from_value(json!({
+        "id": 1,
+        "title": "Write docs",
+        "description": "For the API",
+        "completed": false,
+        "version": 1,
+        "due_at": "2030-01-01T00:00:00Z",
+        "priority": "high",
+        "tags": ["docs"],
+        "created_at": "2026-10-18T09:00:00Z",
+        "updated_at": "2026-10-18T09:00:00Z",
+    }))
+    .unwrap()
+}
+
+fn meta() -> Meta {
+    Meta {
+        request_id: "d6c4a7f4-1b1e-4c55-9d7b-2bfb0a1f0e0b".to_string(),
+        timestamp: "2026-10-18T09:00:00.125+00:00".to_string(),
+        processing_time_ms: 3,
+    }
+}
+
+fn created() -> CreatedTaskBody {
+    CreatedTaskBody {
+        id: 1,
+        task: task(),
+        message: "Task created successfully",
+    }
+}
+
+fn invalid() -> ErrorBody {
+    ErrorBody {
+        fields: Some(BTreeMap::from([
+            ("title".to_string(), "must not be empty".to_string()),
+            ("tags".to_string(), "must not contain spaces".to_string()),
+        ])),
+        ..ErrorBody::new("Validation failed")
+    }
+}
+
+#[test]
+fn test_v1_success_is_flat() {
+    assert_json_snapshot!(Body::success(ApiVersion::V1, created(), meta()));
+}
+
+#[test]
+fn test_v2_success_is_enveloped() {
+    assert_json_snapshot!(Body::success(ApiVersion::V2, created(), meta()));
+}
+
+#[test]
+fn test_v1_failure_is_flat() {
+    assert_json_snapshot!(Body::failure(ApiVersion::V1, invalid(), meta()));
+}
+
+#[test]
+fn test_v2_failure_lists_errors() {
+    assert_json_snapshot!(Body::failure(ApiVersion::V2, invalid(), meta()));
+
+    let not_acceptable = ErrorBody {
+        supported: Some(vec!["application/json".to_string(), "application/cbor".to_string()]),
+        ..ErrorBody::new("Not acceptable")
+    };
+    assert_json_snapshot!(Body::failure(ApiVersion::V2, not_acceptable, meta()));
+}
+
+#[test]
+fn test_flat_bodies_encode_in_every_format() {
+    // Flattening serializes maps of unknown length, which the binary formats
+    // must accept
+    for format in [Format::Json, Format::Cbor, Format::MessagePack] {
+        let body = Body::success(ApiVersion::V1, created(), meta());
+        assert!(format.encode(&body).is_ok(), "{:?}", format);
+    }
+}
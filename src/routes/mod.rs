@@ -0,0 +1,212 @@
+use std::sync::Arc;
+
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Incoming;
+use hyper::header::{HeaderValue, VARY};
+use hyper::{Method, Request, Response};
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::audit::{self, AuditContext};
+use crate::formats::{Format, TASK_FORMATS, TASK_LIST_FORMATS};
+use crate::graphql::GraphQl;
+use crate::handlers::*;
+use crate::idempotency::IdempotencyCache;
+use crate::server::Body;
+use crate::store::Store;
+use crate::versioning::{self, ApiVersion, Deprecation};
+use crate::webhooks::Webhooks;
+use crate::{compression, forms, utils};
+
+/// The routes of the REST API, by method and path template as the OpenAPI
+/// document names them. These are the routes of the versioned route groups,
+/// less the HTML pages; GraphQL, WebSockets and the like are not part of it.
+///
+/// `router` dispatches these, and the OpenAPI tests fail for any that the
+/// document leaves out, so add new REST routes to both.
+#[cfg(test)]
+pub(crate) const API_ROUTES: &[(Method, &str)] = &[
+    (Method::GET, "/tasks"),
+    (Method::POST, "/tasks"),
+    (Method::POST, "/tasks/batch"),
+    (Method::GET, "/tasks/search"),
+    (Method::GET, "/tasks/trash"),
+    (Method::GET, "/tasks/events"),
+    (Method::GET, "/tasks/{id}"),
+    (Method::PUT, "/tasks/{id}"),
+    (Method::PATCH, "/tasks/{id}"),
+    (Method::DELETE, "/tasks/{id}"),
+    (Method::POST, "/tasks/{id}/restore"),
+    (Method::POST, "/tasks/{id}/tags"),
+    (Method::DELETE, "/tasks/{id}/tags/{tag}"),
+    (Method::GET, "/tasks/{id}/subtasks"),
+    (Method::GET, "/tasks/{id}/dependencies"),
+    (Method::GET, "/tasks/{id}/history"),
+    (Method::GET, "/tags"),
+    (Method::GET, "/audit"),
+    (Method::POST, "/webhooks"),
+    (Method::GET, "/webhooks"),
+    (Method::GET, "/webhooks/dead-letters"),
+    (Method::GET, "/webhooks/{id}"),
+    (Method::DELETE, "/webhooks/{id}"),
+    (Method::GET, "/webhooks/{id}/deliveries"),
+];
+
+/// The router function takes in a hyper request and matches on the method and
+/// path to call the corresponding handler.  If the request is invalid, it
+/// returns a 404 Not Found response.  If the handler returns an error, it
+/// returns a 500 Internal Server Error response.
+pub(crate) async fn router(
+    req: Request<Incoming>,
+    store: Arc<Store>,
+    idempotency: Arc<IdempotencyCache>,
+    webhooks: Arc<Webhooks>,
+    graphql: Arc<GraphQl>,
+    origins: Arc<AllowedOrigins>,
+    v1_deprecation: Option<Deprecation>,
+) -> Response<Body> {
+    let request_id = Uuid::new_v4();
+    let start = Instant::now();
+    
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    
+    // Split the path into segments so routes like /tasks/{id}/edit can be
+    // matched as slice patterns
+    let path_segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+
+    // The routes match the path without its /v1 or /v2 prefix; the version
+    // decides the shape of the body and the headers the response carries
+    let (resolved, segments) = match versioning::resolve(&path_segments, req.headers()) {
+        Ok(resolution) => resolution,
+        Err(message) => return handle_version_not_acceptable(&message, request_id, start).map(BodyExt::boxed_unsync),
+    };
+
+    // The event stream stays open, so it has a streaming body and skips
+    // format negotiation and compression
+    if method == Method::GET && segments == ["tasks", "events"] {
+        let mut response = handle_task_events(req.headers(), &store, request_id);
+        resolved.decorate(&mut response, segments, v1_deprecation);
+        return response;
+    }
+
+    // Task endpoints negotiate their response format from the Accept header;
+    // only the task list can be exported as CSV
+    let supported = if method == Method::GET && segments == ["tasks"] {
+        TASK_LIST_FORMATS
+    } else {
+        TASK_FORMATS
+    };
+    let negotiated = Format::negotiate(req.headers(), supported);
+    let request_headers = req.headers().clone();
+    let format = negotiated.unwrap_or(Format::Json);
+
+    // Changes made while handling the request are audited as made by it
+    let context = AuditContext::from_request(req.headers(), request_id);
+    // and answered in the shape of the version it asked for
+    let version = resolved.version().unwrap_or(ApiVersion::V1);
+    let result = audit::scope(context, versioning::scope(version, async {
+        match (method, segments) {
+            (Method::GET, []) if utils::prefers_html(req.headers()) => {
+                handle_index_page(store, request_id, start).await
+            }
+            (Method::GET, []) => handle_root(request_id, start).await,
+            (Method::GET, ["health"]) => handle_health(request_id, start).await,
+            (Method::GET, ["openapi.json"]) => handle_openapi().await,
+            (Method::GET, ["docs"]) => handle_docs().await,
+            (Method::GET, ["ws"]) => handle_websocket(req, store, &origins, request_id).await,
+            (Method::GET, ["graphql"]) => handle_graphql_get(req, &graphql, &origins, request_id).await,
+            (Method::POST, ["graphql"]) => handle_graphql(req, &graphql, request_id, start).await,
+            (Method::POST, ["tasks"]) if forms::is_form(req.headers()) => {
+                handle_create_task_form(req, store, request_id).await
+            }
+            (Method::GET, ["tasks", "new"]) => handle_new_task_page(req.headers(), request_id).await,
+            (Method::GET, ["tasks", task_id, "edit"]) => {
+                handle_edit_task_page(req.headers(), store, task_id, request_id).await
+            }
+            (Method::POST, ["tasks", task_id, "edit"]) => {
+                handle_edit_task_form(req, store, task_id, request_id).await
+            }
+            (_, ["tasks", ..] | ["tags"] | ["audit"] | ["webhooks", ..]) if negotiated.is_none() => {
+                handle_not_acceptable(supported, request_id, start).await
+            }
+            (Method::POST, ["tasks"]) => {
+                handle_create_task(req, store, &idempotency, format, request_id, start).await
+            }
+            (Method::POST, ["tasks", "batch"]) => {
+                handle_batch_tasks(req, store, format, request_id, start).await
+            }
+            (Method::PUT, ["tasks", task_id]) => {
+                handle_update_task(req, store, task_id, format, request_id, start).await
+            }
+            (Method::PATCH, ["tasks", task_id]) => {
+                handle_patch_task(req, store, task_id, format, request_id, start).await
+            }
+            (Method::DELETE, ["tasks", task_id]) => {
+                handle_delete_task(req.headers(), store, task_id, format, request_id, start).await
+            }
+            (Method::POST, ["tasks", task_id, "tags"]) => {
+                handle_add_tags(req, store, task_id, format, request_id, start).await
+            }
+            (Method::DELETE, ["tasks", task_id, "tags", tag]) => {
+                handle_remove_tag(req.headers(), store, task_id, tag, format, request_id, start).await
+            }
+            (Method::POST, ["tasks", task_id, "restore"]) => {
+                handle_restore_task(req.headers(), store, task_id, format, request_id, start).await
+            }
+            (Method::GET, ["tasks", "trash"]) => handle_list_trash(store, format, request_id, start).await,
+            (Method::GET, ["tasks", "search"]) => {
+                handle_search_tasks(store, req.uri().query(), format, request_id, start).await
+            }
+            (Method::GET, ["tasks", task_id, "subtasks"]) => {
+                handle_list_subtasks(store, task_id, format, request_id, start).await
+            }
+            (Method::GET, ["tasks", task_id, "dependencies"]) => {
+                handle_dependency_graph(store, task_id, format, request_id, start).await
+            }
+            (Method::GET, ["tasks", task_id, "history"]) => {
+                handle_task_history(store, task_id, format, request_id, start).await
+            }
+            (Method::GET, ["tags"]) => handle_list_tags(store, format, request_id, start).await,
+            (Method::GET, ["audit"]) => handle_audit_log(store, req.uri().query(), format, request_id, start).await,
+            (Method::POST, ["webhooks"]) => handle_create_webhook(req, webhooks, format, request_id, start).await,
+            (Method::GET, ["webhooks"]) => handle_list_webhooks(webhooks, format, request_id, start).await,
+            (Method::GET, ["webhooks", "dead-letters"]) => {
+                handle_list_dead_letters(webhooks, format, request_id, start).await
+            }
+            (Method::GET, ["webhooks", webhook_id]) => {
+                handle_get_webhook(webhooks, webhook_id, format, request_id, start).await
+            }
+            (Method::DELETE, ["webhooks", webhook_id]) => {
+                handle_delete_webhook(webhooks, webhook_id, format, request_id, start).await
+            }
+            (Method::GET, ["webhooks", webhook_id, "deliveries"]) => {
+                handle_list_deliveries(webhooks, webhook_id, format, request_id, start).await
+            }
+            (Method::GET, ["tasks"]) => handle_list_tasks(store, req.uri().query(), format, request_id).await,
+            (Method::GET, ["tasks", task_id]) => {
+                handle_get_task(req.headers(), store, task_id, format, request_id, start).await
+            }
+            _ => Ok(Response::builder()
+                .status(404)
+                .body(Full::new(Bytes::from("Not Found")))
+                .unwrap()),
+        }
+    }))
+    .await;
+
+    let response = result.unwrap_or_else(|err| {
+        Response::builder()
+            .status(500)
+            .body(Full::new(Bytes::from(format!("Internal Server Error: {}", err))))
+            .unwrap()
+    });
+    let mut response = compression::compress_response(&request_headers, response).await;
+    resolved.decorate(&mut response, segments, v1_deprecation);
+    // Accept also decides between the index page and the JSON greeting
+    if segments.is_empty() {
+        response.headers_mut().append(VARY, HeaderValue::from_static("accept"));
+    }
+    response.map(BodyExt::boxed_unsync)
+} 
@@ -0,0 +1,207 @@
+use crate::models::Task;
+use rust_stemmers::{Algorithm, Stemmer};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::OnceLock;
+
+/// Words too common to be worth indexing or searching for.
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "in", "is", "it", "of", "on",
+    "or", "the", "to", "was", "with",
+];
+
+/// BM25 term-frequency saturation.
+const K1: f64 = 1.2;
+
+/// BM25 length normalization.
+const B: f64 = 0.75;
+
+/// How much a match in each field counts: title, then description.
+const FIELD_WEIGHTS: [f64; 2] = [2.0, 1.0];
+
+/// Longest description snippet returned with a result, in characters.
+pub const SNIPPET_LEN: usize = 160;
+
+/// A word in a text: its index term and where it appears.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Token {
+    /// The lowercased, stemmed word.
+    pub term: String,
+    /// Byte range of the word in the original text.
+    pub start: usize,
+    pub end: usize,
+}
+
+fn stemmer() -> &'static Stemmer {
+    static STEMMER: OnceLock<Stemmer> = OnceLock::new();
+    STEMMER.get_or_init(|| Stemmer::create(Algorithm::English))
+}
+
+/// Split text into index terms.
+///
+/// Words are runs of letters and digits. Each is lowercased and stemmed, so
+/// "Running" and "runs" both become "run"; stop words are dropped.
+pub fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(word_start)) => {
+                let word = text[word_start..i].to_lowercase();
+                if !STOP_WORDS.contains(&word.as_str()) {
+                    tokens.push(Token {
+                        term: stemmer().stem(&word).into_owned(),
+                        start: word_start,
+                        end: i,
+                    });
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    tokens
+}
+
+/// The distinct terms of a search query.
+pub fn query_terms(query: &str) -> BTreeSet<String> {
+    tokenize(query).into_iter().map(|token| token.term).collect()
+}
+
+/// An inverted index over task titles and descriptions.
+///
+/// Kept up to date by `Store` on every write, so searching only visits the
+/// tasks that contain a query term.
+#[derive(Default)]
+pub struct SearchIndex {
+    /// Occurrences of each term in each task, per field.
+    postings: HashMap<String, HashMap<u64, [u32; 2]>>,
+    /// Number of terms in each task, per field.
+    lengths: HashMap<u64, [u32; 2]>,
+    /// Number of terms across all tasks, per field.
+    total_lengths: [u64; 2],
+}
+
+impl SearchIndex {
+    pub fn add(&mut self, task: &Task) {
+        let mut lengths = [0; 2];
+        for (field, text) in [&task.title, &task.description].into_iter().enumerate() {
+            for token in tokenize(text) {
+                self.postings.entry(token.term).or_default().entry(task.id).or_default()[field] += 1;
+                lengths[field] += 1;
+            }
+            self.total_lengths[field] += u64::from(lengths[field]);
+        }
+        self.lengths.insert(task.id, lengths);
+    }
+
+    /// Remove a task, as it was when added.
+    pub fn remove(&mut self, task: &Task) {
+        let Some(lengths) = self.lengths.remove(&task.id) else {
+            return;
+        };
+        for (total, length) in self.total_lengths.iter_mut().zip(lengths) {
+            *total -= u64::from(length);
+        }
+        for text in [&task.title, &task.description] {
+            for token in tokenize(text) {
+                if let Some(postings) = self.postings.get_mut(&token.term) {
+                    postings.remove(&task.id);
+                    if postings.is_empty() {
+                        self.postings.remove(&token.term);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Rank the tasks matching any of `terms` with BM25, best first.
+    ///
+    /// Title matches count double. Ties are broken by task ID.
+    pub fn search(&self, terms: &BTreeSet<String>) -> Vec<(u64, f64)> {
+        let count = self.lengths.len() as f64;
+        let average = |field: usize| (self.total_lengths[field] as f64 / count).max(1.0);
+
+        let mut scores: HashMap<u64, f64> = HashMap::new();
+        for term in terms {
+            let Some(postings) = self.postings.get(term) else {
+                continue;
+            };
+            let frequency = postings.len() as f64;
+            let idf = (1.0 + (count - frequency + 0.5) / (frequency + 0.5)).ln();
+            for (id, occurrences) in postings {
+                let lengths = self.lengths[id];
+                let score: f64 = (0..2)
+                    .map(|field| {
+                        let tf = f64::from(occurrences[field]);
+                        let norm = 1.0 - B + B * f64::from(lengths[field]) / average(field);
+                        FIELD_WEIGHTS[field] * idf * tf * (K1 + 1.0) / (tf + K1 * norm)
+                    })
+                    .sum();
+                *scores.entry(*id).or_default() += score;
+            }
+        }
+
+        let mut ranked: Vec<(u64, f64)> = scores.into_iter().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked
+    }
+}
+
+/// Escape text for inclusion in HTML.
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Highlight the words of `text` matching `terms`.
+///
+/// The result is HTML: the text is escaped and each match is wrapped in
+/// `<mark>`. Text longer than `max_len` characters is cut down to a window
+/// around the first match, with `…` marking what was left out.
+pub fn highlight(text: &str, terms: &BTreeSet<String>, max_len: usize) -> String {
+    let matches: Vec<Token> = tokenize(text).into_iter().filter(|t| terms.contains(&t.term)).collect();
+
+    // Pick a window of at most `max_len` characters, starting a little
+    // before the first match so it has some context
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let (mut from, mut to) = (0, chars.len());
+    if chars.len() > max_len {
+        let first = matches.first().map_or(0, |m| chars.partition_point(|&(i, _)| i < m.start));
+        from = first.saturating_sub(max_len / 4).min(chars.len() - max_len);
+        to = from + max_len;
+    }
+    let byte = |index: usize| chars.get(index).map_or(text.len(), |&(i, _)| i);
+    let (start, end) = (byte(from), byte(to));
+
+    let mut html = String::new();
+    if from > 0 {
+        html.push('…');
+    }
+    let mut cursor = start;
+    for m in matches.iter().filter(|m| m.start >= start && m.end <= end) {
+        html.push_str(&escape_html(&text[cursor..m.start]));
+        html.push_str("<mark>");
+        html.push_str(&escape_html(&text[m.start..m.end]));
+        html.push_str("</mark>");
+        cursor = m.end;
+    }
+    html.push_str(&escape_html(&text[cursor..end]));
+    if to < chars.len() {
+        html.push('…');
+    }
+    html
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,85 @@
+use super::*;
+use crate::models::CreateTask;
+
+fn task(id: u64, title: &str, description: &str) -> Task {
+    Task::new(
+        id,
+        CreateTask {
+            title: title.to_string(),
+            description: description.to_string(),
+            due_at: None,
+            priority: None,
+            tags: Default::default(),
+            parent_id: None,
+            blocked_by: Default::default(),
+        },
+        chrono::Utc::now(),
+    )
+}
+
+fn terms(query: &str) -> BTreeSet<String> {
+    query_terms(query)
+}
+
+#[test]
+fn test_tokenize() {
+    let tokens = tokenize("Running the Tests, quickly!");
+    let terms: Vec<&str> = tokens.iter().map(|token| token.term.as_str()).collect();
+    assert_eq!(terms, vec!["run", "test", "quick"]);
+    assert_eq!((tokens[1].start, tokens[1].end), (12, 17));
+
+    assert_eq!(query_terms("runs RUNNING run"), BTreeSet::from(["run".to_string()]));
+    assert!(query_terms("the and of").is_empty());
+}
+
+#[test]
+fn test_index_add_and_remove() {
+    let mut index = SearchIndex::default();
+    let groceries = task(1, "Buy groceries", "Milk and eggs");
+    index.add(&groceries);
+    index.add(&task(2, "Write report", "Quarterly numbers"));
+
+    let ids = |index: &SearchIndex, query: &str| -> Vec<u64> {
+        index.search(&terms(query)).into_iter().map(|(id, _)| id).collect()
+    };
+    assert_eq!(ids(&index, "egg"), vec![1]);
+    assert_eq!(ids(&index, "reports"), vec![2]);
+    assert!(ids(&index, "holiday").is_empty());
+
+    index.remove(&groceries);
+    assert!(ids(&index, "egg").is_empty());
+    assert_eq!(ids(&index, "report"), vec![2]);
+}
+
+#[test]
+fn test_ranking() {
+    let mut index = SearchIndex::default();
+    index.add(&task(1, "Plan trip", "Book the deploy window"));
+    index.add(&task(2, "Deploy release", "Ship it"));
+    index.add(&task(3, "Unrelated", "Nothing here"));
+
+    // A title match outranks a description match
+    let ranked = index.search(&terms("deploy"));
+    assert_eq!(ranked.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![2, 1]);
+    assert!(ranked[0].1 > ranked[1].1);
+
+    // Matching more of the query scores higher
+    let ranked = index.search(&terms("plan deploy"));
+    assert_eq!(ranked[0].0, 1);
+}
+
+#[test]
+fn test_highlight() {
+    let html = highlight("Fix <b> & running tests", &terms("run"), usize::MAX);
+    assert_eq!(html, "Fix &lt;b&gt; &amp; <mark>running</mark> tests");
+
+    // Long text is cut to a window around the first match
+    let text = format!("{} needle {}", "word ".repeat(40), "word ".repeat(40));
+    let html = highlight(&text, &terms("needle"), 40);
+    assert!(html.starts_with('…'));
+    assert!(html.ends_with('…'));
+    assert!(html.contains("<mark>needle</mark>"));
+    assert_eq!(html.replace("<mark>", "").replace("</mark>", "").chars().count(), 42);
+
+    assert_eq!(highlight("No match here", &terms("absent"), 5), "No ma…");
+}
@@ -0,0 +1,323 @@
+use std::convert::Infallible;
+use std::future::{Future, IntoFuture};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response};
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
+use tonic::transport::server::TcpIncoming;
+
+use crate::config::Config;
+use crate::graphql::GraphQl;
+use crate::handlers::AllowedOrigins;
+use crate::grpc;
+use crate::idempotency::IdempotencyCache;
+use crate::routes;
+use crate::store::Store;
+use crate::versioning::Deprecation;
+use crate::webhooks::{RetryPolicy, Webhooks};
+
+/// The body of every response the server sends.
+pub type Body = UnsyncBoxBody<Bytes, Infallible>;
+
+/// The error binding or serving can fail with.
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
+
+/// A handler for a route added with [`Server::route`].
+///
+/// Implemented for `async` functions and closures taking the request.
+pub trait Handler: Send + Sync + 'static {
+    fn call(&self, req: Request<Incoming>) -> BoxFuture<Response<Full<Bytes>>>;
+}
+
+impl<F, Fut> Handler for F
+where
+    F: Fn(Request<Incoming>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Response<Full<Bytes>>> + Send + 'static,
+{
+    fn call(&self, req: Request<Incoming>) -> BoxFuture<Response<Full<Bytes>>> {
+        Box::pin(self(req))
+    }
+}
+
+/// Middleware added with [`Server::middleware`], which sees every request
+/// before the routes do and every response after.
+///
+/// Implemented for `async` functions and closures taking the request and the
+/// [`Next`] to pass it on to, such as:
+///
+/// ```ignore
+/// Server::new(config).middleware(|req, next: Next| async move {
+///     let mut response = next.run(req).await;
+///     response.headers_mut().insert("x-served-by", HeaderValue::from_static("tasks"));
+///     response
+/// })
+/// ```
+pub trait Middleware: Send + Sync + 'static {
+    fn call(&self, req: Request<Incoming>, next: Next) -> BoxFuture<Response<Body>>;
+}
+
+impl<F, Fut> Middleware for F
+where
+    F: Fn(Request<Incoming>, Next) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Response<Body>> + Send + 'static,
+{
+    fn call(&self, req: Request<Incoming>, next: Next) -> BoxFuture<Response<Body>> {
+        Box::pin(self(req, next))
+    }
+}
+
+/// The rest of the middleware after the one running, and then the routes.
+pub struct Next {
+    middleware: Arc<[Arc<dyn Middleware>]>,
+    position: usize,
+    app: Arc<App>,
+}
+
+impl Next {
+    /// Pass `req` on, returning the response to it.
+    pub async fn run(self, req: Request<Incoming>) -> Response<Body> {
+        match self.middleware.get(self.position).cloned() {
+            Some(middleware) => {
+                let next = Next {
+                    position: self.position + 1,
+                    ..self
+                };
+                middleware.call(req, next).await
+            }
+            None => self.app.handle(req).await,
+        }
+    }
+}
+
+/// A route added with [`Server::route`].
+struct Route {
+    method: Method,
+    segments: Vec<String>,
+    handler: Box<dyn Handler>,
+}
+
+/// What every connection shares: the routes and the state behind them.
+struct App {
+    store: Arc<Store>,
+    idempotency: Arc<IdempotencyCache>,
+    webhooks: Arc<Webhooks>,
+    graphql: Arc<GraphQl>,
+    origins: Arc<AllowedOrigins>,
+    v1_deprecation: Option<Deprecation>,
+    routes: Vec<Route>,
+}
+
+impl App {
+    async fn handle(&self, req: Request<Incoming>) -> Response<Body> {
+        let path = req.uri().path();
+        let route = self.routes.iter().find(|route| {
+            route.method == req.method() && route.segments.iter().map(String::as_str).eq(segments(path))
+        });
+        if let Some(route) = route {
+            return route.handler.call(req).await.map(BodyExt::boxed_unsync);
+        }
+        routes::router(
+            req,
+            Arc::clone(&self.store),
+            Arc::clone(&self.idempotency),
+            Arc::clone(&self.webhooks),
+            Arc::clone(&self.graphql),
+            Arc::clone(&self.origins),
+            self.v1_deprecation,
+        )
+        .await
+    }
+}
+
+/// The non-empty segments of a path.
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|s| !s.is_empty())
+}
+
+/// Builds the task API server: its HTTP routes, which can be extended and
+/// wrapped in middleware, and the gRPC service, over a shared store.
+///
+/// ```ignore
+/// let server = Server::new(Config::from_env()).bind().await?;
+/// println!("Listening on {}", server.addr());
+/// server.await?;
+/// ```
+pub struct Server {
+    config: Config,
+    store: Option<Arc<Store>>,
+    routes: Vec<Route>,
+    middleware: Vec<Arc<dyn Middleware>>,
+}
+
+impl Server {
+    pub fn new(config: Config) -> Self {
+        Server {
+            config,
+            store: None,
+            routes: Vec::new(),
+            middleware: Vec::new(),
+        }
+    }
+
+    /// Serve the tasks in `store` rather than in a new, empty one.
+    pub fn store(mut self, store: Arc<Store>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Answer `method` requests for `path` with `handler`.
+    ///
+    /// The path must match exactly, ignoring empty segments. Added routes are
+    /// matched before the task API's, so they can also replace them.
+    pub fn route(mut self, method: Method, path: &str, handler: impl Handler) -> Self {
+        self.routes.push(Route {
+            method,
+            segments: segments(path).map(String::from).collect(),
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// Wrap every HTTP request in `middleware`. The first added runs
+    /// outermost.
+    pub fn middleware(mut self, middleware: impl Middleware) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Bind the HTTP and gRPC listeners to the configured addresses.
+    ///
+    /// Nothing is served until the returned [`Bound`] is awaited; a port of
+    /// zero picks a free one, which [`Bound::addr`] tells.
+    pub async fn bind(self) -> Result<Bound, Error> {
+        let config = self.config;
+        let listener = TcpListener::bind(config.addr).await?;
+        let grpc_listener = TcpListener::bind(config.grpc_addr).await?;
+        let addr = listener.local_addr()?;
+        let grpc_addr = grpc_listener.local_addr()?;
+
+        let store = self.store.unwrap_or_else(|| Arc::new(Store::new()));
+        let policy = RetryPolicy {
+            max_attempts: config.webhook_max_attempts,
+            base_delay: config.webhook_backoff,
+        };
+        let webhooks = Arc::new(Webhooks::new(policy, config.webhook_allowed_hosts));
+        let grpc = grpc::router(Arc::clone(&store))?;
+        let app = Arc::new(App {
+            idempotency: Arc::new(IdempotencyCache::new(config.idempotency_ttl)),
+            webhooks: Arc::clone(&webhooks),
+            graphql: Arc::new(GraphQl::new(Arc::clone(&store), config.dev_mode)),
+            origins: Arc::new(AllowedOrigins::new(config.ws_allowed_origins)),
+            store: Arc::clone(&store),
+            v1_deprecation: config.v1_deprecation,
+            routes: self.routes,
+        });
+        let middleware: Arc<[Arc<dyn Middleware>]> = self.middleware.into();
+
+        let serve = async move {
+            spawn_trash_purge(Arc::clone(&store), config.trash_retention, config.trash_purge_interval);
+            webhooks.spawn_worker(store);
+            tokio::task::spawn(async move {
+                if let Err(err) = grpc.serve_with_incoming(TcpIncoming::from(grpc_listener)).await {
+                    eprintln!("Error serving gRPC: {}", err);
+                }
+            });
+
+            loop {
+                let (stream, _) = listener.accept().await?;
+                let io = TokioIo::new(stream);
+                let app = Arc::clone(&app);
+                let middleware = Arc::clone(&middleware);
+
+                tokio::task::spawn(async move {
+                    let service = service_fn(move |req| {
+                        let next = Next {
+                            middleware: Arc::clone(&middleware),
+                            position: 0,
+                            app: Arc::clone(&app),
+                        };
+                        async move { Ok::<_, Infallible>(next.run(req).await) }
+                    });
+                    if let Err(err) = http1::Builder::new().serve_connection(io, service).with_upgrades().await {
+                        eprintln!("Error serving connection: {}", err);
+                    }
+                });
+            }
+        };
+        Ok(Bound {
+            addr,
+            grpc_addr,
+            serve: Box::pin(serve),
+        })
+    }
+}
+
+/// A server bound to its addresses, which serves until the listener fails
+/// once awaited.
+///
+/// Dropping it stops accepting connections. The connections already open,
+/// the gRPC service and the background tasks run on until the runtime shuts
+/// down.
+pub struct Bound {
+    addr: SocketAddr,
+    grpc_addr: SocketAddr,
+    serve: BoxFuture<Result<(), Error>>,
+}
+
+impl Bound {
+    /// The address the HTTP API is served on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The address the gRPC service is served on.
+    pub fn grpc_addr(&self) -> SocketAddr {
+        self.grpc_addr
+    }
+}
+
+impl IntoFuture for Bound {
+    type Output = Result<(), Error>;
+    type IntoFuture = BoxFuture<Result<(), Error>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        self.serve
+    }
+}
+
+/// Spawns the background task that permanently removes tasks which have been
+/// in the trash for longer than `retention`, checking every `interval`.
+fn spawn_trash_purge(store: Arc<Store>, retention: Duration, interval: Duration) {
+    // A retention too long to represent never expires anything
+    let Ok(retention) = chrono::Duration::from_std(retention) else {
+        return;
+    };
+    tokio::task::spawn(async move {
+        let mut ticks = tokio::time::interval(interval);
+        loop {
+            ticks.tick().await;
+            let Some(cutoff) = chrono::Utc::now().checked_sub_signed(retention) else {
+                continue;
+            };
+            let purged = store.purge_trash(cutoff).await;
+            if !purged.is_empty() {
+                tracing::info!(count = purged.len(), ids = ?purged, "Purged deleted tasks from the trash");
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,94 @@
+use super::*;
+use crate::models::CreateTask;
+use crate::versioning::Deprecation;
+use crate::testing::{self, TestServer};
+use hyper::header::HeaderValue;
+use hyper::StatusCode;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+
+#[tokio::test]
+async fn test_serves_the_given_store() {
+    let store = Arc::new(Store::new());
+    let task = CreateTask {
+        title: "Embedded".to_string(),
+        description: String::new(),
+        due_at: None,
+        priority: None,
+        tags: Default::default(),
+        parent_id: None,
+        blocked_by: Default::default(),
+    };
+    store.create_task(task).await.unwrap();
+
+    let bound = Server::new(testing::config()).store(store).bind().await.unwrap();
+    assert_ne!(bound.addr().port(), 0);
+    assert_ne!(bound.grpc_addr().port(), 0);
+    let url = format!("http://{}/tasks/1", bound.addr());
+    tokio::spawn(bound.into_future());
+
+    let client = Client::builder(TokioExecutor::new()).build_http::<Full<Bytes>>();
+    let response = client.get(url.parse().unwrap()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = response.into_body().collect().await.unwrap().to_bytes();
+    assert!(String::from_utf8_lossy(&body).contains("Embedded"));
+}
+
+#[tokio::test]
+async fn test_deprecates_v1_on_the_configured_schedule() {
+    let since = chrono::Utc::now();
+    let config = Config {
+        v1_deprecation: Some(Deprecation {
+            since,
+            sunset: since + chrono::Duration::days(180),
+        }),
+        ..testing::config()
+    };
+    let bound = Server::new(config).bind().await.unwrap();
+    let url = format!("http://{}", bound.addr());
+    tokio::spawn(bound.into_future());
+
+    let client = Client::builder(TokioExecutor::new()).build_http::<Full<Bytes>>();
+    let response = client.get(format!("{}/v1/tasks", url).parse().unwrap()).await.unwrap();
+    assert_eq!(response.headers()["deprecation"], format!("@{}", since.timestamp()).as_str());
+    assert!(response.headers().contains_key("sunset"));
+    let response = client.get(format!("{}/v2/tasks", url).parse().unwrap()).await.unwrap();
+    assert!(response.headers().get("deprecation").is_none());
+}
+
+#[tokio::test]
+async fn test_routes_and_middleware() {
+    let server = TestServer::start_with(|server| {
+        server
+            .route(Method::GET, "/version", |_req| async { Response::new(Full::new(Bytes::from("1.2.3"))) })
+            .route(Method::GET, "/health", |_req| async { Response::new(Full::new(Bytes::from("replaced"))) })
+            .middleware(|req, next: Next| async move {
+                let mut response = next.run(req).await;
+                response.headers_mut().append("x-trace", HeaderValue::from_static("outer"));
+                response
+            })
+            .middleware(|req: Request<Incoming>, next: Next| async move {
+                if req.uri().path() == "/forbidden" {
+                    return Response::builder().status(403).body(Full::new(Bytes::new()).boxed_unsync()).unwrap();
+                }
+                let mut response = next.run(req).await;
+                response.headers_mut().append("x-trace", HeaderValue::from_static("inner"));
+                response
+            })
+    })
+    .await;
+
+    let response = server.get("/version").send().await;
+    assert_eq!((response.status, response.text()), (StatusCode::OK, "1.2.3"));
+    let trace: Vec<_> = response.headers.get_all("x-trace").iter().collect();
+    assert_eq!(trace, ["inner", "outer"]);
+
+    // Added routes come first, and the task API's still answer the rest
+    assert_eq!(server.get("/health").send().await.text(), "replaced");
+    assert_eq!(server.get("/tasks").send().await.status, StatusCode::OK);
+    assert_eq!(server.get("/version/2").send().await.status, StatusCode::NOT_FOUND);
+
+    let response = server.get("/forbidden").send().await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    assert_eq!(response.header("x-trace"), "outer");
+}
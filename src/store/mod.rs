@@ -1,35 +1,109 @@
 use std::sync::RwLock;
-use std::collections::HashMap;
-use crate::models::{Task, CreateTask, UpdateTask};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use chrono::{DateTime, Utc};
+use crate::audit::{self, AuditAction, AuditEvent, AuditLog, AuditQuery};
+use crate::events::{EventBus, TaskEvent};
+use crate::models::{BatchOperation, Dependency, DependencyGraph, Task, CreateTask, ReplaceTask, UpdateTask};
+use crate::query::ListQuery;
+use task_map::TaskMap;
+use tokio::sync::broadcast;
 
-#[derive(Default)]
+mod task_map;
+
+/// Why a conditional store operation did not happen.
+#[derive(Debug, PartialEq, Eq)]
+pub enum StoreError {
+    /// No task has the given ID.
+    NotFound,
+    /// The task exists but the caller's precondition rejected it.
+    PreconditionFailed,
+    /// The change conflicts with the task's current state.
+    Conflict(String),
+    /// The change would leave the task invalid.
+    Invalid(String),
+    /// The change would leave a field of the task invalid, such as a
+    /// `parent_id` naming a task that does not exist.
+    InvalidField(&'static str, String),
+    /// Not applied because another operation in an atomic batch failed.
+    Aborted,
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::NotFound => write!(f, "Task not found"),
+            StoreError::PreconditionFailed => write!(f, "Precondition failed"),
+            StoreError::Conflict(reason) | StoreError::Invalid(reason) | StoreError::InvalidField(_, reason) => {
+                write!(f, "{}", reason)
+            }
+            StoreError::Aborted => write!(f, "Not applied because another operation failed"),
+        }
+    }
+}
+
+/// The task store.
+///
+/// Every change is recorded in an audit log, attributed to the request it
+/// was made for (see `audit::scope`), and published on an event bus. Locks
+/// are always taken in the order `next_id`, `tasks`, `audit`.
 pub struct Store {
-    tasks: RwLock<HashMap<u64, Task>>,
+    tasks: RwLock<TaskMap>,
     next_id: RwLock<u64>,
+    audit: RwLock<AuditLog>,
+    events: EventBus,
+}
+
+impl Default for Store {
+    fn default() -> Self {
+        Store::new()
+    }
 }
 
 impl Store {
     pub fn new() -> Self {
         Store {
-            tasks: RwLock::new(HashMap::new()),
+            tasks: RwLock::new(TaskMap::default()),
             next_id: RwLock::new(1),
+            audit: RwLock::new(AuditLog::default()),
+            events: EventBus::default(),
         }
     }
 
-    pub async fn create_task(&self, create_task: CreateTask) -> u64 {
+    /// Records a change to a task in the audit log, as made by the running
+    /// request, and publishes it to event subscribers.
+    fn record(&self, action: AuditAction, before: Option<&Task>, after: Option<&Task>, timestamp: DateTime<Utc>) {
+        let mut log = self.audit.write().unwrap();
+        if let Some(event) = log.record(&audit::current(), action, before, after, timestamp) {
+            self.events.publish(TaskEvent {
+                id: event.id,
+                action,
+                task_id: event.task_id,
+                task: after.cloned(),
+            });
+        }
+    }
+
+    /// Subscribes to changes, as described on `EventBus::subscribe`.
+    pub fn subscribe(&self, last_event_id: Option<u64>) -> (Vec<TaskEvent>, broadcast::Receiver<TaskEvent>) {
+        self.events.subscribe(last_event_id)
+    }
+
+    /// Creates a task, returning it as stored.
+    ///
+    /// Fails if the task's parent or blocking tasks do not exist.
+    pub async fn create_task(&self, create_task: CreateTask) -> Result<Task, StoreError> {
         let mut next_id = self.next_id.write().unwrap();
+        let mut tasks = self.tasks.write().unwrap();
         let id = *next_id;
-        *next_id += 1;
 
-        let task = Task {
-            id,
-            title: create_task.title,
-            description: create_task.description,
-            completed: false,
-        };
-
-        self.tasks.write().unwrap().insert(id, task);
-        id
+        let now = Utc::now();
+        let task = Task::new(id, create_task, now);
+        check_relations(&tasks, &task, None)?;
+        *next_id += 1;
+        self.record(AuditAction::Created, None, Some(&task), now);
+        tasks.insert(id, task.clone());
+        Ok(task)
     }
 
 
@@ -45,32 +119,569 @@ impl Store {
     /// completed status. If the value for any of these fields is `None`, the
     /// corresponding field on the task will not be updated.
     ///
-    /// Returns `None` if the task with the given `id` does not exist.
+    /// Fails if the task with the given `id` does not exist, or if the
+    /// update breaks the rules on its relations (see `update_task_if`).
+    pub async fn update_task(&self, id: u64, update: UpdateTask) -> Result<Task, StoreError> {
+        self.update_task_if(id, update, |_| true).await
+    }
+
+    /// Updates a task only if `precondition` accepts its current state.
+    ///
+    /// The check and the update happen under a single write lock, so two
+    /// clients updating from the same version cannot both succeed.
+    pub async fn update_task_if(
+        &self,
+        id: u64,
+        update: UpdateTask,
+        precondition: impl FnOnce(&Task) -> bool,
+    ) -> Result<Task, StoreError> {
+        self.modify_task(id, |task| {
+            if !precondition(task) {
+                return Err(StoreError::PreconditionFailed);
+            }
+            let mut task = task.clone();
+            task.update(update);
+            Ok(task)
+        })
+        .await
+    }
+
+    /// Replaces a task with the result of `apply`, under a single write lock.
+    ///
+    /// `apply` sees the current task and returns its replacement, or an error
+    /// that leaves the task untouched. The replacement always keeps the
+    /// task's ID and gets the next version, whatever `apply` set them to, and
+    /// its timestamps are maintained as for every other write.
+    pub async fn modify_task(
+        &self,
+        id: u64,
+        apply: impl FnOnce(&Task) -> Result<Task, StoreError>,
+    ) -> Result<Task, StoreError> {
+        let mut tasks = self.tasks.write().unwrap();
+        let task = tasks.get(&id).ok_or(StoreError::NotFound)?;
+        let mut modified = apply(task)?;
+        modified.id = id;
+        modified.version = task.version + 1;
+        let now = Utc::now();
+        stamp(&mut modified, Some(task), now);
+        check_relations(&tasks, &modified, Some(task))?;
+        self.record(AuditAction::Updated, Some(task), Some(&modified), now);
+        tasks.insert(id, modified.clone());
+        Ok(modified)
+    }
+
+    /// Replaces the task with the given ID, creating it if it does not exist.
+    ///
+    /// `precondition` sees the current task, if any, and can veto the write.
+    /// A created task starts at version 1; a replaced one gets the next
+    /// version. Returns the stored task and whether it was created.
+    ///
+    /// Client-chosen IDs advance the ID counter past them, so `create_task`
+    /// never hands out an ID that is already taken; `u64::MAX` leaves no ID
+    /// past it and is invalid. An ID held by a task in the trash is a
+    /// conflict: the task has to be restored first.
+    pub async fn replace_task(
+        &self,
+        id: u64,
+        replacement: ReplaceTask,
+        precondition: impl FnOnce(Option<&Task>) -> bool,
+    ) -> Result<(Task, bool), StoreError> {
+        let Some(after) = id.checked_add(1) else {
+            return Err(StoreError::Invalid("ID out of range".to_string()));
+        };
+        // Same lock order as `create_task`, so the two cannot deadlock
+        let mut next_id = self.next_id.write().unwrap();
+        let mut tasks = self.tasks.write().unwrap();
+
+        if tasks.trashed(&id).is_some() {
+            return Err(StoreError::Conflict(format!(
+                "Task {} is in the trash; restore it before replacing it",
+                id
+            )));
+        }
+        let current = tasks.get(&id);
+        if !precondition(current) {
+            return Err(StoreError::PreconditionFailed);
+        }
+        let created = current.is_none();
+        let version = current.map_or(1, |task| task.version + 1);
+
+        let now = Utc::now();
+        let mut task = Task {
+            id,
+            title: replacement.title,
+            description: replacement.description,
+            completed: replacement.completed,
+            version,
+            due_at: replacement.due_at,
+            priority: replacement.priority,
+            tags: replacement.tags,
+            parent_id: replacement.parent_id,
+            blocked_by: replacement.blocked_by,
+            created_at: now,
+            updated_at: now,
+            completed_at: None,
+            deleted_at: None,
+        };
+        stamp(&mut task, current, now);
+        check_relations(&tasks, &task, current)?;
+        let action = if created { AuditAction::Created } else { AuditAction::Updated };
+        self.record(action, current, Some(&task), now);
+        tasks.insert(id, task.clone());
+        if after > *next_id {
+            *next_id = after;
+        }
+        Ok((task, created))
+    }
+
+    /// Moves a task to the trash, returning it as deleted.
+    ///
+    /// The task keeps its ID and gets the next version and a `deleted_at`
+    /// timestamp. It no longer shows up anywhere but `list_trash` until it
+    /// is restored, or purged for good (see `purge_trash`).
+    ///
+    /// Fails if the task does not exist, or if it still has subtasks or
+    /// blocks other tasks.
+    pub async fn delete_task(&self, id: u64) -> Result<Task, StoreError> {
+        self.delete_task_if(id, |_| true).await
+    }
+
+    /// Deletes a task only if `precondition` accepts its current state.
+    ///
+    /// Fails as for `delete_task` as well.
+    pub async fn delete_task_if(
+        &self,
+        id: u64,
+        precondition: impl FnOnce(&Task) -> bool,
+    ) -> Result<Task, StoreError> {
+        let mut tasks = self.tasks.write().unwrap();
+        let task = tasks.get(&id).ok_or(StoreError::NotFound)?;
+        if !precondition(task) {
+            return Err(StoreError::PreconditionFailed);
+        }
+        check_delete(&tasks, id)?;
+        let now = Utc::now();
+        let (task, deleted) = soft_delete(&mut tasks, id, now);
+        self.record(AuditAction::Deleted, Some(&task), Some(&deleted), now);
+        Ok(deleted)
+    }
+
+    /// Restores a task from the trash, returning it.
+    ///
+    /// Same as `restore_task_if` without a precondition.
+    pub async fn restore_task(&self, id: u64) -> Result<Task, StoreError> {
+        self.restore_task_if(id, |_| true).await
+    }
+
+    /// Restores a task from the trash only if `precondition` accepts its
+    /// deleted state.
+    ///
+    /// The task gets the next version and its `deleted_at` is cleared. Fails
+    /// with `NotFound` if the task is not in the trash, and with `Conflict`
+    /// if its parent or blocking tasks are gone.
+    pub async fn restore_task_if(
+        &self,
+        id: u64,
+        precondition: impl FnOnce(&Task) -> bool,
+    ) -> Result<Task, StoreError> {
+        let mut tasks = self.tasks.write().unwrap();
+        let deleted = tasks.trashed(&id).ok_or(StoreError::NotFound)?;
+        if !precondition(deleted) {
+            return Err(StoreError::PreconditionFailed);
+        }
+        let mut task = deleted.clone();
+        task.version += 1;
+        task.deleted_at = None;
+        let now = Utc::now();
+        stamp(&mut task, Some(deleted), now);
+        // The task was valid when deleted, so a missing relation is down to
+        // what happened since: a conflict rather than bad input
+        check_relations(&tasks, &task, Some(deleted)).map_err(|err| match err {
+            StoreError::InvalidField(_, reason) => StoreError::Conflict(reason),
+            err => err,
+        })?;
+        self.record(AuditAction::Restored, Some(deleted), Some(&task), now);
+        tasks.insert(id, task.clone());
+        Ok(task)
+    }
+
+    /// Lists the tasks in the trash, by ID.
+    pub async fn list_trash(&self) -> Vec<Task> {
+        self.tasks.read().unwrap().trash_values().cloned().collect()
+    }
+
+    /// Permanently removes every task that was moved to the trash before
+    /// `cutoff`, returning their IDs.
+    pub async fn purge_trash(&self, cutoff: DateTime<Utc>) -> Vec<u64> {
+        let mut tasks = self.tasks.write().unwrap();
+        let now = Utc::now();
+        let purged = tasks.purge(cutoff);
+        for task in &purged {
+            self.record(AuditAction::Purged, Some(task), None, now);
+        }
+        purged.iter().map(|task| task.id).collect()
+    }
+
+    /// Lists every recorded change to a task, oldest first.
+    ///
+    /// The history outlives the task: it is kept after the task is purged,
+    /// for as long as the audit log retains it.
+    pub async fn history(&self, task_id: u64) -> Vec<AuditEvent> {
+        self.audit.read().unwrap().history(task_id).into_iter().cloned().collect()
+    }
+
+    /// Lists a page of the recorded changes matching `query`, newest first,
+    /// with the cursor for the next page, as `AuditLog::query` does.
+    pub async fn audit_events(&self, query: &AuditQuery) -> (Vec<AuditEvent>, Option<u64>) {
+        let log = self.audit.read().unwrap();
+        let (events, next_before) = log.query(query);
+        (events.into_iter().cloned().collect(), next_before)
+    }
 
-    pub async fn update_task(&self, id: u64, update: UpdateTask) -> Option<Task> {
+    /// Applies a batch of operations under a single write lock.
+    ///
+    /// Returns one result per operation, in order: the created, updated or
+    /// deleted task. Deletes move tasks to the trash, as `delete_task` does.
+    /// With `atomic`, the first failure rolls back every earlier operation
+    /// and the rest are skipped; all of them then report
+    /// `StoreError::Aborted` except the one that failed. Without it, each
+    /// operation stands on its own.
+    pub async fn apply_batch(
+        &self,
+        operations: Vec<BatchOperation>,
+        atomic: bool,
+    ) -> Vec<Result<Task, StoreError>> {
+        // Same lock order as `create_task`, so the two cannot deadlock
+        let mut next_id = self.next_id.write().unwrap();
         let mut tasks = self.tasks.write().unwrap();
-        if let Some(task) = tasks.get_mut(&id) {
-            if let Some(title) = update.title {
-                task.title = title;
+        let first_id = *next_id;
+        let total = operations.len();
+
+        // Every applied operation with the previous state of the task it
+        // touched, so an atomic batch can be rolled back and the rest audited
+        let mut applied: Vec<(AuditAction, Option<Task>, Task, DateTime<Utc>)> = Vec::new();
+        let mut results = Vec::with_capacity(total);
+        for operation in operations {
+            let (id, action) = match &operation {
+                BatchOperation::Create { .. } => (*next_id, AuditAction::Created),
+                BatchOperation::Update { id, .. } => (*id, AuditAction::Updated),
+                BatchOperation::Delete { id, .. } => (*id, AuditAction::Deleted),
+            };
+            let previous = tasks.get(&id).cloned();
+
+            let now = Utc::now();
+            let result = apply_operation(&mut tasks, &mut next_id, operation, now);
+            match &result {
+                Ok(task) => applied.push((action, previous, task.clone(), now)),
+                Err(_) if atomic => {
+                    for (_, previous, task, _) in applied.into_iter().rev() {
+                        match previous {
+                            Some(previous) => tasks.insert(task.id, previous),
+                            None => tasks.remove(&task.id),
+                        };
+                    }
+                    *next_id = first_id;
+
+                    let failed = results.len();
+                    let mut err = result.err();
+                    return (0..total)
+                        .map(|i| Err(if i == failed { err.take().unwrap() } else { StoreError::Aborted }))
+                        .collect();
+                }
+                Err(_) => {}
             }
-            if let Some(description) = update.description {
-                task.description = description;
+            results.push(result);
+        }
+        for (action, previous, task, now) in &applied {
+            self.record(*action, previous.as_ref(), Some(task), *now);
+        }
+        results
+    }
+
+    pub async fn list_tasks(&self) -> Vec<Task> {
+        self.tasks.read().unwrap().values().cloned().collect()
+    }
+
+    /// Lists the tasks with any of `tags`, or with all of them if `all` is
+    /// set.
+    ///
+    /// Uses the tag index, so only matching tasks are visited.
+    pub async fn list_tasks_tagged(&self, tags: &[String], all: bool) -> Vec<Task> {
+        self.tasks.read().unwrap().tagged(tags, all).into_iter().cloned().collect()
+    }
+
+    /// Lists the tasks matching `query`, filtered and sorted as it describes,
+    /// judging overdue tasks against `now`.
+    pub async fn query_tasks(&self, query: &ListQuery, now: DateTime<Utc>) -> Vec<Task> {
+        let mut tasks = if query.tags.is_empty() {
+            self.list_tasks().await
+        } else {
+            self.list_tasks_tagged(&query.tags, query.all_tags).await
+        };
+        if query.include_deleted {
+            let trash = self.list_trash().await;
+            tasks.extend(trash.into_iter().filter(|task| query.has_tags(task)));
+        }
+        query.apply(tasks, now)
+    }
+
+    /// Full-text search over titles and descriptions.
+    ///
+    /// Returns at most `limit` tasks matching any of the query terms (see
+    /// `search::query_terms`), with their scores, best first.
+    pub async fn search_tasks(&self, terms: &BTreeSet<String>, limit: usize) -> Vec<(Task, f64)> {
+        self.tasks
+            .read()
+            .unwrap()
+            .search(terms)
+            .into_iter()
+            .take(limit)
+            .map(|(task, score)| (task.clone(), score))
+            .collect()
+    }
+
+    /// Lists the direct subtasks of a task, by ID.
+    ///
+    /// Returns `None` if the task does not exist.
+    pub async fn subtasks(&self, id: u64) -> Option<Vec<Task>> {
+        let tasks = self.tasks.read().unwrap();
+        tasks.get(&id)?;
+        Some(tasks.children(id).filter_map(|child| tasks.get(&child)).cloned().collect())
+    }
+
+    /// Builds the dependency graph around a task: everything it is
+    /// transitively blocked by, and everything transitively blocked by it.
+    ///
+    /// Returns `None` if the task does not exist.
+    pub async fn dependency_graph(&self, id: u64) -> Option<DependencyGraph> {
+        let tasks = self.tasks.read().unwrap();
+        tasks.get(&id)?;
+
+        let mut ids = BTreeSet::from([id]);
+        let mut edges = BTreeSet::new();
+        // Walk upstream along `blocked_by` and downstream along the reverse
+        // index; cycles are rejected on write, but `ids` guards the walk anyway
+        let mut upstream = vec![id];
+        while let Some(current) = upstream.pop() {
+            for &blocker in &tasks.get(&current).unwrap().blocked_by {
+                edges.insert(Dependency { task_id: current, blocked_by: blocker });
+                if ids.insert(blocker) {
+                    upstream.push(blocker);
+                }
             }
-            if let Some(completed) = update.completed {
-                task.completed = completed;
+        }
+        let mut downstream = vec![id];
+        while let Some(current) = downstream.pop() {
+            for blocked in tasks.blocks(current) {
+                edges.insert(Dependency { task_id: blocked, blocked_by: current });
+                if ids.insert(blocked) {
+                    downstream.push(blocked);
+                }
             }
-            Some(task.clone())
-        } else {
-            None
         }
+
+        Some(DependencyGraph {
+            tasks: ids.iter().filter_map(|id| tasks.get(id)).cloned().collect(),
+            edges: edges.into_iter().collect(),
+        })
     }
 
-    pub async fn delete_task(&self, id: u64) -> Option<Task> {
-        self.tasks.write().unwrap().remove(&id)
+    /// Counts the tasks carrying each tag, by tag name.
+    pub async fn tag_counts(&self) -> BTreeMap<String, usize> {
+        self.tasks
+            .read()
+            .unwrap()
+            .tag_counts()
+            .into_iter()
+            .map(|(tag, count)| (tag.to_string(), count))
+            .collect()
     }
+}
 
-    pub async fn list_tasks(&self) -> Vec<Task> {
-        self.tasks.read().unwrap().values().cloned().collect()
+/// Sets the store-maintained timestamps on a task about to be written.
+///
+/// `previous` is the stored task being replaced, if any. `completed_at` is
+/// kept while a task stays completed and cleared when it is reopened.
+fn stamp(task: &mut Task, previous: Option<&Task>, now: DateTime<Utc>) {
+    task.created_at = previous.map_or(now, |previous| previous.created_at);
+    task.updated_at = now;
+    task.completed_at = match previous {
+        _ if !task.completed => None,
+        Some(previous) if previous.completed => previous.completed_at.or(Some(now)),
+        _ => Some(now),
+    };
+}
+
+/// Checks a task's relations before it is written over `previous`.
+///
+/// Its parent and blocking tasks must exist, it cannot end up as its own
+/// ancestor or (transitively) blocked by itself, and it cannot become
+/// completed while any task blocking it is still open. Nor can a completed
+/// task be given open blockers; those it already had may be reopened, as
+/// that does not undo its completion.
+fn check_relations(tasks: &TaskMap, task: &Task, previous: Option<&Task>) -> Result<(), StoreError> {
+    if let Some(parent_id) = task.parent_id {
+        if tasks.get(&parent_id).is_none() && parent_id != task.id {
+            return Err(StoreError::InvalidField("parent_id", format!("Parent task {} does not exist", parent_id)));
+        }
+        // `tasks` still holds the old version of `task`, so reaching its ID
+        // while climbing from the new parent means a cycle
+        let mut ancestor = Some(parent_id);
+        while let Some(id) = ancestor {
+            if id == task.id {
+                return Err(StoreError::Conflict(format!(
+                    "Task {} cannot be a subtask of task {}, which is its own subtask",
+                    task.id, parent_id
+                )));
+            }
+            ancestor = tasks.get(&id).and_then(|task| task.parent_id);
+        }
+    }
+
+    if let Some(&missing) = task.blocked_by.iter().find(|&&id| id != task.id && tasks.get(&id).is_none()) {
+        return Err(StoreError::InvalidField("blocked_by", format!("Blocking task {} does not exist", missing)));
+    }
+    if let Some(path) = dependency_cycle(tasks, task) {
+        let path: Vec<String> = path.iter().map(u64::to_string).collect();
+        return Err(StoreError::Conflict(format!(
+            "Task {} cannot be blocked by itself: {}",
+            task.id,
+            path.join(" -> ")
+        )));
+    }
+
+    if task.completed {
+        let was_completed = previous.filter(|previous| previous.completed);
+        let open: Vec<String> = task
+            .blocked_by
+            .iter()
+            .filter(|id| !was_completed.is_some_and(|previous| previous.blocked_by.contains(id)))
+            .filter(|id| tasks.get(id).is_some_and(|blocker| !blocker.completed))
+            .map(u64::to_string)
+            .collect();
+        if !open.is_empty() {
+            let open = open.join(", ");
+            let message = match was_completed {
+                Some(_) => format!("Completed task {} cannot be blocked by open tasks: {}", task.id, open),
+                None => format!("Task {} cannot be completed while blocked by open tasks: {}", task.id, open),
+            };
+            return Err(StoreError::Conflict(message));
+        }
+    }
+    Ok(())
+}
+
+/// Finds a chain of `blocked_by` relations leading from `task` back to
+/// itself, given its new blockers.
+///
+/// Returns the chain of task IDs, starting and ending with the task's.
+fn dependency_cycle(tasks: &TaskMap, task: &Task) -> Option<Vec<u64>> {
+    // Breadth-first search, remembering how each task was reached
+    let mut reached_from: HashMap<u64, u64> = HashMap::new();
+    let mut queue: VecDeque<u64> = VecDeque::new();
+    for &blocker in &task.blocked_by {
+        reached_from.insert(blocker, task.id);
+        queue.push_back(blocker);
+    }
+    while let Some(current) = queue.pop_front() {
+        if current == task.id {
+            let mut path = vec![task.id];
+            let mut step = reached_from[&task.id];
+            while step != task.id {
+                path.push(step);
+                step = reached_from[&step];
+            }
+            path.push(task.id);
+            path.reverse();
+            return Some(path);
+        }
+        let Some(blockers) = tasks.get(&current).map(|task| &task.blocked_by) else {
+            continue;
+        };
+        for &blocker in blockers {
+            if let std::collections::hash_map::Entry::Vacant(entry) = reached_from.entry(blocker) {
+                entry.insert(current);
+                queue.push_back(blocker);
+            }
+        }
+    }
+    None
+}
+
+/// Checks that nothing still refers to a task about to be deleted.
+fn check_delete(tasks: &TaskMap, id: u64) -> Result<(), StoreError> {
+    let list = |ids: Vec<u64>| ids.iter().map(u64::to_string).collect::<Vec<_>>().join(", ");
+    let children: Vec<u64> = tasks.children(id).collect();
+    if !children.is_empty() {
+        return Err(StoreError::Conflict(format!(
+            "Task {} cannot be deleted while it has subtasks: {}",
+            id,
+            list(children)
+        )));
+    }
+    let blocked: Vec<u64> = tasks.blocks(id).collect();
+    if !blocked.is_empty() {
+        return Err(StoreError::Conflict(format!(
+            "Task {} cannot be deleted while it blocks tasks: {}",
+            id,
+            list(blocked)
+        )));
+    }
+    Ok(())
+}
+
+/// Moves a live task to the trash, returning it as it was and as deleted.
+fn soft_delete(tasks: &mut TaskMap, id: u64, now: DateTime<Utc>) -> (Task, Task) {
+    let mut deleted = tasks.get(&id).expect("task to delete exists").clone();
+    deleted.version += 1;
+    deleted.updated_at = now;
+    deleted.deleted_at = Some(now);
+    let task = tasks.trash(deleted.clone()).unwrap();
+    (task, deleted)
+}
+
+/// Applies one batch operation to the locked task map.
+fn apply_operation(
+    tasks: &mut TaskMap,
+    next_id: &mut u64,
+    operation: BatchOperation,
+    now: DateTime<Utc>,
+) -> Result<Task, StoreError> {
+    let errors = operation.validate();
+    if let Some((field, message)) = errors.into_iter().next() {
+        return Err(StoreError::Invalid(format!("{}: {}", field, message)));
+    }
+
+    let version_matches = |task: &Task, version: Option<u64>| version.is_none_or(|v| v == task.version);
+    match operation {
+        BatchOperation::Create { task } => {
+            let id = *next_id;
+            let task = Task::new(id, task, now);
+            check_relations(tasks, &task, None)?;
+            *next_id += 1;
+            tasks.insert(id, task.clone());
+            Ok(task)
+        }
+        BatchOperation::Update { id, task: update, version } => {
+            let previous = tasks.get(&id).ok_or(StoreError::NotFound)?;
+            if !version_matches(previous, version) {
+                return Err(StoreError::PreconditionFailed);
+            }
+            let mut task = previous.clone();
+            task.update(update);
+            stamp(&mut task, Some(previous), now);
+            check_relations(tasks, &task, Some(previous))?;
+            tasks.insert(id, task.clone());
+            Ok(task)
+        }
+        BatchOperation::Delete { id, version } => {
+            let task = tasks.get(&id).ok_or(StoreError::NotFound)?;
+            if !version_matches(task, version) {
+                return Err(StoreError::PreconditionFailed);
+            }
+            check_delete(tasks, id)?;
+            Ok(soft_delete(tasks, id, now).1)
+        }
     }
 }
 
@@ -0,0 +1,165 @@
+use crate::models::Task;
+use crate::search::SearchIndex;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::Hash;
+
+/// The tasks in a `Store`, with secondary indexes kept in step.
+///
+/// There is no `get_mut`: every change goes through `insert` or `remove`
+/// so the indexes always match the tasks.
+///
+/// Soft-deleted tasks are moved to a separate trash, outside the indexes, so
+/// every lookup other than the `trash*` methods sees only live tasks. An ID
+/// is either live or in the trash, never both.
+#[derive(Default)]
+pub struct TaskMap {
+    tasks: HashMap<u64, Task>,
+    /// Task IDs by tag.
+    by_tag: HashMap<String, BTreeSet<u64>>,
+    /// Subtask IDs by parent ID.
+    children: HashMap<u64, BTreeSet<u64>>,
+    /// IDs of the tasks each task blocks; the reverse of `blocked_by`.
+    blocks: HashMap<u64, BTreeSet<u64>>,
+    /// Full-text index over titles and descriptions.
+    search: SearchIndex,
+    /// Soft-deleted tasks, by ID.
+    trash: BTreeMap<u64, Task>,
+}
+
+impl TaskMap {
+    pub fn get(&self, id: &u64) -> Option<&Task> {
+        self.tasks.get(id)
+    }
+
+    /// Insert or replace the task with the given ID, returning the old one.
+    ///
+    /// A task with the same ID in the trash is dropped.
+    pub fn insert(&mut self, id: u64, task: Task) -> Option<Task> {
+        self.trash.remove(&id);
+        let previous = self.remove(&id);
+        for tag in &task.tags {
+            index_add(&mut self.by_tag, tag.clone(), id);
+        }
+        if let Some(parent_id) = task.parent_id {
+            index_add(&mut self.children, parent_id, id);
+        }
+        for &blocker in &task.blocked_by {
+            index_add(&mut self.blocks, blocker, id);
+        }
+        self.search.add(&task);
+        self.tasks.insert(id, task);
+        previous
+    }
+
+    /// Remove a live task for good.
+    pub fn remove(&mut self, id: &u64) -> Option<Task> {
+        let task = self.tasks.remove(id)?;
+        for tag in &task.tags {
+            index_remove(&mut self.by_tag, tag, *id);
+        }
+        if let Some(parent_id) = task.parent_id {
+            index_remove(&mut self.children, &parent_id, *id);
+        }
+        for blocker in &task.blocked_by {
+            index_remove(&mut self.blocks, blocker, *id);
+        }
+        self.search.remove(&task);
+        Some(task)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Task> {
+        self.tasks.values()
+    }
+
+    /// Move the live task with `task`'s ID to the trash, storing `task` in
+    /// its place. Returns the live task.
+    pub fn trash(&mut self, task: Task) -> Option<Task> {
+        let previous = self.remove(&task.id)?;
+        self.trash.insert(task.id, task);
+        Some(previous)
+    }
+
+    /// A task in the trash.
+    pub fn trashed(&self, id: &u64) -> Option<&Task> {
+        self.trash.get(id)
+    }
+
+    /// Tasks in the trash, by ID.
+    pub fn trash_values(&self) -> impl Iterator<Item = &Task> {
+        self.trash.values()
+    }
+
+    /// Drop every task deleted before `cutoff` from the trash, returning
+    /// them.
+    pub fn purge(&mut self, cutoff: DateTime<Utc>) -> Vec<Task> {
+        let expired: Vec<u64> = self
+            .trash
+            .values()
+            .filter(|task| task.deleted_at.is_some_and(|deleted_at| deleted_at < cutoff))
+            .map(|task| task.id)
+            .collect();
+        expired.iter().filter_map(|id| self.trash.remove(id)).collect()
+    }
+
+    /// Tasks with any of `tags`, or with all of them if `all` is set, looked
+    /// up through the tag index.
+    pub fn tagged(&self, tags: &[String], all: bool) -> Vec<&Task> {
+        let mut sets = tags.iter().map(|tag| self.by_tag.get(tag));
+        let ids: BTreeSet<u64> = if all {
+            // Any unknown tag empties the intersection
+            let Some(Some(first)) = sets.next() else {
+                return Vec::new();
+            };
+            let mut ids = first.clone();
+            for set in sets {
+                match set {
+                    Some(set) => ids.retain(|id| set.contains(id)),
+                    None => return Vec::new(),
+                }
+            }
+            ids
+        } else {
+            sets.flatten().flatten().copied().collect()
+        };
+        ids.iter().filter_map(|id| self.tasks.get(id)).collect()
+    }
+
+    /// The number of tasks carrying each tag, by tag name.
+    pub fn tag_counts(&self) -> BTreeMap<&str, usize> {
+        self.by_tag.iter().map(|(tag, ids)| (tag.as_str(), ids.len())).collect()
+    }
+
+    /// Tasks matching any of the search `terms`, with their scores, best
+    /// first.
+    pub fn search(&self, terms: &BTreeSet<String>) -> Vec<(&Task, f64)> {
+        self.search
+            .search(terms)
+            .into_iter()
+            .filter_map(|(id, score)| Some((self.tasks.get(&id)?, score)))
+            .collect()
+    }
+
+    /// IDs of the direct subtasks of a task.
+    pub fn children(&self, id: u64) -> impl Iterator<Item = u64> + '_ {
+        self.children.get(&id).into_iter().flatten().copied()
+    }
+
+    /// IDs of the tasks a task directly blocks.
+    pub fn blocks(&self, id: u64) -> impl Iterator<Item = u64> + '_ {
+        self.blocks.get(&id).into_iter().flatten().copied()
+    }
+}
+
+fn index_add<K: Hash + Eq>(index: &mut HashMap<K, BTreeSet<u64>>, key: K, id: u64) {
+    index.entry(key).or_default().insert(id);
+}
+
+fn index_remove<K: Hash + Eq>(index: &mut HashMap<K, BTreeSet<u64>>, key: &K, id: u64) {
+    if let Some(ids) = index.get_mut(key) {
+        ids.remove(&id);
+        if ids.is_empty() {
+            index.remove(key);
+        }
+    }
+}
@@ -6,15 +6,27 @@ async fn test_create_task() {
     let task = CreateTask {
         title: "Test Task".to_string(),
         description: "Test Description".to_string(),
+        due_at: None,
+        priority: None,
+        tags: Default::default(),
+        parent_id: None,
+        blocked_by: Default::default(),
     };
 
-    let id = store.create_task(task).await;
-    assert!(id > 0);
-
-    let created_task = store.get_task(id).await.unwrap();
+    let created_task = store.create_task(task).await.unwrap();
+    assert!(created_task.id > 0);
+    assert_eq!(created_task.version, 1);
+    assert_eq!(store.get_task(created_task.id).await.unwrap().created_at, created_task.created_at);
     assert_eq!(created_task.title, "Test Task");
     assert_eq!(created_task.description, "Test Description");
-    assert_eq!(created_task.completed, false);
+    assert!(!created_task.completed);
+}
+
+#[tokio::test]
+async fn test_default_store_starts_ids_at_one() {
+    let store = Store::default();
+    let id = store.create_task(related(None, &[])).await.unwrap().id;
+    assert_eq!(id, 1);
 }
 
 #[tokio::test]
@@ -23,16 +35,21 @@ async fn test_get_task() {
     let task = CreateTask {
         title: "Test Task".to_string(),
         description: "Test Description".to_string(),
+        due_at: None,
+        priority: None,
+        tags: Default::default(),
+        parent_id: None,
+        blocked_by: Default::default(),
     };
 
-    let id = store.create_task(task).await;
+    let id = store.create_task(task).await.unwrap().id;
     let retrieved_task = store.get_task(id).await;
 
     assert!(retrieved_task.is_some());
     let task = retrieved_task.unwrap();
     assert_eq!(task.title, "Test Task");
     assert_eq!(task.description, "Test Description");
-    assert_eq!(task.completed, false);
+    assert!(!task.completed);
 }
 
 #[tokio::test]
@@ -41,23 +58,33 @@ async fn test_update_task() {
     let task = CreateTask {
         title: "Test Task".to_string(),
         description: "Test Description".to_string(),
+        due_at: None,
+        priority: None,
+        tags: Default::default(),
+        parent_id: None,
+        blocked_by: Default::default(),
     };
 
-    let id = store.create_task(task).await;
+    let id = store.create_task(task).await.unwrap().id;
     
     let update = UpdateTask {
         title: Some("Updated Task".to_string()),
         description: Some("Updated Description".to_string()),
         completed: Some(true),
+        due_at: None,
+        priority: None,
+        tags: None,
+        parent_id: None,
+        blocked_by: None,
     };
 
     let updated = store.update_task(id, update).await;
-    assert!(updated.is_some());
+    assert!(updated.is_ok());
 
     let updated_task = updated.unwrap();
     assert_eq!(updated_task.title, "Updated Task");
     assert_eq!(updated_task.description, "Updated Description");
-    assert_eq!(updated_task.completed, true);
+    assert!(updated_task.completed);
 }
 
 #[tokio::test]
@@ -66,10 +93,15 @@ async fn test_delete_task() {
     let task = CreateTask {
         title: "Test Task".to_string(),
         description: "Test Description".to_string(),
+        due_at: None,
+        priority: None,
+        tags: Default::default(),
+        parent_id: None,
+        blocked_by: Default::default(),
     };
 
-    let id = store.create_task(task).await;
-    assert!(store.delete_task(id).await.is_some());
+    let id = store.create_task(task).await.unwrap().id;
+    assert!(store.delete_task(id).await.is_ok());
     assert!(store.get_task(id).await.is_none());
 }
 
@@ -79,14 +111,24 @@ async fn test_list_tasks() {
     let task1 = CreateTask {
         title: "Test Task 1".to_string(),
         description: "Test Description 1".to_string(),
+        due_at: None,
+        priority: None,
+        tags: Default::default(),
+        parent_id: None,
+        blocked_by: Default::default(),
     };
     let task2 = CreateTask {
         title: "Test Task 2".to_string(),
         description: "Test Description 2".to_string(),
+        due_at: None,
+        priority: None,
+        tags: Default::default(),
+        parent_id: None,
+        blocked_by: Default::default(),
     };
 
-    store.create_task(task1).await;
-    store.create_task(task2).await;
+    store.create_task(task1).await.unwrap();
+    store.create_task(task2).await.unwrap();
 
     let tasks = store.list_tasks().await;
     assert_eq!(tasks.len(), 2);
@@ -100,6 +142,653 @@ async fn test_task_not_found() {
         title: None, 
         description: None,
         completed: None,
-    }).await.is_none());
-    assert!(store.delete_task(1).await.is_none());
+        due_at: None,
+        priority: None,
+        tags: None,
+        parent_id: None,
+        blocked_by: None,
+    }).await.unwrap_err() == StoreError::NotFound);
+    assert_eq!(store.delete_task(1).await.unwrap_err(), StoreError::NotFound);
+}
+
+#[tokio::test]
+async fn test_update_increments_version() {
+    let store = Store::new();
+    let id = store.create_task(CreateTask {
+        title: "Test Task".to_string(),
+        description: "Test Description".to_string(),
+        due_at: None,
+        priority: None,
+        tags: Default::default(),
+        parent_id: None,
+        blocked_by: Default::default(),
+    }).await.unwrap().id;
+    assert_eq!(store.get_task(id).await.unwrap().version, 1);
+
+    let updated = store.update_task(id, UpdateTask {
+        title: None,
+        description: None,
+        completed: Some(true),
+        due_at: None,
+        priority: None,
+        tags: None,
+        parent_id: None,
+        blocked_by: None,
+    }).await.unwrap();
+    assert_eq!(updated.version, 2);
+}
+
+#[tokio::test]
+async fn test_conditional_update_and_delete() {
+    let store = Store::new();
+    let id = store.create_task(CreateTask {
+        title: "Test Task".to_string(),
+        description: "Test Description".to_string(),
+        due_at: None,
+        priority: None,
+        tags: Default::default(),
+        parent_id: None,
+        blocked_by: Default::default(),
+    }).await.unwrap().id;
+
+    let stale = |task: &Task| task.version == 0;
+    let update = || UpdateTask {
+        title: Some("Updated Task".to_string()),
+        description: None,
+        completed: None,
+        due_at: None,
+        priority: None,
+        tags: None,
+        parent_id: None,
+        blocked_by: None,
+    };
+
+    assert_eq!(store.update_task_if(id, update(), stale).await.unwrap_err(), StoreError::PreconditionFailed);
+    assert_eq!(store.get_task(id).await.unwrap().title, "Test Task");
+    assert_eq!(store.update_task_if(42, update(), |_| true).await.unwrap_err(), StoreError::NotFound);
+
+    let updated = store.update_task_if(id, update(), |task| task.version == 1).await.unwrap();
+    assert_eq!(updated.title, "Updated Task");
+
+    assert_eq!(store.delete_task_if(id, stale).await.unwrap_err(), StoreError::PreconditionFailed);
+    assert!(store.delete_task_if(id, |task| task.version == 2).await.is_ok());
+    assert!(store.get_task(id).await.is_none());
+}
+
+#[tokio::test]
+async fn test_modify_task() {
+    let store = Store::new();
+    let id = store.create_task(CreateTask {
+        title: "Test Task".to_string(),
+        description: "Test Description".to_string(),
+        due_at: None,
+        priority: None,
+        tags: Default::default(),
+        parent_id: None,
+        blocked_by: Default::default(),
+    }).await.unwrap().id;
+
+    let err = store.modify_task(id, |_| Err(StoreError::Conflict("nope".to_string()))).await;
+    assert_eq!(err.unwrap_err(), StoreError::Conflict("nope".to_string()));
+    assert_eq!(store.get_task(id).await.unwrap().version, 1);
+
+    let modified = store.modify_task(id, |task| {
+        let mut task = task.clone();
+        task.id = 99;
+        task.title = "Modified".to_string();
+        Ok(task)
+    }).await.unwrap();
+    assert_eq!(modified.id, id);
+    assert_eq!(modified.version, 2);
+    assert_eq!(store.get_task(id).await.unwrap().title, "Modified");
+}
+
+#[tokio::test]
+async fn test_replace_task_upserts() {
+    let store = Store::new();
+    let replacement = |title: &str| ReplaceTask {
+        id: None,
+        title: title.to_string(),
+        description: "Replaced".to_string(),
+        completed: true,
+        version: None,
+        due_at: None,
+        priority: None,
+        _created_at: None,
+        _updated_at: None,
+        _completed_at: None,
+        _deleted_at: None,
+        tags: Default::default(),
+        parent_id: None,
+        blocked_by: Default::default(),
+    };
+
+    let (created, was_created) = store.replace_task(10, replacement("Chosen"), |_| true).await.unwrap();
+    assert!(was_created);
+    assert_eq!(created.id, 10);
+    assert_eq!(created.version, 1);
+
+    // The ID counter moves past client-chosen IDs
+    let id = store.create_task(CreateTask {
+        title: "Test Task".to_string(),
+        description: "Test Description".to_string(),
+        due_at: None,
+        priority: None,
+        tags: Default::default(),
+        parent_id: None,
+        blocked_by: Default::default(),
+    }).await.unwrap().id;
+    assert_eq!(id, 11);
+
+    let (replaced, was_created) = store.replace_task(10, replacement("Again"), |_| true).await.unwrap();
+    assert!(!was_created);
+    assert_eq!(replaced.title, "Again");
+    assert_eq!(replaced.version, 2);
+
+    let err = store.replace_task(10, replacement("Vetoed"), |current| current.is_none()).await;
+    assert_eq!(err.unwrap_err(), StoreError::PreconditionFailed);
+    assert_eq!(store.get_task(10).await.unwrap().title, "Again");
+}
+
+#[tokio::test]
+async fn test_replace_task_rejects_the_last_id() {
+    let store = Store::new();
+    let replacement: ReplaceTask =
+        serde_json::from_str(r#"{"title":"T","description":"","completed":false}"#).unwrap();
+
+    let err = store.replace_task(u64::MAX, replacement, |_| true).await;
+    assert_eq!(err.unwrap_err(), StoreError::Invalid("ID out of range".to_string()));
+
+    // Nothing was poisoned, and the ID counter did not move
+    assert!(store.get_task(u64::MAX).await.is_none());
+    let id = store.create_task(serde_json::from_str(r#"{"title":"T","description":""}"#).unwrap()).await.unwrap().id;
+    assert_eq!(id, 1);
+}
+
+fn batch_create(title: &str) -> BatchOperation {
+    BatchOperation::Create {
+        task: CreateTask {
+            title: title.to_string(),
+            description: "Batch".to_string(),
+            due_at: None,
+            priority: None,
+            tags: Default::default(),
+            parent_id: None,
+            blocked_by: Default::default(),
+        },
+    }
+}
+
+fn batch_complete(id: u64) -> BatchOperation {
+    BatchOperation::Update {
+        id,
+        task: UpdateTask {
+            title: None,
+            description: None,
+            completed: Some(true),
+            due_at: None,
+            priority: None,
+            tags: None,
+            parent_id: None,
+            blocked_by: None,
+        },
+        version: None,
+    }
+}
+
+#[tokio::test]
+async fn test_apply_batch_atomic() {
+    let store = Store::new();
+    let results = store
+        .apply_batch(vec![batch_create("First"), batch_complete(1), batch_create("Second")], true)
+        .await;
+    assert!(results.iter().all(Result::is_ok));
+    assert!(store.get_task(1).await.unwrap().completed);
+    assert_eq!(store.list_tasks().await.len(), 2);
+
+    // A failure rolls back the earlier operations and skips the later ones
+    let results = store
+        .apply_batch(
+            vec![
+                batch_create("Third"),
+                BatchOperation::Delete { id: 1, version: None },
+                batch_complete(99),
+                batch_create("Fourth"),
+            ],
+            true,
+        )
+        .await;
+    assert_eq!(results[0].as_ref().unwrap_err(), &StoreError::Aborted);
+    assert_eq!(results[1].as_ref().unwrap_err(), &StoreError::Aborted);
+    assert_eq!(results[2].as_ref().unwrap_err(), &StoreError::NotFound);
+    assert_eq!(results[3].as_ref().unwrap_err(), &StoreError::Aborted);
+    assert_eq!(store.list_tasks().await.len(), 2);
+    assert_eq!(store.get_task(1).await.unwrap().version, 2);
+
+    // The ID counter is rolled back too
+    let id = store.create_task(CreateTask {
+        title: "Test Task".to_string(),
+        description: "Test Description".to_string(),
+        due_at: None,
+        priority: None,
+        tags: Default::default(),
+        parent_id: None,
+        blocked_by: Default::default(),
+    }).await.unwrap().id;
+    assert_eq!(id, 3);
+}
+
+#[tokio::test]
+async fn test_apply_batch_validates_only_the_fields_updated() {
+    let store = Store::new();
+    let id = store.create_task(related(None, &[])).await.unwrap().id;
+
+    // Leaving the title out keeps the stored one
+    let results = store.apply_batch(vec![batch_complete(id)], true).await;
+    assert!(results[0].is_ok());
+
+    let rename = |title: &str| BatchOperation::Update {
+        id,
+        task: UpdateTask {
+            title: Some(title.to_string()),
+            description: None,
+            completed: None,
+            due_at: None,
+            priority: None,
+            tags: None,
+            parent_id: None,
+            blocked_by: None,
+        },
+        version: None,
+    };
+    let results = store.apply_batch(vec![rename(" ")], true).await;
+    assert_eq!(results[0].as_ref().unwrap_err(), &StoreError::Invalid("title: Title is required".to_string()));
+    let results = store.apply_batch(vec![rename("Renamed")], true).await;
+    assert_eq!(results[0].as_ref().unwrap().title, "Renamed");
+}
+
+#[tokio::test]
+async fn test_apply_batch_per_item() {
+    let store = Store::new();
+    let results = store
+        .apply_batch(
+            vec![
+                batch_create("First"),
+                batch_create(""),
+                BatchOperation::Delete { id: 1, version: Some(7) },
+                batch_complete(1),
+            ],
+            false,
+        )
+        .await;
+    assert!(results[0].is_ok());
+    assert!(matches!(results[1], Err(StoreError::Invalid(_))));
+    assert_eq!(results[2].as_ref().unwrap_err(), &StoreError::PreconditionFailed);
+    assert_eq!(results[3].as_ref().unwrap().version, 2);
+    assert_eq!(store.list_tasks().await.len(), 1);
+}
+
+#[tokio::test]
+async fn test_timestamps_maintained() {
+    let store = Store::new();
+    let id = store.create_task(CreateTask {
+        title: "Test Task".to_string(),
+        description: "Test Description".to_string(),
+        due_at: None,
+        priority: None,
+        tags: Default::default(),
+        parent_id: None,
+        blocked_by: Default::default(),
+    }).await.unwrap().id;
+    let created = store.get_task(id).await.unwrap();
+    assert_eq!(created.created_at, created.updated_at);
+    assert!(created.completed_at.is_none());
+
+    let complete = |completed: bool| UpdateTask {
+        title: None,
+        description: None,
+        completed: Some(completed),
+        due_at: None,
+        priority: None,
+        tags: None,
+        parent_id: None,
+        blocked_by: None,
+    };
+    let completed = store.update_task(id, complete(true)).await.unwrap();
+    assert_eq!(completed.created_at, created.created_at);
+    assert!(completed.updated_at >= created.updated_at);
+    let completed_at = completed.completed_at.unwrap();
+
+    // Staying completed keeps the original completion time
+    let renamed = store.modify_task(id, |task| {
+        let mut task = task.clone();
+        task.title = "Renamed".to_string();
+        task.created_at = chrono::DateTime::UNIX_EPOCH;
+        Ok(task)
+    }).await.unwrap();
+    assert_eq!(renamed.completed_at, Some(completed_at));
+    assert_eq!(renamed.created_at, created.created_at);
+
+    let reopened = store.update_task(id, complete(false)).await.unwrap();
+    assert!(reopened.completed_at.is_none());
+}
+
+#[tokio::test]
+async fn test_tag_index() {
+    let store = Store::new();
+    let tagged = |title: &str, tags: &[&str]| CreateTask {
+        title: title.to_string(),
+        description: String::new(),
+        due_at: None,
+        priority: None,
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        parent_id: None,
+        blocked_by: Default::default(),
+    };
+    let home = store.create_task(tagged("Home", &["home"])).await.unwrap().id;
+    let both = store.create_task(tagged("Both", &["home", "work"])).await.unwrap().id;
+    let work = store.create_task(tagged("Work", &["work"])).await.unwrap().id;
+    store.create_task(tagged("None", &[])).await.unwrap();
+
+    let ids = |tasks: Vec<Task>| tasks.iter().map(|task| task.id).collect::<Vec<_>>();
+    let tags = |tags: &[&str]| tags.iter().map(|tag| tag.to_string()).collect::<Vec<_>>();
+    assert_eq!(ids(store.list_tasks_tagged(&tags(&["home", "work"]), false).await), vec![home, both, work]);
+    assert_eq!(ids(store.list_tasks_tagged(&tags(&["home", "work"]), true).await), vec![both]);
+    assert!(store.list_tasks_tagged(&tags(&["home", "unknown"]), true).await.is_empty());
+
+    // The index follows updates and deletes
+    store.modify_task(both, |task| {
+        let mut task = task.clone();
+        task.tags.remove("home");
+        Ok(task)
+    }).await.unwrap();
+    store.delete_task(work).await.unwrap();
+    assert_eq!(ids(store.list_tasks_tagged(&tags(&["work"]), false).await), vec![both]);
+
+    let counts = store.tag_counts().await;
+    assert_eq!(counts.get("home"), Some(&1));
+    assert_eq!(counts.get("work"), Some(&1));
+    assert_eq!(counts.len(), 2);
+}
+
+fn related(parent_id: Option<u64>, blocked_by: &[u64]) -> CreateTask {
+    CreateTask {
+        title: "Related".to_string(),
+        description: String::new(),
+        due_at: None,
+        priority: None,
+        tags: Default::default(),
+        parent_id,
+        blocked_by: blocked_by.iter().copied().collect(),
+    }
+}
+
+fn relink(parent_id: Option<u64>, blocked_by: &[u64]) -> UpdateTask {
+    UpdateTask {
+        title: None,
+        description: None,
+        completed: None,
+        due_at: None,
+        priority: None,
+        tags: None,
+        parent_id: Some(parent_id),
+        blocked_by: Some(blocked_by.iter().copied().collect()),
+    }
+}
+
+#[tokio::test]
+async fn test_relations_must_exist() {
+    let store = Store::new();
+    let err = store.create_task(related(Some(9), &[])).await.unwrap_err();
+    assert_eq!(err, StoreError::InvalidField("parent_id", "Parent task 9 does not exist".to_string()));
+    let err = store.create_task(related(None, &[9])).await.unwrap_err();
+    assert_eq!(err, StoreError::InvalidField("blocked_by", "Blocking task 9 does not exist".to_string()));
+
+    // A rejected create does not use up an ID
+    assert_eq!(store.create_task(related(None, &[])).await.unwrap().id, 1);
+}
+
+#[tokio::test]
+async fn test_relation_cycles_rejected() {
+    let store = Store::new();
+    let root = store.create_task(related(None, &[])).await.unwrap().id;
+    let child = store.create_task(related(Some(root), &[root])).await.unwrap().id;
+    let grandchild = store.create_task(related(Some(child), &[child])).await.unwrap().id;
+
+    let err = store.update_task(root, relink(Some(grandchild), &[])).await.unwrap_err();
+    assert!(matches!(err, StoreError::Conflict(_)));
+    let err = store.update_task(root, relink(Some(root), &[])).await.unwrap_err();
+    assert!(matches!(err, StoreError::Conflict(_)));
+
+    let err = store.update_task(root, relink(None, &[grandchild])).await.unwrap_err();
+    assert_eq!(
+        err,
+        StoreError::Conflict(format!(
+            "Task {root} cannot be blocked by itself: {root} -> {grandchild} -> {child} -> {root}"
+        ))
+    );
+
+    assert_eq!(store.subtasks(root).await.unwrap().len(), 1);
+    assert!(store.subtasks(99).await.is_none());
+}
+
+#[tokio::test]
+async fn test_blocked_task_cannot_be_completed() {
+    let store = Store::new();
+    let blocker = store.create_task(related(None, &[])).await.unwrap().id;
+    let blocked = store.create_task(related(None, &[blocker])).await.unwrap().id;
+    let complete = || UpdateTask {
+        title: None,
+        description: None,
+        completed: Some(true),
+        due_at: None,
+        priority: None,
+        tags: None,
+        parent_id: None,
+        blocked_by: None,
+    };
+
+    let err = store.update_task(blocked, complete()).await.unwrap_err();
+    assert_eq!(
+        err,
+        StoreError::Conflict(format!("Task {blocked} cannot be completed while blocked by open tasks: {blocker}"))
+    );
+
+    // Nor can a task that others depend on be deleted
+    assert!(matches!(store.delete_task(blocker).await, Err(StoreError::Conflict(_))));
+
+    store.update_task(blocker, complete()).await.unwrap();
+    assert!(store.update_task(blocked, complete()).await.unwrap().completed);
+
+    let graph = store.dependency_graph(blocker).await.unwrap();
+    assert_eq!(graph.tasks.len(), 2);
+    assert_eq!(graph.edges, vec![Dependency { task_id: blocked, blocked_by: blocker }]);
+
+    // Once completed, a task takes no new open blockers
+    let open = store.create_task(related(None, &[])).await.unwrap().id;
+    let err = store.update_task(blocked, relink(None, &[blocker, open])).await.unwrap_err();
+    assert_eq!(err, StoreError::Conflict(format!("Completed task {blocked} cannot be blocked by open tasks: {open}")));
+    store.update_task(open, complete()).await.unwrap();
+    store.update_task(blocked, relink(None, &[blocker, open])).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_search_index_follows_writes() {
+    let store = Store::new();
+    let titled = |title: &str| CreateTask {
+        title: title.to_string(),
+        description: String::new(),
+        due_at: None,
+        priority: None,
+        tags: Default::default(),
+        parent_id: None,
+        blocked_by: Default::default(),
+    };
+    let milk = store.create_task(titled("Buy milk")).await.unwrap().id;
+    let bread = store.create_task(titled("Buy bread")).await.unwrap().id;
+    let search = |query: &str| {
+        let store = &store;
+        let terms = crate::search::query_terms(query);
+        async move { store.search_tasks(&terms, 10).await.into_iter().map(|(task, _)| task.id).collect::<Vec<_>>() }
+    };
+    assert_eq!(search("buying").await, vec![milk, bread]);
+
+    store.update_task(milk, UpdateTask {
+        title: Some("Buy cheese".to_string()),
+        description: None,
+        completed: None,
+        due_at: None,
+        priority: None,
+        tags: None,
+        parent_id: None,
+        blocked_by: None,
+    }).await.unwrap();
+    assert!(search("milk").await.is_empty());
+    assert_eq!(search("cheese").await, vec![milk]);
+
+    store.delete_task(bread).await.unwrap();
+    assert_eq!(search("buy").await, vec![milk]);
+}
+
+#[tokio::test]
+async fn test_soft_delete_and_restore() {
+    let store = Store::new();
+    let id = store.create_task(related(None, &[])).await.unwrap().id;
+
+    let deleted = store.delete_task(id).await.unwrap();
+    assert_eq!(deleted.version, 2);
+    assert!(deleted.deleted_at.is_some());
+    assert!(store.get_task(id).await.is_none());
+    assert!(store.list_tasks().await.is_empty());
+    assert_eq!(store.list_trash().await.iter().map(|task| task.id).collect::<Vec<_>>(), vec![id]);
+
+    // Deleting again finds nothing; replacing needs a restore first
+    assert_eq!(store.delete_task(id).await.unwrap_err(), StoreError::NotFound);
+    assert!(matches!(
+        store.replace_task(id, serde_json::from_str(r#"{"title":"T","description":"","completed":false}"#).unwrap(), |_| true).await,
+        Err(StoreError::Conflict(_))
+    ));
+
+    assert_eq!(
+        store.restore_task_if(id, |task| task.version == 1).await.unwrap_err(),
+        StoreError::PreconditionFailed
+    );
+    let restored = store.restore_task(id).await.unwrap();
+    assert_eq!(restored.version, 3);
+    assert!(restored.deleted_at.is_none());
+    assert!(store.get_task(id).await.is_some());
+    assert!(store.list_trash().await.is_empty());
+    assert_eq!(store.restore_task(id).await.unwrap_err(), StoreError::NotFound);
+}
+
+#[tokio::test]
+async fn test_restore_needs_relations() {
+    let store = Store::new();
+    let parent = store.create_task(related(None, &[])).await.unwrap().id;
+    let child = store.create_task(related(Some(parent), &[])).await.unwrap().id;
+
+    store.delete_task(child).await.unwrap();
+    store.delete_task(parent).await.unwrap();
+    assert!(matches!(store.restore_task(child).await, Err(StoreError::Conflict(_))));
+
+    store.restore_task(parent).await.unwrap();
+    store.restore_task(child).await.unwrap();
+    assert_eq!(store.subtasks(parent).await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_purge_trash() {
+    let store = Store::new();
+    let old = store.create_task(related(None, &[])).await.unwrap().id;
+    let recent = store.create_task(related(None, &[])).await.unwrap().id;
+    store.delete_task(old).await.unwrap();
+    let cutoff = Utc::now();
+    store.delete_task(recent).await.unwrap();
+
+    assert_eq!(store.purge_trash(cutoff).await, vec![old]);
+    assert_eq!(store.list_trash().await.iter().map(|task| task.id).collect::<Vec<_>>(), vec![recent]);
+    assert_eq!(store.restore_task(old).await.unwrap_err(), StoreError::NotFound);
+}
+
+#[tokio::test]
+async fn test_batch_delete_rollback_leaves_trash_empty() {
+    let store = Store::new();
+    let id = store.create_task(related(None, &[])).await.unwrap().id;
+    let results = store
+        .apply_batch(
+            vec![BatchOperation::Delete { id, version: None }, BatchOperation::Delete { id: 99, version: None }],
+            true,
+        )
+        .await;
+    assert_eq!(results[1].as_ref().unwrap_err(), &StoreError::NotFound);
+    assert_eq!(store.get_task(id).await.unwrap().version, 1);
+    assert!(store.list_trash().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_changes_are_audited() {
+    use crate::audit::{self, AuditAction, AuditContext};
+
+    let store = Store::new();
+    let context = AuditContext { actor: "alice".to_string(), request_id: Some(uuid::Uuid::new_v4()) };
+    let id = audit::scope(context.clone(), async {
+        let id = store.create_task(related(None, &[])).await.unwrap().id;
+        store.update_task(id, UpdateTask {
+            title: Some("Renamed".to_string()),
+            description: None,
+            completed: None,
+            due_at: None,
+            priority: None,
+            tags: None,
+            parent_id: None,
+            blocked_by: None,
+        }).await.unwrap();
+        store.delete_task(id).await.unwrap();
+        id
+    })
+    .await;
+    // Outside a request, changes are the system's
+    store.purge_trash(Utc::now()).await;
+
+    let history = store.history(id).await;
+    let actions: Vec<AuditAction> = history.iter().map(|event| event.action).collect();
+    assert_eq!(actions, vec![AuditAction::Created, AuditAction::Updated, AuditAction::Deleted, AuditAction::Purged]);
+    assert_eq!(history[0].actor, "alice");
+    assert_eq!(history[0].request_id, context.request_id);
+    assert_eq!(history[3].actor, "system");
+
+    let rename = &history[1].changes["title"];
+    assert_eq!((rename.before.as_str(), rename.after.as_str()), (Some("Related"), Some("Renamed")));
+    assert!(history[2].changes.contains_key("deleted_at"));
+}
+
+#[tokio::test]
+async fn test_rolled_back_batch_is_not_audited() {
+    let store = Store::new();
+    store.apply_batch(vec![batch_create("Kept"), batch_complete(99)], false).await;
+    store.apply_batch(vec![batch_create("Dropped"), batch_complete(99)], true).await;
+
+    let (events, _) = store.audit_events(&Default::default()).await;
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].changes["title"].after, "Kept");
+}
+
+#[tokio::test]
+async fn test_changes_are_published() {
+    use crate::audit::AuditAction;
+
+    let store = Store::new();
+    let (_, mut receiver) = store.subscribe(None);
+    let id = store.create_task(related(None, &[])).await.unwrap().id;
+    store.delete_task(id).await.unwrap();
+
+    let created = receiver.recv().await.unwrap();
+    assert_eq!((created.id, created.action, created.task_id), (1, AuditAction::Created, id));
+    let deleted = receiver.recv().await.unwrap();
+    assert_eq!(deleted.action, AuditAction::Deleted);
+    assert!(deleted.task.unwrap().deleted_at.is_some());
+
+    // A reconnecting client picks up where it left off
+    let (missed, _) = store.subscribe(Some(created.id));
+    assert_eq!(missed.iter().map(|event| event.id).collect::<Vec<_>>(), vec![deleted.id]);
 }
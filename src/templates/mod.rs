@@ -0,0 +1,30 @@
+use minijinja::Environment;
+use serde::Serialize;
+use std::sync::OnceLock;
+
+static ENV: OnceLock<Environment<'static>> = OnceLock::new();
+
+/// Returns the shared template environment.
+///
+/// The templates in `frontend/templates` are compiled into the binary so the
+/// server renders the same pages regardless of its working directory.
+fn env() -> &'static Environment<'static> {
+    ENV.get_or_init(|| {
+        let mut env = Environment::new();
+        env.add_template("base.html", include_str!("../../frontend/templates/base.html"))
+            .expect("base.html should be a valid template");
+        env.add_template("index.html", include_str!("../../frontend/templates/index.html"))
+            .expect("index.html should be a valid template");
+        env.add_template("task_form.html", include_str!("../../frontend/templates/task_form.html"))
+            .expect("task_form.html should be a valid template");
+        env
+    })
+}
+
+/// Renders the named template with the given context.
+///
+/// HTML templates are auto-escaped, so values from the store can be passed in
+/// as-is.
+pub fn render<S: Serialize>(name: &str, ctx: S) -> Result<String, minijinja::Error> {
+    env().get_template(name)?.render(ctx)
+}
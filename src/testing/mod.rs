@@ -0,0 +1,185 @@
+use std::future::IntoFuture;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::header::{HeaderMap, CONTENT_TYPE};
+use hyper::body::Incoming;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::models::{CreateTask, Task};
+use crate::server::Server;
+use crate::store::Store;
+
+/// A configuration that serves HTTP and gRPC on free local ports.
+pub fn config() -> Config {
+    Config {
+        addr: SocketAddr::from(([127, 0, 0, 1], 0)),
+        grpc_addr: SocketAddr::from(([127, 0, 0, 1], 0)),
+        ..Config::default()
+    }
+}
+
+/// The real server, running in the background on a free local port over a
+/// fresh store, with a client to send it requests.
+pub struct TestServer {
+    addr: SocketAddr,
+    store: Arc<Store>,
+    client: Client<HttpConnector, Full<Bytes>>,
+}
+
+impl TestServer {
+    pub async fn start() -> Self {
+        Self::start_with(|server| server).await
+    }
+
+    /// Start the server `build` makes of one with the test configuration and
+    /// store, such as to add routes or middleware.
+    pub async fn start_with(build: impl FnOnce(Server) -> Server) -> Self {
+        Self::start_configured(config(), build).await
+    }
+
+    /// Start the server `build` makes of one with `config` and a fresh store.
+    pub async fn start_configured(config: Config, build: impl FnOnce(Server) -> Server) -> Self {
+        let store = Arc::new(Store::new());
+        let server = build(Server::new(config).store(Arc::clone(&store)));
+        let bound = server.bind().await.expect("the test server should bind");
+        let addr = bound.addr();
+        tokio::spawn(bound.into_future());
+        TestServer {
+            addr,
+            store,
+            client: Client::builder(TokioExecutor::new()).build_http(),
+        }
+    }
+
+    /// The store the server serves, to arrange and inspect tasks directly.
+    pub fn store(&self) -> &Arc<Store> {
+        &self.store
+    }
+
+    /// The base URL of the server, as in `http://127.0.0.1:4321`.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn request(&self, method: Method, path: &str) -> TestRequest<'_> {
+        TestRequest {
+            client: &self.client,
+            request: Request::builder().method(method).uri(format!("{}{}", self.url(), path)),
+            body: Bytes::new(),
+        }
+    }
+
+    pub fn get(&self, path: &str) -> TestRequest<'_> {
+        self.request(Method::GET, path)
+    }
+
+    pub fn post(&self, path: &str) -> TestRequest<'_> {
+        self.request(Method::POST, path)
+    }
+
+    pub fn put(&self, path: &str) -> TestRequest<'_> {
+        self.request(Method::PUT, path)
+    }
+
+    pub fn patch(&self, path: &str) -> TestRequest<'_> {
+        self.request(Method::PATCH, path)
+    }
+
+    pub fn delete(&self, path: &str) -> TestRequest<'_> {
+        self.request(Method::DELETE, path)
+    }
+
+    /// Create a task through the API, returning it as created.
+    pub async fn create_task(&self, task: &CreateTask) -> Task {
+        let response = self.post("/v2/tasks").json(task).send().await;
+        assert_eq!(response.status, StatusCode::CREATED, "{}", response.text());
+        let mut data: Value = response.data();
+        serde_json::from_value(data["task"].take()).unwrap()
+    }
+}
+
+/// A request being built for a `TestServer`.
+pub struct TestRequest<'a> {
+    client: &'a Client<HttpConnector, Full<Bytes>>,
+    request: hyper::http::request::Builder,
+    body: Bytes,
+}
+
+impl TestRequest<'_> {
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.request = self.request.header(name, value);
+        self
+    }
+
+    /// Send `body` as JSON.
+    pub fn json<T: Serialize + ?Sized>(self, body: &T) -> Self {
+        self.body("application/json", serde_json::to_vec(body).unwrap())
+    }
+
+    /// Send `body` as the given media type.
+    pub fn body(mut self, content_type: &str, body: impl Into<Bytes>) -> Self {
+        self.request = self.request.header(CONTENT_TYPE, content_type);
+        self.body = body.into();
+        self
+    }
+
+    /// Send the request, returning the whole response.
+    pub async fn send(self) -> TestResponse {
+        let request = self.request.body(Full::new(self.body)).unwrap();
+        let response = self.client.request(request).await.expect("the test server should respond");
+        let (parts, body) = response.into_parts();
+        TestResponse {
+            status: parts.status,
+            headers: parts.headers,
+            body: body.collect().await.unwrap().to_bytes(),
+        }
+    }
+
+    /// Send the request, returning the response as soon as its head arrives,
+    /// for bodies that do not end, such as event streams.
+    pub async fn open(self) -> Response<Incoming> {
+        let request = self.request.body(Full::new(self.body)).unwrap();
+        self.client.request(request).await.expect("the test server should respond")
+    }
+}
+
+/// A response from a `TestServer`, with its body read in full.
+#[derive(Debug)]
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl TestResponse {
+    /// The body as JSON, into any type: `Value`, or a model.
+    pub fn json<T: DeserializeOwned>(&self) -> T {
+        serde_json::from_slice(&self.body).unwrap_or_else(|err| panic!("{}: {}", err, self.text()))
+    }
+
+    /// The `data` of a v2 envelope.
+    pub fn data<T: DeserializeOwned>(&self) -> T {
+        let mut body: Value = self.json();
+        serde_json::from_value(body["data"].take()).unwrap_or_else(|err| panic!("{}: {}", err, self.text()))
+    }
+
+    pub fn text(&self) -> &str {
+        std::str::from_utf8(&self.body).unwrap()
+    }
+
+    /// The value of a header, which must be present.
+    pub fn header(&self, name: &str) -> &str {
+        let value = self.headers.get(name).unwrap_or_else(|| panic!("no {} header", name));
+        value.to_str().unwrap()
+    }
+}
@@ -0,0 +1,170 @@
+use crate::accept;
+use chrono::{DateTime, Utc};
+use hyper::header::{HeaderValue, ACCEPT, LINK, VARY};
+use hyper::{HeaderMap, Response};
+use std::future::Future;
+
+/// Media type prefix of the vendor types that select a version, as in
+/// `application/vnd.rws.v2+json`.
+const VENDOR_PREFIX: &str = "application/vnd.rws.";
+
+/// A version of the REST API.
+///
+/// `/v1/…` and `/v2/…` address a version directly. Without a prefix, the
+/// version is the one named by a vendor media type in `Accept`, such as
+/// `application/vnd.rws.v2+json`, and v1 if there is none.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ApiVersion {
+    V1,
+    V2,
+}
+
+/// When a deprecated version stopped being recommended, and when it will be
+/// removed.
+///
+/// No version is deprecated unless configured to be (see
+/// `Config::v1_deprecation`), so that v1 is only retired on a schedule set
+/// once clients have had v2 to move to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Deprecation {
+    pub since: DateTime<Utc>,
+    pub sunset: DateTime<Utc>,
+}
+
+impl ApiVersion {
+    /// Every version still served, oldest first.
+    pub const ALL: [ApiVersion; 2] = [ApiVersion::V1, ApiVersion::V2];
+
+    /// The newest version, which deprecated versions point clients to.
+    pub const LATEST: ApiVersion = ApiVersion::V2;
+
+    /// The version's path prefix and media type name: `v1` or `v2`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "v1",
+            ApiVersion::V2 => "v2",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ApiVersion> {
+        ApiVersion::ALL.into_iter().find(|version| version.name() == name)
+    }
+
+    /// The version named by a vendor media type essence, with the suffix
+    /// naming its format: `application/vnd.rws.v2+json` is `(V2, "json")`.
+    ///
+    /// Vendor types of versions that are not served are `None`.
+    pub fn from_media_type(essence: &str) -> Option<(ApiVersion, &str)> {
+        let (name, suffix) = essence.strip_prefix(VENDOR_PREFIX)?.split_once('+')?;
+        Some((ApiVersion::from_name(name)?, suffix))
+    }
+}
+
+tokio::task_local! {
+    static VERSION: ApiVersion;
+}
+
+/// Run `future` as handling a request for `version`.
+pub async fn scope<F: Future>(version: ApiVersion, future: F) -> F::Output {
+    VERSION.scope(version, future).await
+}
+
+/// The version of the running request; v1 outside of one, or for a path
+/// outside the versioned route groups.
+pub fn current() -> ApiVersion {
+    VERSION.try_with(|version| *version).unwrap_or(ApiVersion::V1)
+}
+
+/// Whether a path (split into segments) belongs to a versioned route group.
+///
+/// These are the REST resources; pages, GraphQL and the like are not
+/// versioned.
+fn is_versioned(segments: &[&str]) -> bool {
+    matches!(segments, ["tasks", ..] | ["tags"] | ["audit"] | ["webhooks", ..])
+}
+
+/// The versions an `Accept` header names with vendor media types, with their
+/// quality. Versions that are not served are left out.
+fn accepted(headers: &HeaderMap) -> Vec<(ApiVersion, f32)> {
+    accept::parse(headers, ACCEPT)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(range, q)| Some((ApiVersion::from_media_type(&range)?.0, q)))
+        .filter(|&(_, q)| q > 0.0)
+        .collect()
+}
+
+/// How a request's version was chosen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resolved {
+    /// By a `/v1` or `/v2` path prefix.
+    Path(ApiVersion),
+    /// By `Accept`, or by default, for an unversioned path.
+    Negotiated(ApiVersion),
+    /// A path outside the versioned route groups.
+    Unversioned,
+}
+
+/// Resolve the version of a request for the path `segments`, returning it
+/// with the path the routes match, stripped of any version prefix.
+///
+/// A prefix wins over `Accept`, but an `Accept` header that only names other
+/// versions contradicts it, and is an error.
+pub fn resolve<'a>(segments: &'a [&'a str], headers: &HeaderMap) -> Result<(Resolved, &'a [&'a str]), String> {
+    let accepted = accepted(headers);
+    if let Some((first, rest)) = segments.split_first() {
+        if let Some(version) = ApiVersion::from_name(first).filter(|_| is_versioned(rest)) {
+            if !accepted.is_empty() && !accepted.iter().any(|&(accepted, _)| accepted == version) {
+                return Err(format!("The path selects API {} but Accept does not allow it", version.name()));
+            }
+            return Ok((Resolved::Path(version), rest));
+        }
+    }
+    if !is_versioned(segments) {
+        return Ok((Resolved::Unversioned, segments));
+    }
+    // The highest quality wins, and the newest version breaks ties
+    let version = accepted
+        .iter()
+        .max_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)))
+        .map_or(ApiVersion::V1, |&(version, _)| version);
+    Ok((Resolved::Negotiated(version), segments))
+}
+
+impl Resolved {
+    pub fn version(self) -> Option<ApiVersion> {
+        match self {
+            Resolved::Path(version) | Resolved::Negotiated(version) => Some(version),
+            Resolved::Unversioned => None,
+        }
+    }
+
+    /// Add the headers a response to a request of this version carries.
+    ///
+    /// v1 is deprecated on `v1_deprecation`'s schedule, if given, and then
+    /// gets `Deprecation` (RFC 9745) and `Sunset` (RFC 8594) headers, and a
+    /// `Link` to the same path in the latest version. Every version varies
+    /// with `Accept`, which picks the format even when the path picks the
+    /// version.
+    pub fn decorate<B>(self, response: &mut Response<B>, segments: &[&str], v1_deprecation: Option<Deprecation>) {
+        let Some(version) = self.version() else {
+            return;
+        };
+        let headers = response.headers_mut();
+        headers.append(VARY, HeaderValue::from_static("accept"));
+        if let Some(deprecation) = v1_deprecation.filter(|_| version == ApiVersion::V1) {
+            let since = format!("@{}", deprecation.since.timestamp());
+            let sunset = deprecation.sunset.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+            let successor = format!("</{}/{}>; rel=\"successor-version\"", ApiVersion::LATEST.name(), segments.join("/"));
+            for (name, value) in [("deprecation", since), ("sunset", sunset)] {
+                headers.insert(name, HeaderValue::from_str(&value).unwrap());
+            }
+            if let Ok(link) = HeaderValue::from_str(&successor) {
+                headers.append(LINK, link);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
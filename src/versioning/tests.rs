@@ -0,0 +1,85 @@
+use super::*;
+use chrono::TimeZone;
+use hyper::header::HeaderValue;
+
+fn accept(value: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT, HeaderValue::from_str(value).unwrap());
+    headers
+}
+
+#[test]
+fn test_from_media_type() {
+    assert_eq!(ApiVersion::from_media_type("application/vnd.rws.v2+json"), Some((ApiVersion::V2, "json")));
+    assert_eq!(ApiVersion::from_media_type("application/vnd.rws.v1+cbor"), Some((ApiVersion::V1, "cbor")));
+    assert_eq!(ApiVersion::from_media_type("application/vnd.rws.v3+json"), None);
+    assert_eq!(ApiVersion::from_media_type("application/vnd.rws.v2"), None);
+    assert_eq!(ApiVersion::from_media_type("application/json"), None);
+}
+
+#[test]
+fn test_resolve_path_prefix() {
+    let none = HeaderMap::new();
+    assert_eq!(resolve(&["v2", "tasks", "1"], &none), Ok((Resolved::Path(ApiVersion::V2), &["tasks", "1"][..])));
+    assert_eq!(resolve(&["v1", "tags"], &none), Ok((Resolved::Path(ApiVersion::V1), &["tags"][..])));
+
+    // Only the REST resources are versioned, and only known versions
+    assert_eq!(resolve(&["v1", "health"], &none), Ok((Resolved::Unversioned, &["v1", "health"][..])));
+    assert_eq!(resolve(&["v3", "tasks"], &none), Ok((Resolved::Unversioned, &["v3", "tasks"][..])));
+    assert_eq!(resolve(&["health"], &none), Ok((Resolved::Unversioned, &["health"][..])));
+}
+
+#[test]
+fn test_resolve_negotiates_unversioned_paths() {
+    let segments = ["tasks"];
+    let negotiated = |headers: &HeaderMap| resolve(&segments, headers).unwrap().0;
+    assert_eq!(negotiated(&HeaderMap::new()), Resolved::Negotiated(ApiVersion::V1));
+    assert_eq!(negotiated(&accept("application/json")), Resolved::Negotiated(ApiVersion::V1));
+    assert_eq!(negotiated(&accept("application/vnd.rws.v2+json")), Resolved::Negotiated(ApiVersion::V2));
+    assert_eq!(
+        negotiated(&accept("application/vnd.rws.v2+json;q=0.5, application/vnd.rws.v1+json")),
+        Resolved::Negotiated(ApiVersion::V1)
+    );
+    assert_eq!(
+        negotiated(&accept("application/vnd.rws.v1+json, application/vnd.rws.v2+json")),
+        Resolved::Negotiated(ApiVersion::V2)
+    );
+    assert_eq!(negotiated(&accept("application/vnd.rws.v2+json;q=0")), Resolved::Negotiated(ApiVersion::V1));
+}
+
+#[test]
+fn test_resolve_rejects_contradicting_accept() {
+    assert!(resolve(&["v1", "tasks"], &accept("application/vnd.rws.v2+json")).is_err());
+    assert!(resolve(&["v1", "tasks"], &accept("application/vnd.rws.v2+json, application/vnd.rws.v1+json")).is_ok());
+    assert!(resolve(&["v2", "tasks"], &accept("application/json")).is_ok());
+}
+
+#[test]
+fn test_decorate_deprecated_versions() {
+    let deprecation = Deprecation {
+        since: Utc.with_ymd_and_hms(2026, 10, 18, 0, 0, 0).unwrap(),
+        sunset: Utc.with_ymd_and_hms(2027, 4, 30, 0, 0, 0).unwrap(),
+    };
+    let mut response = Response::new(());
+    Resolved::Negotiated(ApiVersion::V1).decorate(&mut response, &["tasks", "7"], Some(deprecation));
+    let headers = response.headers();
+    assert_eq!(headers["deprecation"], "@1792281600");
+    assert_eq!(headers["sunset"], "Fri, 30 Apr 2027 00:00:00 GMT");
+    assert_eq!(headers[LINK], "</v2/tasks/7>; rel=\"successor-version\"");
+    assert_eq!(headers[VARY], "accept");
+
+    let mut response = Response::new(());
+    Resolved::Path(ApiVersion::V2).decorate(&mut response, &["tasks"], Some(deprecation));
+    assert_eq!(response.headers().len(), 1);
+    assert_eq!(response.headers()[VARY], "accept");
+
+    let mut response = Response::new(());
+    Resolved::Unversioned.decorate(&mut response, &["health"], Some(deprecation));
+    assert!(response.headers().is_empty());
+
+    // Without a schedule, v1 is not deprecated
+    let mut response = Response::new(());
+    Resolved::Path(ApiVersion::V1).decorate(&mut response, &["tasks"], None);
+    assert_eq!(response.headers().len(), 1);
+    assert!(response.headers().get("deprecation").is_none());
+}
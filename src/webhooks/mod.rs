@@ -0,0 +1,480 @@
+use crate::audit::AuditAction;
+use crate::events::TaskEvent;
+use crate::store::Store;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use http_body_util::Full;
+use hyper::header::{HeaderName, CONTENT_TYPE};
+use hyper::{Method, Request, Uri};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::client::legacy::connect::dns::Name;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, RwLock};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tower_service::Service;
+use utoipa::ToSchema;
+use tracing::{info, warn};
+
+/// Header carrying the ID of the webhook a delivery is for.
+pub const WEBHOOK_ID: HeaderName = HeaderName::from_static("x-webhook-id");
+
+/// Header carrying the type of the delivered event.
+pub const WEBHOOK_EVENT: HeaderName = HeaderName::from_static("x-webhook-event");
+
+/// Header carrying the delivered event's ID; the same on every retry.
+pub const WEBHOOK_DELIVERY: HeaderName = HeaderName::from_static("x-webhook-delivery");
+
+/// Header carrying the Unix time the request was signed at.
+pub const WEBHOOK_TIMESTAMP: HeaderName = HeaderName::from_static("x-webhook-timestamp");
+
+/// Header carrying the request's signature (see `sign`).
+pub const WEBHOOK_SIGNATURE: HeaderName = HeaderName::from_static("x-webhook-signature");
+
+/// Longest webhook secret accepted, in bytes.
+pub const MAX_SECRET_LEN: usize = 256;
+
+/// How long a receiver has to answer one delivery attempt.
+const ATTEMPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest wait between two attempts, however many have failed.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// How many delivery attempts are kept in the log, across all webhooks.
+const DELIVERY_LOG_CAPACITY: usize = 1000;
+
+/// How many dead letters are kept.
+const DEAD_LETTER_CAPACITY: usize = 1000;
+
+/// A subscription to task events, delivered by `POST` to `url`.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct Webhook {
+    pub id: u64,
+    pub url: String,
+    /// The event types delivered; all of them if empty.
+    pub events: BTreeSet<AuditAction>,
+    /// Key for the request signatures. Never sent back to clients.
+    #[serde(skip)]
+    pub secret: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Webhook {
+    pub fn wants(&self, event: &TaskEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event.action)
+    }
+}
+
+/// The body of `POST /webhooks`.
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct CreateWebhook {
+    pub url: String,
+    #[serde(default)]
+    pub events: BTreeSet<AuditAction>,
+    pub secret: String,
+}
+
+impl CreateWebhook {
+    /// Returns an error message keyed by field name for each invalid field.
+    ///
+    /// Only `http://` and `https://` URLs can be delivered to. Where they
+    /// point is checked by `Webhooks::check_destination`, as that takes a DNS
+    /// lookup.
+    pub fn validate(&self) -> BTreeMap<&'static str, String> {
+        let mut errors = BTreeMap::new();
+        let uri = self.url.parse::<Uri>();
+        let valid = uri.is_ok_and(|uri| matches!(uri.scheme_str(), Some("http" | "https")) && uri.host().is_some());
+        if !valid {
+            errors.insert("url", "URL must be an absolute http:// or https:// URL".to_string());
+        }
+        if self.secret.is_empty() || self.secret.len() > MAX_SECRET_LEN {
+            errors.insert("secret", format!("Secret must be between 1 and {} bytes", MAX_SECRET_LEN));
+        }
+        errors
+    }
+}
+
+/// One attempt at delivering an event to a webhook.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct Delivery {
+    pub webhook_id: u64,
+    pub event_id: u64,
+    pub event_type: AuditAction,
+    /// Which attempt this was, starting at 1.
+    pub attempt: u32,
+    pub timestamp: DateTime<Utc>,
+    pub succeeded: bool,
+    /// The receiver's response status, if it answered.
+    pub status_code: Option<u16>,
+    pub error: Option<String>,
+}
+
+/// An event that could not be delivered to a webhook within the allowed
+/// attempts.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct DeadLetter {
+    pub webhook_id: u64,
+    pub url: String,
+    pub event: TaskEvent,
+    pub attempts: u32,
+    pub last_error: String,
+    pub failed_at: DateTime<Utc>,
+}
+
+/// How failed deliveries are retried.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Attempts made in total before giving up; at least 1.
+    pub max_attempts: u32,
+    /// Wait before the first retry; it doubles for each retry after that.
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// The wait before the attempt following attempt number `attempt`.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.base_delay.saturating_mul(factor).min(MAX_RETRY_DELAY)
+    }
+}
+
+/// Sign a request body sent at `timestamp` (Unix seconds) with `secret`.
+///
+/// The signature is `sha256=` followed by the hex HMAC-SHA256 of
+/// `"{timestamp}.{body}"`, so receivers can also reject stale requests.
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Whether `ip` is an address on the public internet, rather than this
+/// machine, a private network or a reserved range.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || first == 0
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (first == 100 && second & 0xc0 == 64)
+                // Benchmarking, 198.18.0.0/15
+                || (first == 198 && second & 0xfe == 18)
+                // Reserved, 240.0.0.0/4
+                || first >= 240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let [first, second, ..] = ip.segments();
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+                    // NAT64, 64:ff9b::/96 and 64:ff9b:1::/48, which reach
+                    // IPv4 addresses the checks above never see
+                    || (first == 0x64 && second == 0xff9b)
+                    // Documentation, 2001:db8::/32
+                    || (first == 0x2001 && second == 0xdb8))
+            }
+        },
+    }
+}
+
+/// Resolves the hosts webhooks are delivered to, refusing those with an
+/// address that is not public unless they are allowed by name.
+///
+/// The client resolves through it when connecting, so a host cannot pass the
+/// check at registration and then be pointed elsewhere.
+#[derive(Clone)]
+struct PublicResolver {
+    /// Lowercase hosts that may have any address, such as a receiver on the
+    /// local network.
+    allowed_hosts: Arc<BTreeSet<String>>,
+}
+
+impl PublicResolver {
+    /// The addresses of `host`, or why it may not be delivered to.
+    async fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, String> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|err| format!("Could not resolve {}: {}", host, err))?
+            .collect();
+        if !self.allowed_hosts.contains(&host.to_ascii_lowercase()) && !addrs.iter().all(|addr| is_public(addr.ip())) {
+            return Err(format!("{} is not a public address", host));
+        }
+        Ok(addrs)
+    }
+}
+
+impl Service<Name> for PublicResolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, io::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let resolver = self.clone();
+        Box::pin(async move {
+            match resolver.resolve(name.as_str(), 0).await {
+                Ok(addrs) => Ok(addrs.into_iter()),
+                Err(message) => Err(io::Error::new(io::ErrorKind::PermissionDenied, message)),
+            }
+        })
+    }
+}
+
+/// The webhook subscriptions and what happened to their deliveries.
+pub struct Webhooks {
+    hooks: RwLock<BTreeMap<u64, Webhook>>,
+    next_id: Mutex<u64>,
+    deliveries: Mutex<VecDeque<Delivery>>,
+    dead_letters: Mutex<VecDeque<DeadLetter>>,
+    policy: RetryPolicy,
+    resolver: PublicResolver,
+    client: Client<HttpsConnector<HttpConnector<PublicResolver>>, Full<Bytes>>,
+}
+
+impl Webhooks {
+    /// Creates the webhooks, delivering only to public addresses except for
+    /// the hosts in `allowed_hosts`.
+    pub fn new(policy: RetryPolicy, allowed_hosts: impl IntoIterator<Item = String>) -> Self {
+        let resolver = PublicResolver {
+            allowed_hosts: Arc::new(allowed_hosts.into_iter().map(|host| host.to_ascii_lowercase()).collect()),
+        };
+        let mut http = HttpConnector::new_with_resolver(resolver.clone());
+        http.enforce_http(false);
+        let https = HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http1()
+            .wrap_connector(http);
+        Webhooks {
+            hooks: RwLock::new(BTreeMap::new()),
+            next_id: Mutex::new(1),
+            deliveries: Mutex::new(VecDeque::new()),
+            dead_letters: Mutex::new(VecDeque::new()),
+            policy,
+            resolver,
+            client: Client::builder(TokioExecutor::new()).build(https),
+        }
+    }
+
+    /// Checks that a valid webhook URL may be delivered to, returning why not
+    /// otherwise: its host must resolve, and only to public addresses unless
+    /// it is allowed.
+    pub async fn check_destination(&self, url: &str) -> Result<(), String> {
+        let uri = url.parse::<Uri>().map_err(|err| err.to_string())?;
+        let host = uri.host().ok_or("URL has no host")?;
+        self.resolver.resolve(host, uri.port_u16().unwrap_or(0)).await.map(|_| ())
+    }
+
+    /// Adds a webhook, returning it. The input is assumed to be valid.
+    pub fn create(&self, create: CreateWebhook) -> Webhook {
+        let mut next_id = self.next_id.lock().unwrap();
+        let hook = Webhook {
+            id: *next_id,
+            url: create.url,
+            events: create.events,
+            secret: create.secret,
+            created_at: Utc::now(),
+        };
+        *next_id += 1;
+        self.hooks.write().unwrap().insert(hook.id, hook.clone());
+        hook
+    }
+
+    pub fn list(&self) -> Vec<Webhook> {
+        self.hooks.read().unwrap().values().cloned().collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<Webhook> {
+        self.hooks.read().unwrap().get(&id).cloned()
+    }
+
+    /// Removes a webhook, returning it. Retries already scheduled for it are
+    /// dropped.
+    pub fn delete(&self, id: u64) -> Option<Webhook> {
+        self.hooks.write().unwrap().remove(&id)
+    }
+
+    /// The logged delivery attempts for a webhook, oldest first.
+    pub fn deliveries(&self, webhook_id: u64) -> Vec<Delivery> {
+        let deliveries = self.deliveries.lock().unwrap();
+        deliveries.iter().filter(|delivery| delivery.webhook_id == webhook_id).cloned().collect()
+    }
+
+    /// The deliveries that ran out of attempts, oldest first.
+    pub fn dead_letters(&self) -> Vec<DeadLetter> {
+        self.dead_letters.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Spawns the background worker that delivers every change made to the
+    /// store to the webhooks that want it.
+    ///
+    /// Each delivery runs on its own, so a slow or failing receiver does not
+    /// hold up the others; deliveries to one webhook may arrive out of order.
+    pub fn spawn_worker(self: &Arc<Self>, store: Arc<Store>) {
+        let webhooks = Arc::clone(self);
+        // Subscribing before spawning delivers every change made once this
+        // returns
+        let (_, mut events) = store.subscribe(None);
+        tokio::task::spawn(async move {
+            let mut last_id = None;
+            loop {
+                let batch = match events.recv().await {
+                    Ok(event) => vec![event],
+                    // Catch up from the store's replay buffer
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!(skipped, "Webhook worker fell behind; replaying missed events");
+                        let (missed, receiver) = store.subscribe(last_id);
+                        events = receiver;
+                        missed
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                for event in batch {
+                    last_id = Some(event.id);
+                    webhooks.dispatch(event);
+                }
+            }
+        });
+    }
+
+    /// Starts delivering an event to every webhook that wants it.
+    fn dispatch(self: &Arc<Self>, event: TaskEvent) {
+        let hooks: Vec<Webhook> = self.hooks.read().unwrap().values().filter(|hook| hook.wants(&event)).cloned().collect();
+        for hook in hooks {
+            let webhooks = Arc::clone(self);
+            let event = event.clone();
+            tokio::task::spawn(async move { webhooks.deliver(hook, event).await });
+        }
+    }
+
+    /// Delivers an event to a webhook, retrying with exponential backoff
+    /// until it succeeds, the attempts run out or the webhook is deleted.
+    async fn deliver(&self, hook: Webhook, event: TaskEvent) {
+        let body = Bytes::from(serde_json::to_vec(&event).unwrap_or_default());
+        let max_attempts = self.policy.max_attempts.max(1);
+        for attempt in 1..=max_attempts {
+            let result = self.attempt(&hook, &event, body.clone()).await;
+            let (status_code, error) = match &result {
+                Ok(status) if (200..300).contains(status) => (Some(*status), None),
+                Ok(status) => (Some(*status), Some(format!("Receiver responded with status {}", status))),
+                Err(err) => (None, Some(err.clone())),
+            };
+            self.log(Delivery {
+                webhook_id: hook.id,
+                event_id: event.id,
+                event_type: event.action,
+                attempt,
+                timestamp: Utc::now(),
+                succeeded: error.is_none(),
+                status_code,
+                error: error.clone(),
+            });
+            let Some(error) = error else {
+                return;
+            };
+
+            if attempt == max_attempts {
+                warn!(webhook_id = hook.id, event_id = event.id, %error, "Webhook delivery failed for good");
+                let mut dead_letters = self.dead_letters.lock().unwrap();
+                if dead_letters.len() == DEAD_LETTER_CAPACITY {
+                    dead_letters.pop_front();
+                }
+                dead_letters.push_back(DeadLetter {
+                    webhook_id: hook.id,
+                    url: hook.url.clone(),
+                    event: event.clone(),
+                    attempts: attempt,
+                    last_error: error,
+                    failed_at: Utc::now(),
+                });
+                return;
+            }
+            tokio::time::sleep(self.policy.delay(attempt)).await;
+            if self.get(hook.id).is_none() {
+                info!(webhook_id = hook.id, "Webhook deleted; dropping its retries");
+                return;
+            }
+        }
+    }
+
+    /// Makes one delivery attempt, returning the receiver's status.
+    async fn attempt(&self, hook: &Webhook, event: &TaskEvent, body: Bytes) -> Result<u16, String> {
+        // The client connects to IP addresses without resolving them
+        if let Some(host) = hook.url.parse::<Uri>().ok().as_ref().and_then(Uri::host) {
+            if host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>().is_ok() {
+                self.resolver.resolve(host, 0).await?;
+            }
+        }
+        let timestamp = Utc::now().timestamp();
+        let action = serde_json::to_value(event.action).unwrap_or_default();
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(&hook.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(WEBHOOK_ID, hook.id)
+            .header(WEBHOOK_EVENT, action.as_str().unwrap_or_default())
+            .header(WEBHOOK_DELIVERY, event.id)
+            .header(WEBHOOK_TIMESTAMP, timestamp)
+            .header(WEBHOOK_SIGNATURE, sign(&hook.secret, timestamp, &body))
+            .body(Full::new(body))
+            .map_err(|err| err.to_string())?;
+        match tokio::time::timeout(ATTEMPT_TIMEOUT, self.client.request(request)).await {
+            Ok(Ok(response)) => Ok(response.status().as_u16()),
+            Ok(Err(err)) => Err(describe(&err)),
+            Err(_) => Err("Timed out waiting for the receiver".to_string()),
+        }
+    }
+
+    fn log(&self, delivery: Delivery) {
+        let mut deliveries = self.deliveries.lock().unwrap();
+        if deliveries.len() == DELIVERY_LOG_CAPACITY {
+            deliveries.pop_front();
+        }
+        deliveries.push_back(delivery);
+    }
+}
+
+/// An error's message followed by those of its sources, since the client's
+/// own messages say little more than which stage failed.
+fn describe(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        message.push_str(": ");
+        message.push_str(&err.to_string());
+        source = err.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests;
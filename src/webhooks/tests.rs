@@ -0,0 +1,269 @@
+use super::*;
+use crate::models::CreateTask;
+use hyper::body::Incoming;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use std::convert::Infallible;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+/// A request received by a `receiver`.
+struct Received {
+    headers: hyper::HeaderMap,
+    body: Bytes,
+}
+
+/// Start a local HTTP server answering with each of `statuses` in turn, then
+/// 200 OK. Returns its URL and the requests it receives.
+async fn receiver(statuses: Vec<StatusCode>) -> (String, mpsc::UnboundedReceiver<Received>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let (sender, received) = mpsc::unbounded_channel();
+    let statuses = Arc::new(Mutex::new(VecDeque::from(statuses)));
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let sender = sender.clone();
+            let statuses = Arc::clone(&statuses);
+            tokio::spawn(async move {
+                let service = service_fn(move |req: Request<Incoming>| {
+                    let sender = sender.clone();
+                    let status = statuses.lock().unwrap().pop_front().unwrap_or(StatusCode::OK);
+                    async move {
+                        let headers = req.headers().clone();
+                        let body = http_body_util::BodyExt::collect(req.into_body()).await.unwrap().to_bytes();
+                        let _ = sender.send(Received { headers, body });
+                        Ok::<_, Infallible>(Response::builder().status(status).body(Full::new(Bytes::new())).unwrap())
+                    }
+                });
+                let _ = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await;
+            });
+        }
+    });
+    (url, received)
+}
+
+fn create_webhook(url: &str, events: &[AuditAction]) -> CreateWebhook {
+    CreateWebhook {
+        url: url.to_string(),
+        events: events.iter().copied().collect(),
+        secret: "s3cret".to_string(),
+    }
+}
+
+fn policy(max_attempts: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts,
+        base_delay: Duration::from_millis(10),
+    }
+}
+
+/// Webhooks that may deliver to the local receivers.
+fn webhooks(max_attempts: u32) -> Webhooks {
+    Webhooks::new(policy(max_attempts), ["127.0.0.1".to_string()])
+}
+
+async fn create_task(store: &Store, title: &str) -> u64 {
+    let task: CreateTask = serde_json::from_value(serde_json::json!({ "title": title, "description": "" })).unwrap();
+    store.create_task(task).await.unwrap().id
+}
+
+/// Wait for the delivery log of a webhook to reach `count` attempts.
+async fn wait_for_deliveries(webhooks: &Webhooks, webhook_id: u64, count: usize) -> Vec<Delivery> {
+    for _ in 0..200 {
+        let deliveries = webhooks.deliveries(webhook_id);
+        if deliveries.len() >= count {
+            return deliveries;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("expected {} deliveries to webhook {}", count, webhook_id);
+}
+
+#[test]
+fn test_validate() {
+    assert!(create_webhook("http://localhost:8080/hook", &[]).validate().is_empty());
+
+    assert!(create_webhook("https://example.com/hook", &[]).validate().is_empty());
+    let errors = create_webhook("ftp://example.com/hook", &[]).validate();
+    assert!(errors.contains_key("url"));
+    assert!(create_webhook("/hook", &[]).validate().contains_key("url"));
+
+    let mut create = create_webhook("http://example.com", &[]);
+    create.secret = String::new();
+    assert_eq!(create.validate().keys().copied().collect::<Vec<_>>(), ["secret"]);
+}
+
+#[test]
+fn test_is_public() {
+    for ip in ["93.184.215.14", "198.20.0.1", "2606:4700::1111", "::ffff:93.184.215.14", "2001:db9::1"] {
+        assert!(is_public(ip.parse().unwrap()), "{}", ip);
+    }
+    let private = [
+        "127.0.0.1",
+        "10.1.2.3",
+        "172.16.0.1",
+        "192.168.1.1",
+        "169.254.169.254",
+        "100.64.0.1",
+        "0.0.0.0",
+        "::1",
+        "fd00::1",
+        "fe80::1",
+        "::ffff:127.0.0.1",
+        "198.18.0.1",
+        "198.19.255.254",
+        "240.0.0.1",
+        "255.255.255.254",
+        "64:ff9b::7f00:1",
+        "64:ff9b::5db8:d70e",
+        "64:ff9b:1::a00:1",
+        "2001:db8::1",
+    ];
+    for ip in private {
+        assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+    }
+}
+
+#[tokio::test]
+async fn test_check_destination_refuses_private_hosts() {
+    let webhooks = Webhooks::new(policy(1), ["Receiver.localhost".to_string()]);
+    for url in ["http://127.0.0.1/hook", "https://169.254.169.254/", "http://[::1]:8080/", "http://localhost/hook"] {
+        let error = webhooks.check_destination(url).await.unwrap_err();
+        assert!(error.ends_with("is not a public address"), "{}: {}", url, error);
+    }
+    assert!(webhooks.check_destination("http://93.184.215.14/hook").await.is_ok());
+
+    // Allowed hosts may resolve anywhere
+    let webhooks = Webhooks::new(policy(1), ["LocalHost".to_string()]);
+    assert!(webhooks.check_destination("http://localhost:8080/hook").await.is_ok());
+    assert!(webhooks.check_destination("http://127.0.0.1/hook").await.is_err());
+}
+
+#[tokio::test]
+async fn test_refuses_to_deliver_to_private_hosts() {
+    let store = Arc::new(Store::new());
+    let webhooks = Arc::new(Webhooks::new(policy(1), []));
+    let (url, mut received) = receiver(vec![]).await;
+    // Registered without the check, as if the host had since been pointed
+    // at a private address
+    let by_address = webhooks.create(create_webhook(&url, &[]));
+    let by_name = webhooks.create(create_webhook(&url.replace("127.0.0.1", "localhost"), &[]));
+    webhooks.spawn_worker(Arc::clone(&store));
+
+    create_task(&store, "Secret").await;
+
+    for webhook in [by_address, by_name] {
+        let deliveries = wait_for_deliveries(&webhooks, webhook.id, 1).await;
+        assert!(!deliveries[0].succeeded);
+        let error = deliveries[0].error.as_deref().unwrap();
+        assert!(error.contains("is not a public address"), "{}", error);
+    }
+    assert!(received.try_recv().is_err());
+}
+
+#[test]
+fn test_retry_delay_doubles_up_to_a_cap() {
+    let policy = RetryPolicy {
+        max_attempts: 5,
+        base_delay: Duration::from_secs(1),
+    };
+    assert_eq!(policy.delay(1), Duration::from_secs(1));
+    assert_eq!(policy.delay(2), Duration::from_secs(2));
+    assert_eq!(policy.delay(4), Duration::from_secs(8));
+    assert_eq!(policy.delay(40), MAX_RETRY_DELAY);
+}
+
+#[test]
+fn test_sign() {
+    // echo -n '1700000000.{}' | openssl dgst -sha256 -hmac key
+    assert_eq!(
+        sign("key", 1_700_000_000, b"{}"),
+        "sha256=9d713ed406bb7076d4123f0dc2c39d2df5c654ed4b0cd56b52c8b4c940bd63ae"
+    );
+    assert_ne!(sign("key", 1_700_000_000, b"{}"), sign("other", 1_700_000_000, b"{}"));
+    assert_ne!(sign("key", 1_700_000_000, b"{}"), sign("key", 1_700_000_001, b"{}"));
+}
+
+#[test]
+fn test_secret_is_not_serialized() {
+    let webhooks = webhooks(1);
+    let webhook = webhooks.create(create_webhook("http://localhost/hook", &[AuditAction::Created]));
+    let value = serde_json::to_value(&webhook).unwrap();
+    assert!(value.get("secret").is_none());
+    assert_eq!(value["events"], serde_json::json!(["created"]));
+}
+
+#[tokio::test]
+async fn test_delivers_signed_events() {
+    let store = Arc::new(Store::new());
+    let webhooks = Arc::new(webhooks(3));
+    let (url, mut received) = receiver(vec![]).await;
+    let webhook = webhooks.create(create_webhook(&url, &[AuditAction::Created]));
+    webhooks.spawn_worker(Arc::clone(&store));
+
+    let task_id = create_task(&store, "Ship it").await;
+    store.delete_task(task_id).await.unwrap();
+
+    let request = received.recv().await.unwrap();
+    let header = |name: &HeaderName| request.headers.get(name).unwrap().to_str().unwrap().to_string();
+    assert_eq!(header(&WEBHOOK_ID), webhook.id.to_string());
+    assert_eq!(header(&WEBHOOK_EVENT), "created");
+    let timestamp: i64 = header(&WEBHOOK_TIMESTAMP).parse().unwrap();
+    assert_eq!(header(&WEBHOOK_SIGNATURE), sign("s3cret", timestamp, &request.body));
+
+    let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+    assert_eq!(body["type"], "created");
+    assert_eq!(body["task"]["title"], "Ship it");
+    assert_eq!(header(&WEBHOOK_DELIVERY), body["id"].to_string());
+
+    let deliveries = wait_for_deliveries(&webhooks, webhook.id, 1).await;
+    assert!(deliveries[0].succeeded);
+    assert_eq!(deliveries[0].status_code, Some(200));
+
+    // The deletion was filtered out
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(received.try_recv().is_err());
+    assert_eq!(webhooks.deliveries(webhook.id).len(), 1);
+}
+
+#[tokio::test]
+async fn test_retries_failed_deliveries() {
+    let store = Arc::new(Store::new());
+    let webhooks = Arc::new(webhooks(3));
+    let (url, _received) = receiver(vec![StatusCode::INTERNAL_SERVER_ERROR, StatusCode::SERVICE_UNAVAILABLE]).await;
+    let webhook = webhooks.create(create_webhook(&url, &[]));
+    webhooks.spawn_worker(Arc::clone(&store));
+
+    create_task(&store, "Flaky").await;
+
+    let deliveries = wait_for_deliveries(&webhooks, webhook.id, 3).await;
+    let attempts: Vec<(u32, bool, Option<u16>)> =
+        deliveries.iter().map(|delivery| (delivery.attempt, delivery.succeeded, delivery.status_code)).collect();
+    assert_eq!(attempts, [(1, false, Some(500)), (2, false, Some(503)), (3, true, Some(200))]);
+    assert!(deliveries.iter().all(|delivery| delivery.event_id == deliveries[0].event_id));
+    assert!(webhooks.dead_letters().is_empty());
+}
+
+#[tokio::test]
+async fn test_dead_letters_undeliverable_events() {
+    let store = Arc::new(Store::new());
+    let webhooks = Arc::new(webhooks(2));
+    let (url, _received) = receiver(vec![StatusCode::BAD_GATEWAY; 2]).await;
+    let webhook = webhooks.create(create_webhook(&url, &[]));
+    webhooks.spawn_worker(Arc::clone(&store));
+
+    let task_id = create_task(&store, "Doomed").await;
+
+    let deliveries = wait_for_deliveries(&webhooks, webhook.id, 2).await;
+    assert!(deliveries.iter().all(|delivery| !delivery.succeeded));
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let dead_letters = webhooks.dead_letters();
+    assert_eq!(dead_letters.len(), 1);
+    assert_eq!(dead_letters[0].webhook_id, webhook.id);
+    assert_eq!(dead_letters[0].event.task_id, task_id);
+    assert_eq!(dead_letters[0].attempts, 2);
+    assert!(dead_letters[0].last_error.contains("502"));
+}
@@ -0,0 +1,281 @@
+use crate::audit::{self, AuditContext};
+use crate::events::TaskEvent;
+use crate::handlers::tasks::store_error_status;
+use crate::models::{self, BatchOperation, CreateTask, Task, UpdateTask};
+use crate::store::Store;
+use futures_util::{SinkExt, StreamExt};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::{self, Instant};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// How often the server pings each client. A client that has not answered
+/// the previous ping by the next one is disconnected.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How many messages can be waiting to be written to a client. Change
+/// broadcasts that find the queue full disconnect the client, since it is
+/// not keeping up.
+pub const OUTBOX_CAPACITY: usize = 64;
+
+/// A message from a client.
+///
+/// `ref` is an optional client-chosen tag, echoed back in the reply so
+/// clients can match replies to commands.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ClientMessage {
+    /// Receive changes to the tasks with any of `ids` or `tags`, or to every
+    /// task if both are empty. Replaces any earlier subscription.
+    Subscribe {
+        #[serde(default)]
+        ids: BTreeSet<u64>,
+        #[serde(default)]
+        tags: BTreeSet<String>,
+    },
+    /// Stop receiving changes.
+    Unsubscribe,
+    Create {
+        #[serde(default, rename = "ref")]
+        reference: Option<String>,
+        task: CreateTask,
+    },
+    /// Update a task, optionally only if it is still at `version`.
+    Update {
+        #[serde(default, rename = "ref")]
+        reference: Option<String>,
+        id: u64,
+        task: UpdateTask,
+        #[serde(default)]
+        version: Option<u64>,
+    },
+}
+
+/// A message to a client.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ServerMessage {
+    /// The client's subscription changed; `None` after unsubscribing.
+    Subscribed { subscription: Option<Subscription> },
+    /// A command succeeded.
+    Ack {
+        #[serde(rename = "ref", skip_serializing_if = "Option::is_none")]
+        reference: Option<String>,
+        request_id: Uuid,
+        task: Task,
+    },
+    /// A message could not be handled; `status` is the HTTP status the same
+    /// failure gets over HTTP.
+    Error {
+        #[serde(rename = "ref", skip_serializing_if = "Option::is_none")]
+        reference: Option<String>,
+        status: u16,
+        error: String,
+    },
+    /// A task the client subscribed to changed.
+    Change { event: TaskEvent },
+}
+
+impl ServerMessage {
+    fn to_message(&self) -> Message {
+        Message::text(serde_json::to_string(self).unwrap_or_default())
+    }
+}
+
+/// The set of tasks a client receives changes for.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Subscription {
+    pub ids: BTreeSet<u64>,
+    /// Normalized tags.
+    pub tags: BTreeSet<String>,
+}
+
+impl Subscription {
+    pub fn new(ids: BTreeSet<u64>, tags: BTreeSet<String>) -> Self {
+        Subscription {
+            ids,
+            tags: tags.iter().map(|tag| models::normalize_tag(tag)).collect(),
+        }
+    }
+
+    /// Whether the event concerns a subscribed task.
+    ///
+    /// Tags are matched against the task as it is after the change, so a
+    /// task that loses a subscribed tag is not reported any more.
+    pub fn matches(&self, event: &TaskEvent) -> bool {
+        let tagged = || {
+            let tags = event.task.as_ref().map(|task| &task.tags);
+            tags.is_some_and(|tags| !self.tags.is_disjoint(tags))
+        };
+        (self.ids.is_empty() && self.tags.is_empty()) || self.ids.contains(&event.task_id) || tagged()
+    }
+}
+
+/// Why a connection is being closed by the server.
+enum Disconnect {
+    /// The client closed the connection, or it dropped.
+    Gone,
+    /// The client is not reading its messages fast enough.
+    SlowConsumer,
+    /// The client did not answer a ping in time.
+    Unresponsive,
+}
+
+/// Serve one WebSocket client until it disconnects.
+///
+/// Commands run with `actor` as their audit actor, each under a request ID
+/// of its own, which is returned in the acknowledgement.
+pub async fn serve<S>(socket: WebSocketStream<S>, store: Arc<Store>, actor: String)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sink, mut stream) = socket.split();
+
+    // Writes go through a bounded queue drained by their own task, so a slow
+    // client holds up neither the store nor other clients
+    let (outbox, mut queued) = mpsc::channel::<Message>(OUTBOX_CAPACITY);
+    let writer = tokio::spawn(async move {
+        while let Some(message) = queued.recv().await {
+            let closing = matches!(message, Message::Close(_));
+            if sink.send(message).await.is_err() || closing {
+                break;
+            }
+        }
+    });
+
+    let (_, mut changes) = store.subscribe(None);
+    let mut subscription: Option<Subscription> = None;
+    let mut heartbeat = time::interval_at(Instant::now() + HEARTBEAT_INTERVAL, HEARTBEAT_INTERVAL);
+    let mut awaiting_pong = false;
+
+    let disconnect = loop {
+        tokio::select! {
+            message = stream.next() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Pong(_))) => {
+                        awaiting_pong = false;
+                        continue;
+                    }
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break Disconnect::Gone,
+                    // Pings are answered by the protocol layer
+                    Some(Ok(_)) => continue,
+                };
+                let reply = match serde_json::from_str::<ClientMessage>(text.as_str()) {
+                    Ok(message) => handle_message(message, &store, &actor, &mut subscription).await,
+                    Err(err) => ServerMessage::Error {
+                        reference: None,
+                        status: StatusCode::BAD_REQUEST.as_u16(),
+                        error: format!("Invalid message: {}", err),
+                    },
+                };
+                // Waiting here stops reading commands from a client that is
+                // not reading the replies
+                if outbox.send(reply.to_message()).await.is_err() {
+                    break Disconnect::Gone;
+                }
+            }
+            change = changes.recv() => {
+                let event = match change {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(_)) => break Disconnect::SlowConsumer,
+                    Err(broadcast::error::RecvError::Closed) => break Disconnect::Gone,
+                };
+                if !subscription.as_ref().is_some_and(|subscription| subscription.matches(&event)) {
+                    continue;
+                }
+                match outbox.try_send(ServerMessage::Change { event }.to_message()) {
+                    Ok(()) => {}
+                    Err(mpsc::error::TrySendError::Full(_)) => break Disconnect::SlowConsumer,
+                    Err(mpsc::error::TrySendError::Closed(_)) => break Disconnect::Gone,
+                }
+            }
+            _ = heartbeat.tick() => {
+                if awaiting_pong {
+                    break Disconnect::Unresponsive;
+                }
+                awaiting_pong = true;
+                if outbox.try_send(Message::Ping(Default::default())).is_err() {
+                    break Disconnect::SlowConsumer;
+                }
+            }
+        }
+    };
+
+    match disconnect {
+        Disconnect::Gone => debug!("WebSocket client disconnected"),
+        Disconnect::SlowConsumer => {
+            // The queue is full, so a close frame would only wait behind it
+            warn!("Disconnecting WebSocket client that is not keeping up");
+            writer.abort();
+            return;
+        }
+        Disconnect::Unresponsive => {
+            warn!("Disconnecting WebSocket client that stopped answering pings");
+            let _ = outbox.try_send(Message::Close(Some(CloseFrame {
+                code: CloseCode::Policy,
+                reason: "Heartbeat timed out".into(),
+            })));
+        }
+    }
+    drop(outbox);
+    let _ = writer.await;
+}
+
+/// Handle one client message, returning the reply.
+async fn handle_message(
+    message: ClientMessage,
+    store: &Store,
+    actor: &str,
+    subscription: &mut Option<Subscription>,
+) -> ServerMessage {
+    let (reference, operation) = match message {
+        ClientMessage::Subscribe { ids, tags } => {
+            *subscription = Some(Subscription::new(ids, tags));
+            return ServerMessage::Subscribed { subscription: subscription.clone() };
+        }
+        ClientMessage::Unsubscribe => {
+            *subscription = None;
+            return ServerMessage::Subscribed { subscription: None };
+        }
+        ClientMessage::Create { reference, task } => (reference, BatchOperation::Create { task }),
+        ClientMessage::Update { reference, id, task, version } => {
+            (reference, BatchOperation::Update { id, task, version })
+        }
+    };
+
+    // A batch of one validates the command and checks its version exactly
+    // as `POST /tasks/batch` would
+    let request_id = Uuid::new_v4();
+    let context = AuditContext {
+        actor: actor.to_string(),
+        request_id: Some(request_id),
+    };
+    let result = audit::scope(context, store.apply_batch(vec![operation], true)).await;
+    match result.into_iter().next() {
+        Some(Ok(task)) => ServerMessage::Ack { reference, request_id, task },
+        Some(Err(err)) => ServerMessage::Error {
+            reference,
+            status: store_error_status(&err, false).as_u16(),
+            error: err.to_string(),
+        },
+        None => ServerMessage::Error {
+            reference,
+            status: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+            error: "Command was not applied".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests;
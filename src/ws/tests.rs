@@ -0,0 +1,100 @@
+use super::*;
+use crate::audit::AuditAction;
+use serde_json::{json, Value};
+
+fn event(task_id: u64, tags: &[&str]) -> TaskEvent {
+    let mut task: Task = serde_json::from_value(json!({
+        "id": task_id,
+        "title": "Task",
+        "description": "",
+        "completed": false,
+        "version": 1,
+        "created_at": "2024-05-01T12:00:00Z",
+        "updated_at": "2024-05-01T12:00:00Z",
+    }))
+    .unwrap();
+    task.tags = tags.iter().map(|tag| tag.to_string()).collect();
+    TaskEvent {
+        id: 1,
+        action: AuditAction::Updated,
+        task_id,
+        task: Some(task),
+    }
+}
+
+#[test]
+fn test_parse_client_messages() {
+    let message: ClientMessage = serde_json::from_str(r#"{"type":"subscribe","ids":[1,2]}"#).unwrap();
+    assert!(matches!(message, ClientMessage::Subscribe { ids, tags } if ids.len() == 2 && tags.is_empty()));
+
+    let message: ClientMessage =
+        serde_json::from_str(r#"{"type":"update","ref":"r1","id":3,"task":{"completed":true},"version":2}"#).unwrap();
+    assert!(matches!(message, ClientMessage::Update { reference: Some(r), id: 3, version: Some(2), .. } if r == "r1"));
+
+    assert!(serde_json::from_str::<ClientMessage>(r#"{"type":"delete","id":1}"#).is_err());
+}
+
+#[test]
+fn test_subscription_matches() {
+    let everything = Subscription::default();
+    assert!(everything.matches(&event(1, &[])));
+
+    let subscription = Subscription::new([1].into(), ["Work".to_string()].into());
+    assert!(subscription.matches(&event(1, &[])));
+    assert!(subscription.matches(&event(2, &["work"])));
+    assert!(!subscription.matches(&event(3, &["home"])));
+}
+
+/// Open a socket served by `serve`, returning the client end.
+async fn connect(store: Arc<Store>) -> WebSocketStream<tokio::io::DuplexStream> {
+    let (client, server) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(async move {
+        let socket = WebSocketStream::from_raw_socket(server, tokio_tungstenite::tungstenite::protocol::Role::Server, None).await;
+        serve(socket, store, "alice".to_string()).await;
+    });
+    WebSocketStream::from_raw_socket(client, tokio_tungstenite::tungstenite::protocol::Role::Client, None).await
+}
+
+async fn send(socket: &mut WebSocketStream<tokio::io::DuplexStream>, message: Value) {
+    socket.send(Message::text(message.to_string())).await.unwrap();
+}
+
+async fn receive(socket: &mut WebSocketStream<tokio::io::DuplexStream>) -> Value {
+    loop {
+        if let Message::Text(text) = socket.next().await.unwrap().unwrap() {
+            return serde_json::from_str(text.as_str()).unwrap();
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_commands_and_broadcasts() {
+    let store = Arc::new(Store::new());
+    let mut watcher = connect(Arc::clone(&store)).await;
+    let mut editor = connect(Arc::clone(&store)).await;
+
+    send(&mut watcher, json!({"type": "subscribe", "tags": ["work"]})).await;
+    assert_eq!(receive(&mut watcher).await["subscription"]["tags"], json!(["work"]));
+
+    send(&mut editor, json!({"type": "create", "ref": "a", "task": {"title": "Home", "description": ""}})).await;
+    let ack = receive(&mut editor).await;
+    assert_eq!((ack["type"].as_str(), ack["ref"].as_str()), (Some("ack"), Some("a")));
+    send(&mut editor, json!({"type": "create", "task": {"title": "Work", "description": "", "tags": ["work"]}})).await;
+    let work_id = receive(&mut editor).await["task"]["id"].as_u64().unwrap();
+
+    // Only the subscribed task's change reaches the watcher
+    let change = receive(&mut watcher).await;
+    assert_eq!(change["type"], "change");
+    assert_eq!(change["event"]["task_id"], work_id);
+    assert_eq!(change["event"]["type"], "created");
+
+    // Failures come back with the status HTTP would use
+    send(&mut editor, json!({"type": "update", "ref": "b", "id": work_id, "task": {}, "version": 9})).await;
+    let error = receive(&mut editor).await;
+    assert_eq!((error["type"].as_str(), error["status"].as_u64()), (Some("error"), Some(412)));
+    send(&mut editor, json!({"type": "bogus"})).await;
+    assert_eq!(receive(&mut editor).await["status"], 400);
+
+    // Commands are audited under the socket's actor
+    assert_eq!(store.history(work_id).await[0].actor, "alice");
+}